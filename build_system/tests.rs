@@ -60,6 +60,25 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
     TestCase::build_lib("build.mini_core", "example/mini_core.rs", "lib,dylib"),
     TestCase::build_lib("build.example", "example/example.rs", "lib"),
     TestCase::jit_bin("jit.mini_core_hello_world", "example/mini_core_hello_world.rs", "abc bcd"),
+    TestCase::custom("jit.emit_obj", &|runner| {
+        // --emit=obj writes the object file AOT compilation would produce before running the
+        // jitted program.
+        let obj_path = BUILD_EXAMPLE_OUT_DIR.to_path(&runner.dirs).join("mini_core_hello_world.o");
+        let _ = fs::remove_file(&obj_path);
+        let mut jit_cmd = runner.rustc_command([
+            "-Zunstable-options",
+            "-Cllvm-args=mode=jit",
+            "-Cprefer-dynamic",
+            "-Ccodegen-units=1",
+            "--emit=obj,link",
+            "example/mini_core_hello_world.rs",
+            "--cfg",
+            "jit",
+        ]);
+        jit_cmd.env("CG_CLIF_JIT_ARGS", "abc bcd");
+        spawn_and_wait(jit_cmd);
+        assert!(obj_path.exists(), "{} was not written", obj_path.display());
+    }),
     TestCase::build_bin_and_run(
         "aot.mini_core_hello_world",
         "example/mini_core_hello_world.rs",
@@ -89,6 +108,7 @@ const fn jit_bin(config: &'static str, source: &'static str, args: &'static str)
         &[],
     ),
     TestCase::build_bin_and_run("aot.float-minmax-pass", "example/float-minmax-pass.rs", &[]),
+    TestCase::build_bin_and_run("aot.f16_f128", "example/f16_f128.rs", &[]),
     TestCase::build_bin_and_run("aot.mod_bench", "example/mod_bench.rs", &[]),
     TestCase::build_bin_and_run("aot.issue-72793", "example/issue-72793.rs", &[]),
     TestCase::build_bin("aot.issue-59326", "example/issue-59326.rs"),
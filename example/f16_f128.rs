@@ -0,0 +1,55 @@
+// Test basic arithmetic, comparisons and casts of f16 and f128.
+
+#![feature(f16, f128)]
+
+use std::hint::black_box;
+
+fn main() {
+    let a: f16 = black_box(1.5);
+    let b: f16 = black_box(2.25);
+    assert_eq!((a + b).to_bits(), (3.75f16).to_bits());
+    assert_eq!((b - a).to_bits(), (0.75f16).to_bits());
+    assert_eq!((a * b).to_bits(), (3.375f16).to_bits());
+    assert_eq!((-a).to_bits(), (-1.5f16).to_bits());
+    assert_eq!((b % a).to_bits(), (0.75f16).to_bits());
+    assert!(a < b);
+    assert!(black_box(f16::NAN) != black_box(f16::NAN));
+    assert_eq!(a as f32, 1.5);
+    assert_eq!(black_box(3.75f32) as f16, 3.75f16);
+    assert_eq!(b as i32, 2);
+    assert_eq!(black_box(-7i32) as f16, -7.0f16);
+    assert_eq!(black_box(f16::NAN) as u8, 0);
+    assert_eq!(black_box(f16::INFINITY) as i8, i8::MAX);
+
+    let c: f128 = black_box(1.5);
+    let d: f128 = black_box(2.25);
+    assert_eq!((c + d).to_bits(), (3.75f128).to_bits());
+    assert_eq!((d - c).to_bits(), (0.75f128).to_bits());
+    assert_eq!((c * d).to_bits(), (3.375f128).to_bits());
+    assert_eq!((d / c).to_bits(), (1.5f128).to_bits());
+    assert_eq!((-c).to_bits(), (-1.5f128).to_bits());
+    // Only targets with a libm function for it support f128 remainder.
+    #[cfg(all(
+        target_os = "linux",
+        any(
+            target_env = "gnu",
+            target_arch = "aarch64",
+            target_arch = "riscv64",
+            target_arch = "s390x",
+        ),
+    ))]
+    assert_eq!((d % c).to_bits(), (0.75f128).to_bits());
+    assert!(c < d);
+    assert!(d >= c);
+    assert!(black_box(f128::NAN) != black_box(f128::NAN));
+    assert_eq!(c as f64, 1.5);
+    assert_eq!(black_box(3.75f64) as f128, 3.75f128);
+    assert_eq!(a as f128, 1.5f128);
+    assert_eq!(d as u64, 2);
+    assert_eq!(black_box(u128::MAX) as f128 as u128, u128::MAX);
+    assert_eq!(black_box(-300.0f128) as i8, i8::MIN);
+    assert_eq!(black_box(f128::NAN) as i16, 0);
+
+    assert_eq!(black_box(-1.5f16).abs(), 1.5f16);
+    assert_eq!(black_box(1.5f128).copysign(-0.0), -1.5f128);
+}
@@ -5,6 +5,7 @@
     linkage,
     extern_types,
     naked_functions,
+    fn_align,
     thread_local,
     repr_simd
 )]
@@ -274,7 +275,7 @@ unsafe fn uninitialized<T>() -> T {
 
     assert_eq!(((|()| 42u8) as fn(()) -> u8)(()), 42);
 
-    #[cfg(not(any(jit, windows)))]
+    #[cfg(not(windows))]
     {
         extern "C" {
             #[linkage = "extern_weak"]
@@ -332,10 +333,16 @@ struct ExternTypeWrapper {
     #[cfg(all(not(jit), not(all(windows, target_env = "gnu"))))]
     test_tls();
 
-    #[cfg(all(not(jit), target_arch = "x86_64", any(target_os = "linux", target_os = "macos")))]
+    #[cfg(all(
+        not(jit),
+        target_arch = "x86_64",
+        any(target_os = "linux", target_os = "macos", windows)
+    ))]
     unsafe {
         global_asm_test();
         naked_test();
+        assert_eq!(naked_test_aligned(), 42);
+        assert_eq!(naked_test_aligned as usize % 64, 0);
     }
 
     // Both statics have a reference that points to the same anonymous allocation.
@@ -361,12 +368,16 @@ fn stack_val_align() {
     assert_eq!(&a as *const Foo as usize % 8192, 0);
 }
 
-#[cfg(all(not(jit), target_arch = "x86_64", any(target_os = "linux", target_os = "macos")))]
+#[cfg(all(
+    not(jit),
+    target_arch = "x86_64",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
 extern "C" {
     fn global_asm_test();
 }
 
-#[cfg(all(not(jit), target_arch = "x86_64", target_os = "linux"))]
+#[cfg(all(not(jit), target_arch = "x86_64", any(target_os = "linux", windows)))]
 global_asm! {
     "
     .global global_asm_test
@@ -386,6 +397,20 @@ fn stack_val_align() {
     "
 }
 
+// Switching sections must not affect later global_asm! items or asm wrappers.
+#[cfg(all(
+    not(jit),
+    target_arch = "x86_64",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+global_asm! {
+    "
+    .data
+    global_asm_data_test:
+    .byte 42
+    "
+}
+
 #[cfg(all(not(jit), target_arch = "x86_64"))]
 #[naked]
 extern "C" fn naked_test() {
@@ -394,6 +419,15 @@ extern "C" fn naked_test() {
     }
 }
 
+#[cfg(all(not(jit), target_arch = "x86_64"))]
+#[naked]
+#[repr(align(64))]
+extern "C" fn naked_test_aligned() -> u32 {
+    unsafe {
+        naked_asm!("mov eax, 42", "ret");
+    }
+}
+
 #[repr(C)]
 enum c_void {
     _1,
@@ -1,6 +1,6 @@
 // Most of these tests are copied from https://github.com/japaric/stdsimd/blob/0f4413d01c4f0c3ffbc5a69e9a37fbc7235b31a9/coresimd/arm/neon.rs
 
-#![feature(portable_simd)]
+#![feature(link_llvm_intrinsics, portable_simd)]
 
 #[cfg(target_arch = "aarch64")]
 use std::arch::aarch64::*;
@@ -240,6 +240,289 @@ unsafe fn test_vrndnq_f32() {
     assert_eq!(r, e);
 }
 
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vmull_s8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.smull.v8i16
+    let a = i8x8::from([1, -2, 3, -4, 5, 6, 127, -128]);
+    let b = i8x8::from([2, 3, -4, -5, 6, 7, 127, -128]);
+    let e = i16x8::from([2, -6, -12, 20, 30, 42, 16129, 16384]);
+    let r: i16x8 = transmute(vmull_s8(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vmull_u16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.umull.v4i32
+    let a = u16x4::from([1, 2, 65535, 300]);
+    let b = u16x4::from([3, 4, 65535, 400]);
+    let e = u32x4::from([3, 8, 4294836225, 120000]);
+    let r: u32x4 = transmute(vmull_u16(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vqtbl2q_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.tbl2.v16i8
+    let t0 = u8x16::from([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    let t1 = u8x16::from([16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31]);
+    let idx = u8x16::from([31, 0, 16, 15, 32, 255, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21]);
+    let e = u8x16::from([31, 0, 16, 15, 0, 0, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21]);
+    let r: u8x16 =
+        transmute(vqtbl2q_u8(uint8x16x2_t(transmute(t0), transmute(t1)), transmute(idx)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vqtbx1q_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.tbx1.v16i8
+    let a = u8x16::splat(42);
+    let t = u8x16::from([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    let idx = u8x16::from([15, 16, 0, 255, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 100]);
+    let e = u8x16::from([15, 42, 0, 42, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 42]);
+    let r: u8x16 = transmute(vqtbx1q_u8(transmute(a), transmute(t), transmute(idx)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vrhaddq_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.urhadd.v16i8
+    let a = u8x16::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 255, 255]);
+    let b = u8x16::from([2, 2, 3, 5, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 254, 255]);
+    let e = u8x16::from([2, 2, 3, 5, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 255, 255]);
+    let r: u8x16 = transmute(vrhaddq_u8(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vhadd_s16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.shadd.v4i16
+    let a = i16x4::from([1, -3, i16::MAX, i16::MIN]);
+    let b = i16x4::from([2, 0, i16::MAX, i16::MIN]);
+    let e = i16x4::from([1, -2, i16::MAX, i16::MIN]);
+    let r: i16x4 = transmute(vhadd_s16(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vabdq_s8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.sabd.v16i8
+    let a = i8x16::from([1, -2, 3, -4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, -128]);
+    let b = i8x16::from([2, 3, -4, -5, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 127]);
+    let e = u8x16::from([1, 5, 7, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255]);
+    let r: u8x16 = transmute(vabdq_s8(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vaddlvq_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.uaddlv.i32.v16i8
+    let a = u8x16::splat(255);
+    let r = vaddlvq_u8(transmute(a));
+    assert_eq!(r, 255 * 16);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vqmovn_s16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.sqxtn.v8i8
+    let a = i16x8::from([1, -1, 127, 128, -128, -129, i16::MAX, i16::MIN]);
+    let e = i8x8::from([1, -1, 127, 127, -128, -128, 127, -128]);
+    let r: i8x8 = transmute(vqmovn_s16(transmute(a)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vqmovun_s16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.sqxtun.v8i8
+    let a = i16x8::from([1, -1, 127, 255, 256, -129, i16::MAX, i16::MIN]);
+    let e = u8x8::from([1, 0, 127, 255, 255, 0, 255, 0]);
+    let r: u8x8 = transmute(vqmovun_s16(transmute(a)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vqdmulhq_s16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.sqdmulh.v8i16
+    let a = i16x8::from([0x4000, i16::MIN, -0x4000, 1, 2, 3, 4, 5]);
+    let b = i16x8::from([0x4000, i16::MIN, 0x4000, 1, 2, 3, 4, 5]);
+    let e = i16x8::from([0x2000, i16::MAX, -0x2000, 0, 0, 0, 0, 0]);
+    let r: i16x8 = transmute(vqdmulhq_s16(transmute(a), transmute(b)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vld2q_vst2q_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.ld2.v16i8.p0 and llvm.aarch64.neon.st2.v16i8.p0
+    let mut data = [0u8; 32];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let r = vld2q_u8(data.as_ptr());
+    let r0: u8x16 = transmute(r.0);
+    let r1: u8x16 = transmute(r.1);
+    assert_eq!(r0, u8x16::from([0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30]));
+    assert_eq!(r1, u8x16::from([1, 3, 5, 7, 9, 11, 13, 15, 17, 19, 21, 23, 25, 27, 29, 31]));
+
+    let mut out = [0u8; 32];
+    vst2q_u8(out.as_mut_ptr(), r);
+    assert_eq!(out, data);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vld1q_vst1q_u16_x2() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.ld1x2.v8i16.p0 and llvm.aarch64.neon.st1x2.v8i16.p0
+    let data: [u16; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    let r = vld1q_u16_x2(data.as_ptr());
+    let r0: u16x8 = transmute(r.0);
+    let r1: u16x8 = transmute(r.1);
+    assert_eq!(r0, u16x8::from([0, 1, 2, 3, 4, 5, 6, 7]));
+    assert_eq!(r1, u16x8::from([8, 9, 10, 11, 12, 13, 14, 15]));
+
+    let mut out = [0u16; 16];
+    vst1q_u16_x2(out.as_mut_ptr(), r);
+    assert_eq!(out, data);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vld3q_vst3q_u16() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.ld3.v8i16.p0 and llvm.aarch64.neon.st3.v8i16.p0
+    let mut data = [0u16; 24];
+    for (i, elem) in data.iter_mut().enumerate() {
+        *elem = i as u16;
+    }
+    let r = vld3q_u16(data.as_ptr());
+    let r0: u16x8 = transmute(r.0);
+    let r1: u16x8 = transmute(r.1);
+    let r2: u16x8 = transmute(r.2);
+    assert_eq!(r0, u16x8::from([0, 3, 6, 9, 12, 15, 18, 21]));
+    assert_eq!(r1, u16x8::from([1, 4, 7, 10, 13, 16, 19, 22]));
+    assert_eq!(r2, u16x8::from([2, 5, 8, 11, 14, 17, 20, 23]));
+
+    let mut out = [0u16; 24];
+    vst3q_u16(out.as_mut_ptr(), r);
+    assert_eq!(out, data);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vld4q_vst4q_u32() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.ld4.v4i32.p0 and llvm.aarch64.neon.st4.v4i32.p0
+    let mut data = [0u32; 16];
+    for (i, elem) in data.iter_mut().enumerate() {
+        *elem = i as u32;
+    }
+    let r = vld4q_u32(data.as_ptr());
+    let r0: u32x4 = transmute(r.0);
+    let r3: u32x4 = transmute(r.3);
+    assert_eq!(r0, u32x4::from([0, 4, 8, 12]));
+    assert_eq!(r3, u32x4::from([3, 7, 11, 15]));
+
+    let mut out = [0u32; 16];
+    vst4q_u32(out.as_mut_ptr(), r);
+    assert_eq!(out, data);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vld1q_vst1q_u8_x3_and_f32_x4() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.ld1x3.v16i8.p0, llvm.aarch64.neon.st1x3.v16i8.p0,
+    // llvm.aarch64.neon.ld1x4.v4f32.p0 and llvm.aarch64.neon.st1x4.v4f32.p0
+    let mut bytes = [0u8; 48];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let r = vld1q_u8_x3(bytes.as_ptr());
+    let r2: u8x16 = transmute(r.2);
+    assert_eq!(r2, u8x16::from([32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47]));
+    let mut out = [0u8; 48];
+    vst1q_u8_x3(out.as_mut_ptr(), r);
+    assert_eq!(out, bytes);
+
+    let floats: [f32; 16] =
+        [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0];
+    let r = vld1q_f32_x4(floats.as_ptr());
+    let r1: f32x4 = transmute(r.1);
+    assert_eq!(r1, f32x4::from([4.0, 5.0, 6.0, 7.0]));
+    let mut out = [0f32; 16];
+    vst1q_f32_x4(out.as_mut_ptr(), r);
+    assert_eq!(out, floats);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "aes")]
+unsafe fn test_vaeseq_u8() {
+    // AArch64 llvm intrinsic: llvm.aarch64.crypto.aese and llvm.aarch64.crypto.aesmc
+    // SubBytes maps 0 to 0x63 and MixColumns leaves columns with identical bytes unchanged.
+    let zero = vdupq_n_u8(0);
+    let r: u8x16 = transmute(vaesmcq_u8(vaeseq_u8(zero, zero)));
+    assert_eq!(r, u8x16::splat(0x63));
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "aes")]
+unsafe fn test_vmull_p64() {
+    // AArch64 llvm intrinsic: llvm.aarch64.neon.pmull64
+    assert_eq!(vmull_p64(3, 3), 5);
+    assert_eq!(vmull_p64(1 << 63, 2), 1 << 64);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "sha2")]
+unsafe fn test_vsha1h_u32() {
+    // AArch64 llvm intrinsic: llvm.aarch64.crypto.sha1h
+    assert_eq!(vsha1h_u32(0x1234_5678), 0x1234_5678u32.rotate_left(30));
+}
+
+// The f16 vector types of core::arch are unstable, so use the LLVM intrinsics which take the f16
+// lanes as integers instead.
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    #[link_name = "llvm.aarch64.neon.vcvthf2fp"]
+    fn vcvt_f32_f16(a: int16x4_t) -> float32x4_t;
+    #[link_name = "llvm.aarch64.neon.vcvtfp2hf"]
+    fn vcvt_f16_f32(a: float32x4_t) -> int16x4_t;
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_vcvt_f32_f16_and_f16_f32() {
+    let a = u16x4::from([0x3c00, 0xc000, 0x7c00, 0x3555]);
+    let e = f32x4::from([1., -2., f32::INFINITY, 0.333251953125]);
+    let r: f32x4 = transmute(vcvt_f32_f16(transmute(a)));
+    assert_eq!(r, e);
+
+    let a = f32x4::from([1., -2., 65536., 1. / 3.]);
+    let e = u16x4::from([0x3c00, 0xc000, 0x7c00, 0x3555]);
+    let r: u16x4 = transmute(vcvt_f16_f32(transmute(a)));
+    assert_eq!(r, e);
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn test_inline_asm_vreg() {
+    let a: float32x4_t = transmute(f32x4::from([1., 2., 3., 4.]));
+    let b: float32x4_t = transmute(f32x4::from([10., 20., 30., 40.]));
+    let r: float32x4_t;
+    std::arch::asm!(
+        "fadd {0:v}.4s, {1:v}.4s, {2:v}.4s",
+        lateout(vreg) r,
+        in(vreg) a,
+        in(vreg_low16) b,
+        options(pure, nomem, nostack),
+    );
+    let r: f32x4 = transmute(r);
+    assert_eq!(r, f32x4::from([11., 22., 33., 44.]));
+
+    let x = std::hint::black_box(1.5f64);
+    let y: u64;
+    std::arch::asm!(
+        "movi v0.2d, #0",
+        "movi v16.2d, #0",
+        "movi v31.2d, #0",
+        "mov x9, #0",
+        "mov {}, #3",
+        out(reg) y,
+        clobber_abi("C"),
+    );
+    assert_eq!(y, 3);
+    assert_eq!(x * 2.0, 3.0);
+}
+
 #[cfg(target_arch = "aarch64")]
 fn main() {
     unsafe {
@@ -272,6 +555,33 @@ fn main() {
         test_vminq_f32();
         test_vaddvq_f32();
         test_vrndnq_f32();
+
+        test_vmull_s8();
+        test_vmull_u16();
+        test_vqtbl2q_u8();
+        test_vqtbx1q_u8();
+        test_vrhaddq_u8();
+        test_vhadd_s16();
+        test_vabdq_s8();
+        test_vaddlvq_u8();
+        test_vqmovn_s16();
+        test_vqmovun_s16();
+        test_vqdmulhq_s16();
+        test_vld2q_vst2q_u8();
+        test_vld1q_vst1q_u16_x2();
+        test_vld3q_vst3q_u16();
+        test_vld4q_vst4q_u32();
+        test_vld1q_vst1q_u8_x3_and_f32_x4();
+        test_vcvt_f32_f16_and_f16_f32();
+        test_inline_asm_vreg();
+
+        if std::arch::is_aarch64_feature_detected!("aes") {
+            test_vaeseq_u8();
+            test_vmull_p64();
+        }
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            test_vsha1h_u32();
+        }
     }
 }
 
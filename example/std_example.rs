@@ -1,12 +1,18 @@
 #![feature(
+    asm_unwind,
+    c_variadic,
     core_intrinsics,
     coroutines,
+    link_llvm_intrinsics,
     stmt_expr_attributes,
     coroutine_trait,
+    ptr_mask,
+    ptr_metadata,
     repr_simd,
     tuple_trait,
     unboxed_closures
 )]
+#![cfg_attr(target_arch = "x86_64", feature(avx512_target_feature, stdarch_x86_avx512))]
 #![allow(internal_features)]
 
 #[cfg(target_arch = "x86_64")]
@@ -55,6 +61,13 @@ fn main() {
     println!("{}", 2.3f32.log2());
     assert_eq!(2.3f32.copysign(-1.0), -2.3f32);
     println!("{}", 2.3f32.powf(2.0));
+    assert_eq!(black_box(2.5f32).round_ties_even(), 2.0);
+    assert_eq!(black_box(-3.5f64).round_ties_even(), -4.0);
+    assert_eq!(black_box(2.5f64).round(), 3.0);
+    assert_eq!(unsafe { std::intrinsics::rintf64(black_box(0.5)) }, 0.0);
+    assert_eq!(unsafe { std::intrinsics::nearbyintf32(black_box(1.5)) }, 2.0);
+    assert_eq!(black_box(-1.7f64).trunc(), -1.0);
+    assert_eq!(black_box(2.0f64).mul_add(3.0, 1.0), 7.0);
 
     assert_eq!(i64::MAX.checked_mul(2), None);
 
@@ -99,10 +112,66 @@ fn main() {
     assert_eq!(houndred_f32 as i128, 100);
     assert_eq!(houndred_f64 as i128, 100);
     assert_eq!(1u128.rotate_left(2), 4);
+    test_i128_rotate();
 
     assert_eq!(black_box(f32::NAN) as i128, 0);
     assert_eq!(black_box(f32::NAN) as u128, 0);
 
+    test_float_to_int_saturating_casts();
+    test_simd_bit_ops();
+    test_simd_wide_bitmask();
+    test_three_way_compare_and_select();
+    test_branch_hints();
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    test_simple_inline_asm();
+    #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
+    test_inline_asm_registers();
+    test_raw_eq_and_compare_bytes();
+    test_prefetch_and_nontemporal_store();
+    test_ptr_mask_and_vtable_layout();
+
+    #[cfg(all(
+        any(target_arch = "x86_64", all(target_arch = "aarch64", not(target_vendor = "apple"))),
+        not(windows)
+    ))]
+    unsafe {
+        // More arguments than fit in registers to test reading them from the stack.
+        assert_eq!(variadic_sum(8, 1i64, 2i64, 3i64, 4i64, 5i64, 6i64, 7i64, 8i64), 36);
+
+        // Calling C-variadic functions with float arguments isn't supported yet, so call these
+        // through a non-variadic signature instead. On these targets variadic arguments are passed
+        // like fixed arguments, except for the vector register count in `al` on x86_64, which
+        // isn't read by the callee.
+        let variadic_sum_mixed: unsafe extern "C" fn(usize, i64, f64, i64, f64) -> f64 =
+            std::mem::transmute(variadic_sum_mixed as *const ());
+        assert_eq!(variadic_sum_mixed(4, 1, 2.5, 3, 4.5), 11.0);
+        let variadic_all_regs_used: unsafe extern "C" fn(
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            i64,
+            f64,
+            i64,
+        ) -> f64 = std::mem::transmute(variadic_all_regs_used as *const ());
+        assert_eq!(
+            variadic_all_regs_used(
+                1, 2, 3, 4, 5, 6, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 7, 8.5, 9
+            ),
+            24.5
+        );
+    }
+
     // Test signed 128bit comparing
     let max = usize::MAX as i128;
     if 100i128 < 0i128 || 100i128 > max {
@@ -111,6 +180,8 @@ fn main() {
 
     test_checked_mul();
 
+    test_atomic_rmw();
+
     let _a = 1u32 << 2u8;
 
     let empty: [i32; 0] = [];
@@ -185,6 +256,346 @@ fn panic(_: u128) {
     panic!();
 }
 
+#[cfg(all(
+    any(target_arch = "x86_64", all(target_arch = "aarch64", not(target_vendor = "apple"))),
+    not(windows)
+))]
+unsafe extern "C" fn variadic_sum(count: usize, mut args: ...) -> i64 {
+    let mut args_copy = args.clone();
+    let mut sum = 0;
+    for _ in 0..count {
+        sum += args.arg::<i64>();
+    }
+    assert_eq!(args_copy.arg::<i64>(), 1);
+    sum
+}
+
+#[cfg(all(
+    any(target_arch = "x86_64", all(target_arch = "aarch64", not(target_vendor = "apple"))),
+    not(windows)
+))]
+unsafe extern "C" fn variadic_sum_mixed(count: usize, mut args: ...) -> f64 {
+    let mut sum = 0.0;
+    for i in 0..count {
+        if i % 2 == 0 {
+            sum += args.arg::<i64>() as f64;
+        } else {
+            sum += args.arg::<f64>();
+        }
+    }
+    sum
+}
+
+// On x86_64 the fixed arguments use all argument registers, so all variadic arguments are passed
+// on the stack.
+#[cfg(all(
+    any(target_arch = "x86_64", all(target_arch = "aarch64", not(target_vendor = "apple"))),
+    not(windows)
+))]
+unsafe extern "C" fn variadic_all_regs_used(
+    a: i64,
+    b: i64,
+    c: i64,
+    d: i64,
+    e: i64,
+    f: i64,
+    g: f64,
+    h: f64,
+    i: f64,
+    j: f64,
+    k: f64,
+    l: f64,
+    m: f64,
+    n: f64,
+    mut args: ...
+) -> f64 {
+    assert_eq!((a, b, c, d, e, f), (1, 2, 3, 4, 5, 6));
+    assert_eq!((g, h, i, j, k, l, m, n), (1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0));
+    args.arg::<i64>() as f64 + args.arg::<f64>() + args.arg::<i64>() as f64
+}
+
+fn test_simd_bit_ops() {
+    use std::intrinsics::simd::{simd_bitreverse, simd_ctlz, simd_ctpop, simd_cttz};
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct U8X16([u8; 16]);
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct U32X4([u32; 4]);
+
+    let bytes = U8X16([0, 1, 2, 3, 0x80, 0xff, 0x0f, 0xf0, 7, 8, 9, 10, 11, 12, 13, 0x55]);
+    let words = U32X4([0, 1, 0x8000_0000, 0x00f0_0f00]);
+    unsafe {
+        let r: [u8; 16] = transmute(simd_ctpop(black_box(bytes)));
+        assert_eq!(r, [0, 1, 1, 2, 1, 8, 4, 4, 3, 1, 2, 2, 3, 2, 3, 4]);
+        let r: [u32; 4] = transmute(simd_ctpop(black_box(words)));
+        assert_eq!(r, [0, 1, 1, 8]);
+        let r: [u32; 4] = transmute(simd_ctlz(black_box(words)));
+        assert_eq!(r, [32, 31, 0, 8]);
+        let r: [u32; 4] = transmute(simd_cttz(black_box(words)));
+        assert_eq!(r, [32, 0, 31, 8]);
+        let r: [u32; 4] = transmute(simd_bitreverse(black_box(words)));
+        assert_eq!(r, [0, 0x8000_0000, 1, 0x00f0_0f00]);
+    }
+}
+
+fn test_simd_wide_bitmask() {
+    use std::intrinsics::simd::simd_select_bitmask;
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct U8X128([u8; 128]);
+
+    // The mask is larger than any integer type.
+    let a = U8X128([1; 128]);
+    let b = U8X128([2; 128]);
+    let mut mask = [0u8; 16];
+    mask[0] = 0b0000_0101;
+    mask[15] = 0b1000_0000;
+    let r: [u8; 128] = unsafe { transmute(simd_select_bitmask(black_box(mask), a, b)) };
+    let mut expected = [2u8; 128];
+    if cfg!(target_endian = "little") {
+        expected[0] = 1;
+        expected[2] = 1;
+        expected[127] = 1;
+    } else {
+        expected[5] = 1;
+        expected[7] = 1;
+        expected[120] = 1;
+    }
+    assert_eq!(r, expected);
+}
+
+fn test_three_way_compare_and_select() {
+    use std::cmp::Ordering;
+    use std::intrinsics::{select_unpredictable, three_way_compare};
+
+    assert_eq!(three_way_compare(black_box(-1i8), black_box(1i8)), Ordering::Less);
+    assert_eq!(three_way_compare(black_box(u64::MAX), black_box(0u64)), Ordering::Greater);
+    assert_eq!(three_way_compare(black_box(i128::MIN), black_box(i128::MIN)), Ordering::Equal);
+    assert_eq!(three_way_compare(black_box(1u128 << 100), black_box(1u128)), Ordering::Greater);
+
+    assert_eq!(select_unpredictable(black_box(true), 1u8, 2u8), 1);
+    assert_eq!(select_unpredictable(black_box(false), 1.5f64, 2.5f64), 2.5);
+    assert_eq!(select_unpredictable(black_box(false), u128::MAX, 3u128), 3);
+    assert_eq!(select_unpredictable(black_box(true), "a", "b"), "a");
+    assert_eq!(select_unpredictable(black_box(false), [1u8; 32], [2u8; 32]), [2u8; 32]);
+}
+
+fn test_branch_hints() {
+    use std::intrinsics::{likely, unlikely};
+
+    fn sum_checked(values: &[u32]) -> Option<u32> {
+        let mut sum = 0u32;
+        for &value in values {
+            let (new_sum, overflow) = sum.overflowing_add(value);
+            if unlikely(overflow) {
+                return None;
+            }
+            sum = new_sum;
+        }
+        if likely(sum != 0) { Some(sum) } else { None }
+    }
+
+    assert_eq!(sum_checked(black_box(&[1, 2, 3])), Some(6));
+    assert_eq!(sum_checked(black_box(&[u32::MAX, 1])), None);
+    assert_eq!(sum_checked(black_box(&[])), None);
+}
+
+// These don't need an external assembler and thus also work in jit mode.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn test_simple_inline_asm() {
+    use std::arch::asm;
+
+    let x = black_box(42u64);
+    let y: u64;
+    let (mut a, mut b) = (black_box(1u64), black_box(2u64));
+    unsafe {
+        asm!("", options(nomem, nostack, preserves_flags));
+        asm!("/* {0} */ // {1}", in(reg) x, in(reg) &a);
+        asm!("nop\n nop; nop", options(nomem, nostack));
+        #[cfg(target_arch = "x86_64")]
+        asm!("nop", "mfence", options(nostack, preserves_flags));
+        #[cfg(target_arch = "aarch64")]
+        asm!("nop", "dmb ish", options(nostack, preserves_flags));
+        #[cfg(target_arch = "x86_64")]
+        asm!("pause", options(nomem, nostack, preserves_flags));
+        #[cfg(target_arch = "aarch64")]
+        asm!("yield", options(nomem, nostack, preserves_flags));
+        asm!("mov {0}, {1}", out(reg) y, in(reg) x, options(nomem, nostack));
+        asm!(
+            "mov {tmp}, {a}",
+            "mov {a}, {b}",
+            "mov {b}, {tmp}",
+            a = inout(reg) a,
+            b = inout(reg) b,
+            tmp = out(reg) _,
+            options(nomem, nostack),
+        );
+    }
+    assert_eq!(y, 42);
+    assert_eq!((a, b), (2, 1));
+}
+
+#[cfg(target_arch = "riscv64")]
+fn test_inline_asm_registers() {
+    let r: f64;
+    unsafe {
+        std::arch::asm!(
+            "fadd.d {r}, {a}, {b}",
+            // fs0 is callee saved
+            "fmv.d.x fs0, zero",
+            r = lateout(freg) r,
+            a = in(freg) black_box(1.5f64),
+            b = in(freg) 2.25f64,
+            out("fs0") _,
+        );
+    }
+    assert_eq!(r, 3.75);
+
+    let r: f32;
+    unsafe {
+        std::arch::asm!(
+            "fadd.s {r}, {a}, {b}",
+            r = lateout(freg) r,
+            a = in(freg) black_box(1.5f32),
+            b = in(freg) 2.25f32,
+        );
+    }
+    assert_eq!(r, 3.75);
+}
+
+#[cfg(target_arch = "s390x")]
+fn test_inline_asm_registers() {
+    let r: f64;
+    let x: u64;
+    unsafe {
+        std::arch::asm!(
+            "adbr {a}, {b}",
+            "lgr {x}, {y}",
+            // f8 is callee saved
+            "lzdr %f8",
+            a = inout(freg) black_box(1.5f64) => r,
+            b = in(freg) 2.25f64,
+            x = out(reg) x,
+            y = in(reg_addr) 5u64,
+            out("f8") _,
+        );
+    }
+    assert_eq!(r, 3.75);
+    assert_eq!(x, 5);
+}
+
+fn test_raw_eq_and_compare_bytes() {
+    use std::intrinsics::{compare_bytes, raw_eq};
+
+    let a = *b"abcdefghijklmnopqrstuvwxyz012345";
+    let mut b = a;
+    unsafe {
+        assert!(raw_eq(&black_box([1u8, 2, 3]), &[1, 2, 3]));
+        assert!(!raw_eq(&black_box([1u8, 2, 3]), &[1, 2, 4]));
+        assert!(raw_eq(&black_box(a), &b));
+        b[31] = b'x';
+        assert!(!raw_eq(&black_box(a), &b));
+        let c: [u8; 29] = a[..29].try_into().unwrap();
+        let d: [u8; 29] = b[..29].try_into().unwrap();
+        assert!(raw_eq(&black_box(c), &d));
+
+        assert_eq!(compare_bytes(a.as_ptr(), b.as_ptr(), 0), 0);
+        assert_eq!(compare_bytes(a.as_ptr(), b.as_ptr(), 31), 0);
+        assert!(compare_bytes(a.as_ptr(), b.as_ptr(), 32) < 0);
+        assert!(compare_bytes(b.as_ptr(), a.as_ptr(), 32) > 0);
+        assert!(compare_bytes(b"\x01\xff\x00".as_ptr(), b"\x02\x00\x00".as_ptr(), 3) < 0);
+        assert!(compare_bytes(b"abcdefghiz".as_ptr(), b"abcdefghia".as_ptr(), 10) > 0);
+        assert!(compare_bytes(a.as_ptr(), b.as_ptr(), black_box(32)) < 0);
+    }
+}
+
+fn test_prefetch_and_nontemporal_store() {
+    use std::intrinsics::{nontemporal_store, prefetch_read_data, prefetch_write_data};
+
+    let mut data = [0u64; 4];
+    unsafe {
+        prefetch_read_data(data.as_ptr(), 3);
+        prefetch_write_data(data.as_mut_ptr(), 0);
+        nontemporal_store(&mut data[1], 42);
+    }
+    assert_eq!(black_box(data), [0, 42, 0, 0]);
+}
+
+fn test_ptr_mask_and_vtable_layout() {
+    #[repr(align(16))]
+    struct Aligned([u8; 48]);
+    impl std::fmt::Debug for Aligned {
+        fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            Ok(())
+        }
+    }
+
+    let val = Aligned([0; 48]);
+    let ptr = black_box(&val.0[5] as *const u8);
+    assert_eq!(ptr.mask(!15).addr(), &val as *const Aligned as usize);
+
+    let metadata = std::ptr::metadata(black_box(&val as &dyn std::fmt::Debug));
+    assert_eq!(metadata.size_of(), 48);
+    assert_eq!(metadata.align_of(), 16);
+}
+
+fn test_i128_rotate() {
+    let x = black_box(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210u128);
+    assert_eq!(x.rotate_left(black_box(0)), x);
+    assert_eq!(x.rotate_right(black_box(0)), x);
+    assert_eq!(x.rotate_left(black_box(128)), x);
+    assert_eq!(x.rotate_left(black_box(64)), 0xfedc_ba98_7654_3210_0123_4567_89ab_cdefu128);
+    assert_eq!(x.rotate_right(black_box(4)), 0x0012_3456_789a_bcde_ffed_cba9_8765_4321u128);
+    assert_eq!(x.rotate_left(black_box(68)), 0xedcb_a987_6543_2100_1234_5678_9abc_deffu128);
+    assert_eq!(x.rotate_left(black_box(1)), x.rotate_right(black_box(127)));
+    assert_eq!(black_box(i128::MIN).rotate_left(black_box(1)), 1);
+    assert_eq!(black_box(1i128).rotate_right(black_box(1)), i128::MIN);
+    assert_eq!(black_box(1i128).rotate_left(black_box(300)), 1i128 << 44);
+}
+
+fn test_float_to_int_saturating_casts() {
+    assert_eq!(black_box(f32::INFINITY) as i128, i128::MAX);
+    assert_eq!(black_box(f32::NEG_INFINITY) as i128, i128::MIN);
+    assert_eq!(black_box(f64::INFINITY) as u128, u128::MAX);
+    assert_eq!(black_box(-1.0f64) as u128, 0);
+    assert_eq!(black_box(1e40f64) as i128, i128::MAX);
+    assert_eq!(black_box(-1e40f64) as i128, i128::MIN);
+    assert_eq!(black_box(f64::NAN) as i128, 0);
+    assert_eq!(black_box(-((1u128 << 100) as f64)) as i128, -(1i128 << 100));
+
+    assert_eq!(black_box(300.0f32) as i8, i8::MAX);
+    assert_eq!(black_box(-300.0f32) as i8, i8::MIN);
+    assert_eq!(black_box(-1.0f32) as u16, 0);
+    assert_eq!(black_box(1e10f64) as u16, u16::MAX);
+    assert_eq!(black_box(f32::NAN) as i16, 0);
+    assert_eq!(black_box(-1.5f64) as u64, 0);
+    assert_eq!(black_box(f64::NAN) as u32, 0);
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct F32X4([f32; 4]);
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct I8X4([i8; 4]);
+
+    #[repr(simd)]
+    #[derive(Copy, Clone)]
+    struct U32X4([u32; 4]);
+
+    let floats = F32X4([f32::NAN, 1000.0, -1000.0, -1.9]);
+    unsafe {
+        let ints: I8X4 = std::intrinsics::simd::simd_as(black_box(floats));
+        assert_eq!(transmute::<_, [i8; 4]>(ints), [0, i8::MAX, i8::MIN, -1]);
+        let uints: U32X4 = std::intrinsics::simd::simd_as(black_box(floats));
+        assert_eq!(transmute::<_, [u32; 4]>(uints), [0, 1000, 0, 0]);
+    }
+}
+
 use std::mem::transmute;
 
 #[cfg(target_pointer_width = "32")]
@@ -224,6 +635,347 @@ unsafe fn test_crc32() {
     assert_eq!(_mm_crc32_u64(a as u64, b as u64), 241952147);
 }
 
+#[cfg(target_arch = "x86_64")]
+#[cfg(not(jit))]
+unsafe fn test_rdtsc_rdrand() {
+    let start = _rdtsc();
+    let mut aux = 0;
+    let end = __rdtscp(&mut aux);
+    assert!(end >= start);
+
+    if is_x86_feature_detected!("rdrand") {
+        let mut val = 0u64;
+        // rdrand may transiently fail, so retry a couple of times.
+        assert!((0..10).any(|_| _rdrand64_step(&mut val) == 1));
+        let mut val = 0u16;
+        assert!((0..10).any(|_| _rdrand16_step(&mut val) == 1));
+    }
+    if is_x86_feature_detected!("rdseed") {
+        let mut val = 0u32;
+        assert!((0..100).any(|_| _rdseed32_step(&mut val) == 1));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi1,bmi2")]
+unsafe fn test_bmi() {
+    let a = black_box(0xdead_beef_1234_5678u64);
+    assert_eq!(_bzhi_u64(a, 0), 0);
+    assert_eq!(_bzhi_u64(a, 16), 0x5678);
+    assert_eq!(_bzhi_u64(a, 64), a);
+    assert_eq!(_bzhi_u64(a, 0x1_0010), 0x5678);
+    assert_eq!(_bzhi_u32(a as u32, 200), 0x1234_5678);
+    assert_eq!(_bextr_u64(a, 32, 16), 0xbeef);
+    assert_eq!(_bextr_u64(a, 60, 16), 0xd);
+    assert_eq!(_bextr_u64(a, 64, 16), 0);
+    assert_eq!(_bextr_u32(a as u32, 4, 0), 0);
+    assert_eq!(_andn_u64(0xff00, a), 0xdead_beef_1234_0078);
+
+    #[cfg(not(jit))]
+    {
+        assert_eq!(_pext_u64(a, 0xff00_0000_0000_00ff), 0xde78);
+        assert_eq!(_pdep_u64(0xde78, 0xff00_0000_0000_00ff), 0xde00_0000_0000_0078);
+        assert_eq!(_pext_u32(0b1011_0110, 0b1111_0000), 0b1011);
+        assert_eq!(_pdep_u32(0b1011, 0b1111_0000), 0b1011_0000);
+    }
+}
+
+// Without the bmi2 target feature pdep and pext are emulated rather than using the native
+// instructions.
+#[cfg(target_arch = "x86_64")]
+fn test_pdep_pext_fallback() {
+    extern "C" {
+        #[link_name = "llvm.x86.bmi.pdep.64"]
+        fn pdep_u64(a: u64, mask: u64) -> u64;
+        #[link_name = "llvm.x86.bmi.pext.64"]
+        fn pext_u64(a: u64, mask: u64) -> u64;
+        #[link_name = "llvm.x86.bmi.pdep.32"]
+        fn pdep_u32(a: u32, mask: u32) -> u32;
+        #[link_name = "llvm.x86.bmi.pext.32"]
+        fn pext_u32(a: u32, mask: u32) -> u32;
+    }
+
+    let a = black_box(0xdead_beef_1234_5678u64);
+    unsafe {
+        assert_eq!(pext_u64(a, 0xff00_0000_0000_00ff), 0xde78);
+        assert_eq!(pdep_u64(0xde78, 0xff00_0000_0000_00ff), 0xde00_0000_0000_0078);
+        assert_eq!(pext_u64(a, 0), 0);
+        assert_eq!(pdep_u64(a, u64::MAX), a);
+        assert_eq!(pext_u32(0b1011_0110, 0b1111_0000), 0b1011);
+        assert_eq!(pdep_u32(0b1011, 0b1111_0000), 0b1011_0000);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn test_mm_cmp_ps_and_pd() {
+    let x = _mm_setr_ps(1., f32::NAN, 3., 4.);
+    let y = _mm_setr_ps(2., 2., 3., 3.);
+    let r: [u32; 4] = transmute(_mm_cmp_ps::<_CMP_LT_OQ>(x, y));
+    assert_eq!(r, [u32::MAX, 0, 0, 0]);
+    let r: [u32; 4] = transmute(_mm_cmp_ps::<_CMP_TRUE_UQ>(x, y));
+    assert_eq!(r, [u32::MAX; 4]);
+    let r: [u32; 4] = transmute(_mm_cmp_ps::<_CMP_FALSE_OS>(x, y));
+    assert_eq!(r, [0; 4]);
+
+    let x = _mm256_setr_pd(1., f64::NAN, 3., 4.);
+    let y = _mm256_setr_pd(2., 2., 3., 3.);
+    let r: [u64; 4] = transmute(_mm256_cmp_pd::<_CMP_NLT_UQ>(x, y));
+    assert_eq!(r, [0, u64::MAX, u64::MAX, u64::MAX]);
+    let r: [u64; 4] = transmute(_mm256_cmp_pd::<_CMP_TRUE_US>(x, y));
+    assert_eq!(r, [u64::MAX; 4]);
+    let r: [u64; 4] = transmute(_mm256_cmp_pd::<_CMP_FALSE_OQ>(x, y));
+    assert_eq!(r, [0; 4]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn test_avx512() {
+    let a = _mm512_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+    let b = _mm512_setr_epi32(16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31);
+    let idx = _mm512_setr_epi32(15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 16 + 32);
+    let r: [i32; 16] = transmute(_mm512_permutexvar_epi32(idx, a));
+    assert_eq!(r, [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    let idx = _mm512_setr_epi32(0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 31 + 64);
+    let r: [i32; 16] = transmute(_mm512_permutex2var_epi32(a, idx, b));
+    assert_eq!(r, [0, 16, 1, 17, 2, 18, 3, 19, 4, 20, 5, 21, 6, 22, 7, 31]);
+
+    let x =
+        _mm512_setr_ps(0., 1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11., 12., 13., 14., f32::NAN);
+    let y = _mm512_set1_ps(7.5);
+    assert_eq!(_mm512_cmp_ps_mask::<_CMP_LT_OQ>(x, y), 0x00ff);
+    assert_eq!(_mm512_cmp_ps_mask::<_CMP_NLT_UQ>(x, y), 0xff00);
+    assert_eq!(_mm512_mask_cmp_ps_mask::<_CMP_LT_OQ>(0x0f0f, x, y), 0x000f);
+    assert_eq!(_mm512_cmp_ps_mask::<_CMP_TRUE_UQ>(x, y), 0xffff);
+    assert_eq!(_mm512_cmp_ps_mask::<_CMP_FALSE_OQ>(x, y), 0);
+    let r: [f32; 16] = transmute(_mm512_mask_mov_ps(y, 0x0003, x));
+    assert_eq!(r[..3], [0., 1., 7.5]);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_clobber_abi() {
+    let x = black_box(1.5f64);
+    let y: u64;
+    unsafe {
+        std::arch::asm!(
+            "xorps xmm0, xmm0",
+            "xorps xmm7, xmm7",
+            "xorps xmm15, xmm15",
+            "xor esi, esi",
+            "xor r11d, r11d",
+            "mov eax, 3",
+            out("rax") y,
+            clobber_abi("C"),
+        );
+    }
+    assert_eq!(y, 3);
+    assert_eq!(x * 2.0, 3.0);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_const() {
+    const SHIFT: u32 = 3;
+    let r: u64;
+    let n: i64;
+    unsafe {
+        std::arch::asm!(
+            "mov {r}, {a}",
+            "shl {r}, {s}",
+            "mov {n}, {m}",
+            r = out(reg) r,
+            n = out(reg) n,
+            a = const 5,
+            s = const SHIFT,
+            m = const -(1i64 << 40),
+        );
+    }
+    assert_eq!(r, 40);
+    assert_eq!(n, -(1 << 40));
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_att_syntax_and_raw() {
+    let a: u64;
+    let b: u64;
+    let c: u16;
+    unsafe {
+        std::arch::asm!(
+            "movq $-1, {0}",
+            "movl $7, {0:e}",
+            "leaq 1({0}), {1}",
+            "movw $0x1234, {2:x}",
+            out(reg) a,
+            out(reg) b,
+            out(reg) c,
+            options(att_syntax, nomem, nostack),
+        );
+    }
+    assert_eq!((a, b, c), (7, 8, 0x1234));
+
+    let x: u64;
+    unsafe {
+        std::arch::asm!("/* {not a placeholder} */ mov eax, 42", out("rax") x, options(raw));
+    }
+    assert_eq!(x, 42);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_may_unwind() {
+    let x: u64;
+    unsafe {
+        std::arch::asm!("mov {}, 1", out(reg) x, options(may_unwind));
+    }
+    assert_eq!(x, 1);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_explicit_reg_passthrough() {
+    let x = black_box(42u64);
+    let y: u64;
+    unsafe {
+        // The output register isn't written and thus still holds the input.
+        std::arch::asm!("", in("rax") x, lateout("rax") y, options(nomem, nostack));
+    }
+    assert_eq!(y, 42);
+}
+
+// In jit-lazy mode every function is compiled on its own. Their inline asm wrappers must still get
+// distinct names.
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+fn test_inline_asm_in_multiple_fns() {
+    #[inline(never)]
+    fn first() -> u64 {
+        let x: u64;
+        unsafe {
+            std::arch::asm!("mov {}, 1", out(reg) x, options(nomem, nostack));
+        }
+        x
+    }
+
+    #[inline(never)]
+    fn second() -> u64 {
+        let x: u64;
+        unsafe {
+            std::arch::asm!("mov {}, 2", out(reg) x, options(nomem, nostack));
+        }
+        x
+    }
+
+    assert_eq!(first(), 1);
+    assert_eq!(second(), 2);
+}
+
+// Private to the codegen unit, so the asm has to reference it through a wrapper.
+#[cfg(all(target_arch = "x86_64", not(jit)))]
+extern "sysv64" fn asm_sym_callee(x: u64) -> u64 {
+    x * 2
+}
+
+#[cfg(all(target_arch = "x86_64", not(jit)))]
+static ASM_SYM_STATIC: u64 = 21;
+
+#[cfg(all(target_arch = "x86_64", any(target_os = "linux", windows), not(jit)))]
+std::arch::global_asm!(
+    ".globl asm_sym_global_test",
+    "asm_sym_global_test:",
+    "    add rdi, qword ptr [rip + {static_}]",
+    "    jmp {callee}",
+    static_ = sym ASM_SYM_STATIC,
+    callee = sym asm_sym_callee,
+);
+
+#[cfg(all(target_arch = "x86_64", any(target_os = "linux", windows), not(jit)))]
+extern "sysv64" {
+    fn asm_sym_global_test(x: u64) -> u64;
+}
+
+#[cfg(all(target_arch = "x86_64", not(jit)))]
+fn test_inline_asm_sym() {
+    let r: u64;
+    let p: *const u64;
+    unsafe {
+        std::arch::asm!(
+            "call {}",
+            sym asm_sym_callee,
+            inout("rdi") 20u64 => _,
+            out("rax") r,
+            clobber_abi("sysv64"),
+        );
+        std::arch::asm!("lea {}, [rip + {}]", out(reg) p, sym ASM_SYM_STATIC);
+    }
+    assert_eq!(r, 40);
+    assert_eq!(unsafe { *p }, 21);
+
+    #[cfg(any(target_os = "linux", windows))]
+    assert_eq!(unsafe { asm_sym_global_test(1) }, 44);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+#[target_feature(enable = "avx")]
+unsafe fn test_inline_asm_ymm_reg() {
+    let a = _mm256_setr_ps(1., 2., 3., 4., 5., 6., 7., 8.);
+    let b = _mm256_set1_ps(10.);
+    let r: __m256;
+    std::arch::asm!(
+        "vaddps {}, {}, {}",
+        lateout(ymm_reg) r,
+        in(ymm_reg) a,
+        in(ymm_reg) b,
+        options(pure, nomem, nostack),
+    );
+    let r: [f32; 8] = transmute(r);
+    assert_eq!(r, [11., 12., 13., 14., 15., 16., 17., 18.]);
+}
+
+#[cfg(all(target_arch = "x86_64", any(not(jit), target_os = "linux")))]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn test_inline_asm_zmm_reg_and_kreg() {
+    let a = _mm512_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+    let b = _mm512_set1_epi32(8);
+    let mask: u16;
+    std::arch::asm!(
+        "vpcmpd {k}, {a}, {b}, 1",
+        k = lateout(kreg) mask,
+        a = in(zmm_reg) a,
+        b = in(zmm_reg) b,
+        options(pure, nomem, nostack),
+    );
+    assert_eq!(mask, 0x00ff);
+
+    let r: __m512i;
+    std::arch::asm!(
+        "vpaddd {r} {{{k}}}{{z}}, {a}, {a}",
+        r = lateout(zmm_reg) r,
+        k = in(kreg) 0x0f0fu16,
+        a = in(zmm_reg) a,
+        options(pure, nomem, nostack),
+    );
+    let r: [i32; 16] = transmute(r);
+    assert_eq!(r, [0, 2, 4, 6, 0, 0, 0, 0, 16, 18, 20, 22, 0, 0, 0, 0]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "f16c")]
+unsafe fn test_f16c() {
+    // 1.0, -2.5, 65504.0 (f16::MAX), inf, 1.0 + 2^-10, 0, -0, 2^-24
+    let halfs =
+        _mm_setr_epi16(0x3c00, 0xc100u16 as i16, 0x7bff, 0x7c00, 0x3c01, 0, 0x8000u16 as i16, 1);
+    let r: [f32; 4] = transmute(_mm_cvtph_ps(halfs));
+    assert_eq!(r, [1.0, -2.5, 65504.0, f32::INFINITY]);
+    let r: [f32; 8] = transmute(_mm256_cvtph_ps(halfs));
+    assert_eq!(r[4..], [1.0 + 1.0 / 1024.0, 0.0, -0.0, 1.0 / 16777216.0]);
+
+    let floats = _mm_setr_ps(1.0 + 1.0 / 4096.0, -(1.0 + 1.0 / 4096.0), 1e10, 1.0 / 33554432.0);
+    let r: [u16; 8] = transmute(_mm_cvtps_ph::<_MM_FROUND_TO_NEAREST_INT>(floats));
+    assert_eq!(r, [0x3c00, 0xbc00, 0x7c00, 0, 0, 0, 0, 0]);
+    let r: [u16; 8] = transmute(_mm_cvtps_ph::<_MM_FROUND_TO_POS_INF>(floats));
+    assert_eq!(r, [0x3c01, 0xbc00, 0x7c00, 1, 0, 0, 0, 0]);
+    let r: [u16; 8] = transmute(_mm_cvtps_ph::<_MM_FROUND_TO_NEG_INF>(floats));
+    assert_eq!(r, [0x3c00, 0xbc01, 0x7bff, 0, 0, 0, 0, 0]);
+    let r: [u16; 8] = transmute(_mm_cvtps_ph::<_MM_FROUND_TO_ZERO>(floats));
+    assert_eq!(r, [0x3c00, 0xbc00, 0x7bff, 0, 0, 0, 0, 0]);
+}
+
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse2")]
 unsafe fn test_simd() {
@@ -260,10 +1012,15 @@ unsafe fn test_simd() {
 
     #[cfg(not(jit))]
     test_mm_cmpestri();
+    #[cfg(not(jit))]
+    test_mm_cmpistri();
 
     test_mm256_shuffle_epi8();
     test_mm256_permute2x128_si256();
     test_mm256_permutevar8x32_epi32();
+    test_mm256_hadd_epi16();
+    test_mm256_sign_epi16();
+    test_mm256_srlv_epi32();
 
     #[rustfmt::skip]
     let mask1 = _mm_movemask_epi8(dbg!(_mm_setr_epi8(255u8 as i8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0)));
@@ -271,6 +1028,45 @@ unsafe fn test_simd() {
 
     #[cfg(not(jit))]
     test_crc32();
+    #[cfg(not(jit))]
+    test_rdtsc_rdrand();
+    if is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("bmi2") {
+        test_bmi();
+    }
+    test_pdep_pext_fallback();
+    if is_x86_feature_detected!("avx") {
+        test_mm_cmp_ps_and_pd();
+    }
+    if is_x86_feature_detected!("avx512f") {
+        test_avx512();
+    }
+    if is_x86_feature_detected!("f16c") {
+        test_f16c();
+    }
+
+    // Inline asm in jit mode is only supported for ELF targets.
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_clobber_abi();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_const();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_att_syntax_and_raw();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_may_unwind();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_in_multiple_fns();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    test_inline_asm_explicit_reg_passthrough();
+    #[cfg(any(not(jit), target_os = "linux"))]
+    if is_x86_feature_detected!("avx") {
+        test_inline_asm_ymm_reg();
+    }
+    #[cfg(any(not(jit), target_os = "linux"))]
+    if is_x86_feature_detected!("avx512bw") {
+        test_inline_asm_zmm_reg_and_kreg();
+    }
+    #[cfg(not(jit))]
+    test_inline_asm_sym();
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -455,6 +1251,19 @@ unsafe fn test_mm_cmpestri() {
     let b = str_to_m128i(b"foobar");
     let i = _mm_cmpestri::<_SIDD_CMP_EQUAL_ORDERED>(a, 3, b, 6);
     assert_eq!(3, i);
+    assert_eq!(1, _mm_cmpestrc::<_SIDD_CMP_EQUAL_ORDERED>(a, 3, b, 6));
+    assert_eq!(0, _mm_cmpestrz::<_SIDD_CMP_EQUAL_ORDERED>(a, 3, b, 16));
+}
+
+#[cfg(not(jit))]
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn test_mm_cmpistri() {
+    let a = str_to_m128i(b"bar");
+    let b = str_to_m128i(b"foobar");
+    let i = _mm_cmpistri::<_SIDD_CMP_EQUAL_ORDERED>(a, b);
+    assert_eq!(3, i);
+    assert_eq!(1, _mm_cmpistrz::<_SIDD_CMP_EQUAL_ORDERED>(a, b));
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -505,6 +1314,41 @@ unsafe fn test_mm256_permutevar8x32_epi32() {
     assert_eq_m256i(r, e);
 }
 
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn test_mm256_hadd_epi16() {
+    let a = _mm256_setr_epi16(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16);
+    let b = _mm256_setr_epi16(i16::MAX, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -1);
+    let r = _mm256_hadd_epi16(a, b);
+    let e = _mm256_setr_epi16(3, 7, 11, 15, i16::MIN, 0, 0, 0, 19, 23, 27, 31, 0, 0, 0, -1);
+    assert_eq_m256i(r, e);
+    let r = _mm256_hadds_epi16(a, b);
+    let e = _mm256_setr_epi16(3, 7, 11, 15, i16::MAX, 0, 0, 0, 19, 23, 27, 31, 0, 0, 0, -1);
+    assert_eq_m256i(r, e);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn test_mm256_sign_epi16() {
+    let a = _mm256_setr_epi16(1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8);
+    let b = _mm256_setr_epi16(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1);
+    let r = _mm256_sign_epi16(a, b);
+    let e = _mm256_setr_epi16(-1, 0, 3, -4, 0, 6, -7, 0, 1, -2, 0, 4, -5, 0, 7, -8);
+    assert_eq_m256i(r, e);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn test_mm256_srlv_epi32() {
+    let a = _mm256_set1_epi32(-1);
+    let count = _mm256_setr_epi32(0, 1, 4, 31, 32, 33, -1, 16);
+    let r = _mm256_srlv_epi32(a, count);
+    let e = _mm256_setr_epi32(-1, i32::MAX, 0x0fff_ffff, 1, 0, 0, 0, 0xffff);
+    assert_eq_m256i(r, e);
+    let r = _mm256_srav_epi32(a, count);
+    assert_eq_m256i(r, a);
+}
+
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
 #[cfg(not(jit))]
@@ -561,6 +1405,30 @@ fn test_checked_mul() {
     assert_eq!(i64::MIN.checked_mul(i64::MIN), None);
 }
 
+fn test_atomic_rmw() {
+    use std::sync::atomic::*;
+
+    macro_rules! test_atomic_rmw {
+        ($($atomic:ident: $int:ident),*) => {$({
+            let a = black_box($atomic::new(5));
+            assert_eq!(a.fetch_min(3, Ordering::SeqCst), 5);
+            assert_eq!(a.fetch_max(7, Ordering::SeqCst), 3);
+            assert_eq!(a.fetch_nand(6, Ordering::SeqCst), 7);
+            assert_eq!(a.load(Ordering::SeqCst), !6);
+            a.store($int::MAX, Ordering::SeqCst);
+            assert_eq!(a.fetch_min($int::MIN, Ordering::Relaxed), $int::MAX);
+            assert_eq!(a.fetch_max($int::MAX, Ordering::Relaxed), $int::MIN);
+            assert_eq!(a.load(Ordering::SeqCst), $int::MAX);
+        })*};
+    }
+
+    test_atomic_rmw!(
+        AtomicI8: i8, AtomicU8: u8, AtomicI16: i16, AtomicU16: u16,
+        AtomicI32: i32, AtomicU32: u32, AtomicI64: i64, AtomicU64: u64,
+        AtomicIsize: isize, AtomicUsize: usize
+    );
+}
+
 #[derive(PartialEq)]
 enum LoopState {
     Continue(()),
@@ -0,0 +1,307 @@
+//! Support for defining C-variadic functions and reading variadic arguments using the SysV x86_64
+//! and the AArch64 AAPCS calling conventions.
+//!
+//! A C-variadic function gets extra parameters for all argument registers not used by the fixed
+//! arguments. These are spilled to a register save area on entry, which `va_arg` reads from until
+//! it runs out of registers of the respective kind and continues with the stack arguments. The
+//! stack arguments are found using the frame pointer, so codegen units defining C-variadic
+//! functions always preserve frame pointers.
+//!
+//! See section 3.5.7 of <https://gitlab.com/x86-psABIs/x86-64-ABI> and appendix B of
+//! <https://github.com/ARM-software/abi-aa/blob/main/aapcs64/aapcs64.rst>
+
+use cranelift_codegen::ir::ArgumentPurpose;
+use rustc_hir::def::DefKind;
+use rustc_middle::mir::mono::MonoItem;
+use rustc_target::callconv::{Conv, FnAbi};
+
+use crate::prelude::*;
+
+/// The amount of floating point/vector argument registers. Each takes 16 bytes in the register
+/// save area.
+const FPR_COUNT: usize = 8;
+
+// Field offsets of the SysV x86_64 `VaListImpl` struct.
+const GP_OFFSET: i64 = 0;
+const FP_OFFSET: i64 = 4;
+const OVERFLOW_ARG_AREA: i64 = 8;
+const REG_SAVE_AREA: i64 = 16;
+
+// Field offsets of the AArch64 AAPCS `VaListImpl` struct.
+const STACK: i64 = 0;
+const GR_TOP: i64 = 8;
+const VR_TOP: i64 = 16;
+const GR_OFFS: i64 = 24;
+const VR_OFFS: i64 = 28;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum VaListAbi {
+    SysVX86_64,
+    Aapcs64,
+}
+
+impl VaListAbi {
+    fn for_target(tcx: TyCtxt<'_>) -> Option<Self> {
+        let target = &tcx.sess.target;
+        match &*target.arch {
+            "x86_64" if !target.is_like_windows => Some(VaListAbi::SysVX86_64),
+            // Apple and Windows use a plain pointer as `va_list` and pass variadic arguments
+            // differently.
+            "aarch64" if !target.is_like_windows && !target.is_like_osx => Some(VaListAbi::Aapcs64),
+            _ => None,
+        }
+    }
+
+    fn gpr_count(self) -> usize {
+        match self {
+            VaListAbi::SysVX86_64 => 6,
+            VaListAbi::Aapcs64 => 8,
+        }
+    }
+
+    /// Offset of the first floating point register in the register save area.
+    fn fp_save_offset(self) -> u32 {
+        self.gpr_count() as u32 * 8
+    }
+
+    fn reg_save_area_size(self) -> u32 {
+        self.fp_save_offset() + FPR_COUNT as u32 * 16
+    }
+}
+
+fn supported_abi(tcx: TyCtxt<'_>, fn_abi: &FnAbi<'_, Ty<'_>>) -> Option<VaListAbi> {
+    VaListAbi::for_target(tcx).filter(|_| fn_abi.conv == Conv::C)
+}
+
+/// Returns whether any of the given mono items defines a C-variadic function and thus needs frame
+/// pointers to be preserved.
+pub(crate) fn defines_c_variadic_fn<'a, 'tcx: 'a>(
+    tcx: TyCtxt<'tcx>,
+    mono_items: impl IntoIterator<Item = &'a MonoItem<'tcx>>,
+) -> bool {
+    mono_items.into_iter().any(|mono_item| match *mono_item {
+        MonoItem::Fn(instance) => {
+            let def_id = instance.def_id();
+            matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+                && tcx.fn_sig(def_id).skip_binder().c_variadic()
+        }
+        MonoItem::Static(_) | MonoItem::GlobalAsm(_) => false,
+    })
+}
+
+/// Returns the amount of general purpose and floating point registers used by the fixed arguments
+/// or `None` if some of them are passed on the stack.
+fn fixed_register_usage(abi: VaListAbi, params: &[AbiParam]) -> Option<(usize, usize)> {
+    let mut gprs: usize = 0;
+    let mut fprs: usize = 0;
+    for param in params {
+        match param.purpose {
+            ArgumentPurpose::Normal => {}
+            // AArch64 passes the return area pointer in x8 rather than in an argument register.
+            ArgumentPurpose::StructReturn if abi == VaListAbi::Aapcs64 => continue,
+            ArgumentPurpose::StructReturn => {}
+            _ => return None,
+        }
+        match param.value_type {
+            types::I8 | types::I16 | types::I32 | types::I64 => gprs += 1,
+            types::I128 => {
+                // AArch64 passes 128bit integers in an even numbered register pair.
+                if abi == VaListAbi::Aapcs64 {
+                    gprs = gprs.next_multiple_of(2);
+                }
+                gprs += 2;
+            }
+            types::F32 | types::F64 => fprs += 1,
+            _ => return None,
+        }
+    }
+
+    if gprs <= abi.gpr_count() && fprs <= FPR_COUNT { Some((gprs, fprs)) } else { None }
+}
+
+/// Add a parameter for every argument register not used by the fixed arguments of a C-variadic
+/// function.
+pub(super) fn add_register_save_area_params<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+    params: &mut Vec<AbiParam>,
+) {
+    let Some(abi) = supported_abi(tcx, fn_abi) else {
+        return;
+    };
+    let Some((gprs, fprs)) = fixed_register_usage(abi, params) else {
+        return;
+    };
+
+    params.extend((gprs..abi.gpr_count()).map(|_| AbiParam::new(types::I64)));
+    params.extend((fprs..FPR_COUNT).map(|_| AbiParam::new(types::F64)));
+}
+
+/// Create the `VaListImpl` for the `...` argument of the current function by saving the
+/// remaining block params in the register save area.
+pub(super) fn codegen_va_start<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+    block_params_iter: &mut impl Iterator<Item = Value>,
+) -> CValue<'tcx> {
+    let reg_params = block_params_iter.collect::<Vec<_>>();
+    let params = &fx.bcx.func.signature.params;
+    let fixed_params = &params[..params.len() - reg_params.len()];
+    let Some((abi, (gprs, fprs))) = supported_abi(fx.tcx, fx.fn_abi)
+        .and_then(|abi| Some((abi, fixed_register_usage(abi, fixed_params)?)))
+    else {
+        fx.tcx.dcx().span_fatal(
+            fx.mir.span,
+            "Defining variadic functions is only supported for the SysV x86_64 and AArch64 AAPCS \
+            C ABIs by Cranelift when all fixed arguments are passed in registers",
+        );
+    };
+    assert_eq!(reg_params.len(), abi.gpr_count() - gprs + FPR_COUNT - fprs);
+    assert!(
+        fx.module.isa().flags().preserve_frame_pointers(),
+        "frame pointers must be preserved for C-variadic functions",
+    );
+
+    let reg_save_area = fx.create_stack_slot(abi.reg_save_area_size(), 16);
+    let (gpr_params, fpr_params) = reg_params.split_at(abi.gpr_count() - gprs);
+    for (i, &param) in gpr_params.iter().enumerate() {
+        let offset = (gprs + i) * 8;
+        reg_save_area.offset_i64(fx, offset as i64).store(fx, param, MemFlags::trusted());
+    }
+    for (i, &param) in fpr_params.iter().enumerate() {
+        let offset = abi.fp_save_offset() as usize + (fprs + i) * 16;
+        reg_save_area.offset_i64(fx, offset as i64).store(fx, param, MemFlags::trusted());
+    }
+    let reg_save_area = reg_save_area.get_addr(fx);
+
+    // The stack arguments start right after the saved frame pointer and the return address.
+    let frame_pointer = fx.bcx.ins().get_frame_pointer(fx.pointer_type);
+    let stack_args = fx.bcx.ins().iadd_imm(frame_pointer, 16);
+
+    let va_list = CPlace::new_stack_slot(fx, layout);
+    let va_list_ptr = va_list.to_ptr();
+    match abi {
+        VaListAbi::SysVX86_64 => {
+            let gp_offset = fx.bcx.ins().iconst(types::I32, gprs as i64 * 8);
+            let fp_offset =
+                fx.bcx.ins().iconst(types::I32, i64::from(abi.fp_save_offset()) + fprs as i64 * 16);
+
+            va_list_ptr.offset_i64(fx, GP_OFFSET).store(fx, gp_offset, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, FP_OFFSET).store(fx, fp_offset, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, OVERFLOW_ARG_AREA).store(
+                fx,
+                stack_args,
+                MemFlags::trusted(),
+            );
+            va_list_ptr.offset_i64(fx, REG_SAVE_AREA).store(fx, reg_save_area, MemFlags::trusted());
+        }
+        VaListAbi::Aapcs64 => {
+            // The register offsets are negative offsets from the end of the respective part of
+            // the register save area.
+            let gr_top = fx.bcx.ins().iadd_imm(reg_save_area, i64::from(abi.fp_save_offset()));
+            let vr_top = fx.bcx.ins().iadd_imm(reg_save_area, i64::from(abi.reg_save_area_size()));
+            let gr_offs = fx.bcx.ins().iconst(types::I32, -((abi.gpr_count() - gprs) as i64 * 8));
+            let vr_offs = fx.bcx.ins().iconst(types::I32, -((FPR_COUNT - fprs) as i64 * 16));
+
+            va_list_ptr.offset_i64(fx, STACK).store(fx, stack_args, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, GR_TOP).store(fx, gr_top, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, VR_TOP).store(fx, vr_top, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, GR_OFFS).store(fx, gr_offs, MemFlags::trusted());
+            va_list_ptr.offset_i64(fx, VR_OFFS).store(fx, vr_offs, MemFlags::trusted());
+        }
+    }
+
+    va_list.to_cvalue(fx)
+}
+
+/// Read the next variadic argument from the `VaListImpl` pointed to by `va_list`.
+pub(crate) fn codegen_va_arg<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    va_list: Value,
+    layout: TyAndLayout<'tcx>,
+    span: Span,
+) -> CValue<'tcx> {
+    let Some(abi) = VaListAbi::for_target(fx.tcx) else {
+        fx.tcx.dcx().span_fatal(
+            span,
+            "`va_arg` is only supported for SysV x86_64 and AArch64 AAPCS by Cranelift",
+        );
+    };
+
+    let clif_ty = match layout.backend_repr {
+        BackendRepr::Scalar(scalar) => scalar_to_clif_type(fx.tcx, scalar),
+        _ => fx.tcx.dcx().span_fatal(span, format!("`va_arg` for {} is not supported", layout.ty)),
+    };
+    assert!(clif_ty.bytes() <= 8, "{}", layout.ty);
+
+    // Floats are passed in floating point registers, everything else in general purpose
+    // registers.
+    let is_float = clif_ty.is_float();
+    let reg_size = if is_float { 16 } else { 8 };
+    let (reg_offset_field, stack_field) = match abi {
+        VaListAbi::SysVX86_64 => (if is_float { FP_OFFSET } else { GP_OFFSET }, OVERFLOW_ARG_AREA),
+        VaListAbi::Aapcs64 => (if is_float { VR_OFFS } else { GR_OFFS }, STACK),
+    };
+
+    let va_list = Pointer::new(va_list);
+    let reg_offset =
+        va_list.offset_i64(fx, reg_offset_field).load(fx, types::I32, MemFlags::trusted());
+    let in_reg = match abi {
+        VaListAbi::SysVX86_64 => {
+            let reg_offset_limit =
+                if is_float { abi.reg_save_area_size() } else { abi.fp_save_offset() };
+            fx.bcx.ins().icmp_imm(
+                IntCC::UnsignedLessThanOrEqual,
+                reg_offset,
+                i64::from(reg_offset_limit - reg_size),
+            )
+        }
+        // The offset counts up to zero as registers get used.
+        VaListAbi::Aapcs64 => fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, reg_offset, 0),
+    };
+
+    let in_reg_block = fx.bcx.create_block();
+    let in_mem_block = fx.bcx.create_block();
+    let done_block = fx.bcx.create_block();
+    let arg_addr = fx.bcx.append_block_param(done_block, fx.pointer_type);
+
+    fx.bcx.ins().brif(in_reg, in_reg_block, &[], in_mem_block, &[]);
+    fx.bcx.seal_block(in_reg_block);
+    fx.bcx.seal_block(in_mem_block);
+
+    fx.bcx.switch_to_block(in_reg_block);
+    let reg_addr = match abi {
+        VaListAbi::SysVX86_64 => {
+            let reg_save_area = va_list.offset_i64(fx, REG_SAVE_AREA).load(
+                fx,
+                fx.pointer_type,
+                MemFlags::trusted(),
+            );
+            let offset = fx.bcx.ins().uextend(fx.pointer_type, reg_offset);
+            fx.bcx.ins().iadd(reg_save_area, offset)
+        }
+        VaListAbi::Aapcs64 => {
+            let reg_top = va_list.offset_i64(fx, if is_float { VR_TOP } else { GR_TOP }).load(
+                fx,
+                fx.pointer_type,
+                MemFlags::trusted(),
+            );
+            let offset = fx.bcx.ins().sextend(fx.pointer_type, reg_offset);
+            fx.bcx.ins().iadd(reg_top, offset)
+        }
+    };
+    let next_reg_offset = fx.bcx.ins().iadd_imm(reg_offset, i64::from(reg_size));
+    va_list.offset_i64(fx, reg_offset_field).store(fx, next_reg_offset, MemFlags::trusted());
+    fx.bcx.ins().jump(done_block, &[reg_addr]);
+
+    fx.bcx.switch_to_block(in_mem_block);
+    let stack = va_list.offset_i64(fx, stack_field).load(fx, fx.pointer_type, MemFlags::trusted());
+    let next_stack = fx.bcx.ins().iadd_imm(stack, 8);
+    va_list.offset_i64(fx, stack_field).store(fx, next_stack, MemFlags::trusted());
+    fx.bcx.ins().jump(done_block, &[stack]);
+
+    fx.bcx.seal_block(done_block);
+    fx.bcx.switch_to_block(done_block);
+    let arg = fx.bcx.ins().load(clif_ty, MemFlags::trusted(), arg_addr, 0);
+    CValue::by_val(arg, layout)
+}
@@ -1,5 +1,6 @@
 //! Handling of everything related to the calling convention. Also fills `fx.local_map`.
 
+mod c_variadic;
 mod comments;
 mod pass_mode;
 mod returning;
@@ -21,6 +22,7 @@
 use rustc_span::source_map::Spanned;
 use rustc_target::callconv::{Conv, FnAbi, PassMode};
 
+pub(crate) use self::c_variadic::{codegen_va_arg, defines_c_variadic_fn};
 use self::pass_mode::*;
 pub(crate) use self::returning::codegen_return;
 use crate::prelude::*;
@@ -36,7 +38,11 @@ fn clif_sig_from_fn_abi<'tcx>(
 
     let (return_ptr, returns) = fn_abi.ret.get_abi_return(tcx);
     // Sometimes the first param is a pointer to the place where the return value needs to be stored.
-    let params: Vec<_> = return_ptr.into_iter().chain(inputs).collect();
+    let mut params: Vec<_> = return_ptr.into_iter().chain(inputs).collect();
+
+    if fn_abi.c_variadic {
+        c_variadic::add_register_save_area_params(tcx, fn_abi, &mut params);
+    }
 
     Signature { params, returns, call_conv }
 }
@@ -130,7 +136,8 @@ pub(crate) fn lib_call(
                 .into_iter()
                 .zip(args)
                 .map(|(param, &arg)| {
-                    if param.value_type == types::I128 {
+                    // Windows passes `i128` and `f128` arguments of libcalls by reference.
+                    if param.value_type == types::I128 || param.value_type == types::F128 {
                         let arg_ptr = self.create_stack_slot(16, 16);
                         arg_ptr.store(self, arg, MemFlags::trusted());
                         (AbiParam::new(self.pointer_type), arg_ptr.get_addr(self))
@@ -228,22 +235,23 @@ enum ArgKind<'tcx> {
         Spread(Vec<Option<CValue<'tcx>>>),
     }
 
-    // FIXME implement variadics in cranelift
-    if fx.fn_abi.c_variadic {
-        fx.tcx.dcx().span_fatal(
-            fx.mir.span,
-            "Defining variadic functions is not yet supported by Cranelift",
-        );
-    }
-
     let mut arg_abis_iter = fx.fn_abi.args.iter();
 
     let func_params = fx
         .mir
         .args_iter()
-        .map(|local| {
+        .enumerate()
+        .map(|(arg_index, local)| {
             let arg_ty = fx.monomorphize(fx.mir.local_decls[local].ty);
 
+            if fx.fn_abi.c_variadic && arg_index == fx.fn_abi.args.len() {
+                // This is the `VaListImpl` for the `...` argument of a C-variadic function.
+                let layout = fx.layout_of(arg_ty);
+                let va_list =
+                    self::c_variadic::codegen_va_start(fx, layout, &mut block_params_iter);
+                return (local, ArgKind::Normal(Some(va_list)), arg_ty);
+            }
+
             // Adapted from https://github.com/rust-lang/rust/blob/145155dc96757002c7b2e9de8489416e2fdbbd57/src/librustc_codegen_llvm/mir/mod.rs#L442-L482
             if Some(local) == fx.mir.spread_arg {
                 // This argument (e.g. the last argument in the "rust-call" ABI)
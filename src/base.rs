@@ -1,11 +1,17 @@
 //! Codegen of a single function
 
 use cranelift_codegen::CodegenError;
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::dominator_tree::DominatorTree;
+use cranelift_codegen::flowgraph::ControlFlowGraph;
 use cranelift_codegen::ir::UserFuncName;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::loop_analysis::LoopAnalysis;
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
-use cranelift_module::ModuleError;
+use cranelift_object::ObjectModule;
 use rustc_ast::InlineAsmOptions;
 use rustc_codegen_ssa::base::is_call_from_compiler_builtins_to_upstream_monomorphization;
+use rustc_data_structures::profiling::SelfProfilerRef;
 use rustc_index::IndexVec;
 use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::mir::InlineAsmMacro;
@@ -13,6 +19,7 @@
 use rustc_middle::ty::adjustment::PointerCoercion;
 use rustc_middle::ty::layout::FnAbiOf;
 use rustc_middle::ty::print::with_no_trimmed_paths;
+use rustc_span::sym;
 
 use crate::BackendConfig;
 use crate::constant::ConstantCx;
@@ -20,6 +27,7 @@
 use crate::inline_asm::codegen_naked_asm;
 use crate::prelude::*;
 use crate::pretty_clif::CommentWriter;
+use crate::unwind_module::UnwindModule;
 
 pub(crate) struct CodegenedFunction {
     symbol_name: String,
@@ -96,7 +104,11 @@ pub(crate) fn codegen_fn<'tcx>(
     func.clear();
     func.name = UserFuncName::user(0, func_id.as_u32());
     func.signature = sig;
-    func.collect_debug_info();
+    if cx.debug_context.is_some() {
+        // Value labels are only consumed when emitting debuginfo. Skip collecting them otherwise
+        // to keep the clif ir that is buffered until the compile phase small.
+        func.collect_debug_info();
+    }
 
     let mut bcx = FunctionBuilder::new(&mut func, &mut func_ctx);
 
@@ -164,19 +176,21 @@ pub(crate) fn codegen_fn<'tcx>(
     }
 
     // Verify function
-    verify_func(tcx, backend_config, &clif_comments, &func);
+    verify_func(tcx, backend_config, module.isa(), &clif_comments, &func);
 
     Some(CodegenedFunction { symbol_name, func_id, func, clif_comments, func_debug_cx })
 }
 
+#[cfg(feature = "jit")]
 pub(crate) fn compile_fn(
     cx: &mut crate::CodegenCx,
     cached_context: &mut Context,
     module: &mut dyn Module,
     codegened_func: CodegenedFunction,
 ) {
+    let profiler = cx.profiler.clone();
     let _timer =
-        cx.profiler.generic_activity_with_arg("compile function", &*codegened_func.symbol_name);
+        profiler.generic_activity_with_arg("compile function", &*codegened_func.symbol_name);
 
     let clif_comments = codegened_func.clif_comments;
 
@@ -218,14 +232,8 @@ pub(crate) fn compile_fn(
         context.want_disasm = cx.should_write_ir;
         match module.define_function(codegened_func.func_id, context) {
             Ok(()) => {}
-            Err(ModuleError::Compilation(CodegenError::ImplLimitExceeded)) => {
-                let early_dcx = rustc_session::EarlyDiagCtxt::new(
-                    rustc_session::config::ErrorOutputType::default(),
-                );
-                early_dcx.early_fatal(format!(
-                    "backend implementation limit exceeded while compiling {name}",
-                    name = codegened_func.symbol_name
-                ));
+            Err(cranelift_module::ModuleError::Compilation(err)) => {
+                report_compile_error(&codegened_func.symbol_name, err);
             }
             Err(err) => {
                 panic!("Error while defining {name}: {err:?}", name = codegened_func.symbol_name);
@@ -233,21 +241,125 @@ pub(crate) fn compile_fn(
         }
     });
 
+    finalize_fn(
+        cx,
+        module.isa(),
+        &codegened_func.symbol_name,
+        codegened_func.func_id,
+        &clif_comments,
+        codegened_func.func_debug_cx,
+        context,
+    );
+
+    // The machine code and relocations have been copied into the module at this point. Release
+    // the compiled code and clif ir now rather than when the next function gets compiled.
+    context.clear();
+}
+
+/// A function for which machine code has been generated, but which hasn't been defined in the
+/// module yet.
+pub(crate) struct CompiledFunction {
+    symbol_name: String,
+    func_id: FuncId,
+    clif_comments: CommentWriter,
+    func_debug_cx: Option<FunctionDebugContext>,
+    context: Context,
+}
+
+/// Run Cranelift on a function without defining it in the module.
+///
+/// Unlike [`compile_fn`] this doesn't need access to the module, which allows it to run on another
+/// thread than the one defining functions in the object file and emitting debuginfo for them.
+pub(crate) fn lower_fn(
+    profiler: &SelfProfilerRef,
+    isa: &dyn TargetIsa,
+    should_write_ir: bool,
+    codegened_func: CodegenedFunction,
+) -> CompiledFunction {
+    let _timer =
+        profiler.generic_activity_with_arg("compile function", &*codegened_func.symbol_name);
+
+    let mut context = Context::for_function(codegened_func.func);
+    context.want_disasm = should_write_ir;
+    if let Err(err) = context.compile(isa, &mut ControlPlane::default()) {
+        report_compile_error(&codegened_func.symbol_name, err.inner);
+    }
+
+    // The analysis results are not needed for defining the function or its debuginfo. Free them
+    // before the function gets queued for the module. Clearing them would keep their allocations.
+    context.cfg = ControlFlowGraph::new();
+    context.domtree = DominatorTree::new();
+    context.loop_analysis = LoopAnalysis::new();
+
+    CompiledFunction {
+        symbol_name: codegened_func.symbol_name,
+        func_id: codegened_func.func_id,
+        clif_comments: codegened_func.clif_comments,
+        func_debug_cx: codegened_func.func_debug_cx,
+        context,
+    }
+}
+
+/// Copy the machine code of a function lowered by [`lower_fn`] into the object file and generate
+/// debuginfo and unwind info for it.
+pub(crate) fn define_compiled_fn(
+    cx: &mut crate::CodegenCx,
+    module: &mut UnwindModule<ObjectModule>,
+    compiled_func: CompiledFunction,
+) {
+    let profiler = cx.profiler.clone();
+    let _timer = profiler.generic_activity_with_arg("define function", &*compiled_func.symbol_name);
+
+    let CompiledFunction { symbol_name, func_id, clif_comments, func_debug_cx, context } =
+        compiled_func;
+
+    if let Err(err) = module.define_compiled_function(func_id, &context) {
+        panic!("Error while defining {symbol_name}: {err:?}");
+    }
+
+    finalize_fn(cx, module.isa(), &symbol_name, func_id, &clif_comments, func_debug_cx, &context);
+}
+
+fn report_compile_error(symbol_name: &str, err: CodegenError) -> ! {
+    match err {
+        CodegenError::ImplLimitExceeded => {
+            let early_dcx = rustc_session::EarlyDiagCtxt::new(
+                rustc_session::config::ErrorOutputType::default(),
+            );
+            early_dcx.early_fatal(format!(
+                "backend implementation limit exceeded while compiling {symbol_name}"
+            ));
+        }
+        err => panic!("Error while compiling {symbol_name}: {err:?}"),
+    }
+}
+
+/// Write the optimized clif ir if requested and define debuginfo for a function whose machine
+/// code is stored in `context`.
+fn finalize_fn(
+    cx: &mut crate::CodegenCx,
+    isa: &dyn TargetIsa,
+    symbol_name: &str,
+    func_id: FuncId,
+    clif_comments: &CommentWriter,
+    func_debug_cx: Option<FunctionDebugContext>,
+    context: &Context,
+) {
     if cx.should_write_ir {
         // Write optimized function to file for debugging
         crate::pretty_clif::write_clif_file(
             &cx.output_filenames,
-            &codegened_func.symbol_name,
+            symbol_name,
             "opt",
-            module.isa(),
+            isa,
             &context.func,
-            &clif_comments,
+            clif_comments,
         );
 
         if let Some(disasm) = &context.compiled_code().unwrap().vcode {
             crate::pretty_clif::write_ir_file(
                 &cx.output_filenames,
-                &format!("{}.vcode", codegened_func.symbol_name),
+                &format!("{}.vcode", symbol_name),
                 |file| file.write_all(disasm.as_bytes()),
             )
         }
@@ -257,11 +369,7 @@ pub(crate) fn compile_fn(
     let debug_context = &mut cx.debug_context;
     cx.profiler.generic_activity("generate debug info").run(|| {
         if let Some(debug_context) = debug_context {
-            codegened_func.func_debug_cx.unwrap().finalize(
-                debug_context,
-                codegened_func.func_id,
-                context,
-            );
+            func_debug_cx.unwrap().finalize(debug_context, func_id, context);
         }
     });
 }
@@ -269,6 +377,7 @@ pub(crate) fn compile_fn(
 fn verify_func(
     tcx: TyCtxt<'_>,
     backend_config: &BackendConfig,
+    isa: &dyn TargetIsa,
     writer: &crate::pretty_clif::CommentWriter,
     func: &Function,
 ) {
@@ -277,8 +386,9 @@ fn verify_func(
     }
 
     tcx.prof.generic_activity("verify clif ir").run(|| {
-        let flags = cranelift_codegen::settings::Flags::new(cranelift_codegen::settings::builder());
-        match cranelift_codegen::verify_function(&func, &flags) {
+        // Verify against the ISA rather than just the flags, as some instructions like
+        // `get_frame_pointer` are only valid for some ISAs.
+        match cranelift_codegen::verify_function(&func, isa) {
             Ok(_) => {}
             Err(err) => {
                 tcx.dcx().err(format!("{:?}", err));
@@ -310,6 +420,7 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
         .run(|| crate::abi::codegen_fn_prelude(fx, start_block));
 
     let reachable_blocks = traversal::mono_reachable_as_bitset(fx.mir, fx.tcx, fx.instance);
+    let branch_hints = collect_branch_hints(fx);
 
     for (bb, bb_data) in fx.mir.basic_blocks.iter_enumerated() {
         let block = fx.get_block(bb);
@@ -435,6 +546,7 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
             }
 
             TerminatorKind::SwitchInt { discr, targets } => {
+                let discr_local = discr.place().and_then(|place| place.as_local());
                 let discr = codegen_operand(fx, discr);
                 let switch_ty = discr.layout().ty;
                 let discr = discr.load_scalar(fx);
@@ -444,14 +556,32 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
                 if use_bool_opt {
                     assert_eq!(targets.iter().count(), 1);
                     let (then_value, then_block) = targets.iter().next().unwrap();
-                    let then_block = fx.get_block(then_block);
-                    let else_block = fx.get_block(targets.otherwise());
                     let test_zero = match then_value {
                         0 => true,
                         1 => false,
                         _ => unreachable!("{:?}", targets),
                     };
 
+                    // Move the unlikely branch target out of line. Cranelift doesn't support
+                    // branch weights, so this is only done when the target block isn't reachable
+                    // through any other edge.
+                    if let Some(&expected) = discr_local.and_then(|local| branch_hints.get(&local))
+                    {
+                        let (true_block, false_block) = if test_zero {
+                            (targets.otherwise(), then_block)
+                        } else {
+                            (then_block, targets.otherwise())
+                        };
+                        let unlikely_block = if expected { false_block } else { true_block };
+                        if fx.mir.basic_blocks.predecessors()[unlikely_block].len() == 1 {
+                            let unlikely_block = fx.get_block(unlikely_block);
+                            fx.bcx.set_cold_block(unlikely_block);
+                        }
+                    }
+
+                    let then_block = fx.get_block(then_block);
+                    let else_block = fx.get_block(targets.otherwise());
+
                     let (discr, is_inverted) =
                         crate::optimize::peephole::maybe_unwrap_bool_not(&mut fx.bcx, discr);
                     let test_zero = if is_inverted { !test_zero } else { test_zero };
@@ -514,12 +644,8 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
                 line_spans: _,
                 unwind: _,
             } => {
-                if options.contains(InlineAsmOptions::MAY_UNWIND) {
-                    fx.tcx.dcx().span_fatal(
-                        source_info.span,
-                        "cranelift doesn't support unwinding from inline assembly.",
-                    );
-                }
+                // FIXME use the unwind edge for options(may_unwind) once unwinding is supported.
+                // Until then it is ignored just like the unwind edge of calls.
 
                 let have_labels = if options.contains(InlineAsmOptions::NORETURN) {
                     !targets.is_empty()
@@ -567,6 +693,30 @@ fn codegen_fn_body(fx: &mut FunctionCx<'_, '_, '_>, start_block: Block) {
     }
 }
 
+/// Find all locals holding the result of a `likely` or `unlikely` call together with the value the
+/// hint expects them to have.
+fn collect_branch_hints(fx: &FunctionCx<'_, '_, '_>) -> FxHashMap<Local, bool> {
+    let mut branch_hints = FxHashMap::default();
+    for bb_data in fx.mir.basic_blocks.iter() {
+        let TerminatorKind::Call { func, destination, .. } = &bb_data.terminator().kind else {
+            continue;
+        };
+        let (Some((def_id, _)), Some(local)) = (func.const_fn_def(), destination.as_local()) else {
+            continue;
+        };
+        match fx.tcx.intrinsic(def_id).map(|intrinsic| intrinsic.name) {
+            Some(sym::likely) => {
+                branch_hints.insert(local, true);
+            }
+            Some(sym::unlikely) => {
+                branch_hints.insert(local, false);
+            }
+            _ => {}
+        }
+    }
+    branch_hints
+}
+
 fn codegen_stmt<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     #[allow(unused_variables)] cur_block: Block,
@@ -647,7 +797,9 @@ fn codegen_stmt<'tcx>(
                             let val = operand.load_scalar(fx);
                             match layout.ty.kind() {
                                 ty::Int(_) => CValue::by_val(fx.bcx.ins().ineg(val), layout),
-                                ty::Float(_) => CValue::by_val(fx.bcx.ins().fneg(val), layout),
+                                ty::Float(_) => {
+                                    CValue::by_val(crate::codegen_f16_f128::fneg(fx, val), layout)
+                                }
                                 _ => unreachable!("un op Neg for {:?}", layout.ty),
                             }
                         }
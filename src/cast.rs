@@ -36,6 +36,13 @@ pub(crate) fn clif_int_or_float_cast(
 ) -> Value {
     let from_ty = fx.bcx.func.dfg.value_type(from);
 
+    if matches!(from_ty, types::F16 | types::F128) || matches!(to_ty, types::F16 | types::F128) {
+        if from_ty == to_ty {
+            return from;
+        }
+        return crate::codegen_f16_f128::codegen_cast(fx, from, from_signed, to_ty, to_signed);
+    }
+
     if from_ty.is_int() && to_ty.is_int() {
         // int-like -> int-like
         clif_intcast(
@@ -0,0 +1,333 @@
+//! Lowering of `f16` and `f128` operations.
+//!
+//! Cranelift can load, store and pass these types around, but most native backends don't support
+//! arithmetic on them. `f16` is computed using `f32` which can represent every `f16` value
+//! exactly, while `f128` operations are lowered to compiler-builtins libcalls.
+
+use crate::prelude::*;
+
+/// On x86_64 Apple targets compiler-builtins passes and returns `f16` as `u16`.
+fn f16_libcall_ty(fx: &FunctionCx<'_, '_, '_>) -> Type {
+    if fx.tcx.sess.target.is_like_osx && fx.tcx.sess.target.arch == "x86_64" {
+        types::I16
+    } else {
+        types::F16
+    }
+}
+
+pub(crate) fn f16_to_f32(fx: &mut FunctionCx<'_, '_, '_>, value: Value) -> Value {
+    let arg_ty = f16_libcall_ty(fx);
+    let value = if arg_ty == types::I16 { codegen_bitcast(fx, types::I16, value) } else { value };
+    fx.lib_call("__extendhfsf2", vec![AbiParam::new(arg_ty)], vec![AbiParam::new(types::F32)], &[
+        value,
+    ])[0]
+}
+
+pub(crate) fn f32_to_f16(fx: &mut FunctionCx<'_, '_, '_>, value: Value) -> Value {
+    let ret_ty = f16_libcall_ty(fx);
+    let ret = fx.lib_call(
+        "__truncsfhf2",
+        vec![AbiParam::new(types::F32)],
+        vec![AbiParam::new(ret_ty)],
+        &[value],
+    )[0];
+    if ret_ty == types::I16 { codegen_bitcast(fx, types::F16, ret) } else { ret }
+}
+
+fn f64_to_f16(fx: &mut FunctionCx<'_, '_, '_>, value: Value) -> Value {
+    // Going through `f32` would round twice.
+    let ret_ty = f16_libcall_ty(fx);
+    let ret = fx.lib_call(
+        "__truncdfhf2",
+        vec![AbiParam::new(types::F64)],
+        vec![AbiParam::new(ret_ty)],
+        &[value],
+    )[0];
+    if ret_ty == types::I16 { codegen_bitcast(fx, types::F16, ret) } else { ret }
+}
+
+/// Like `fcmp`, but also supports `f16` and `f128`.
+pub(crate) fn fcmp(fx: &mut FunctionCx<'_, '_, '_>, cc: FloatCC, lhs: Value, rhs: Value) -> Value {
+    let ty = fx.bcx.func.dfg.value_type(lhs);
+    match ty {
+        types::F32 | types::F64 => fx.bcx.ins().fcmp(cc, lhs, rhs),
+        types::F16 => {
+            let lhs = f16_to_f32(fx, lhs);
+            let rhs = f16_to_f32(fx, rhs);
+            fx.bcx.ins().fcmp(cc, lhs, rhs)
+        }
+        types::F128 => {
+            // The comparison libcalls return a value which has to be compared against zero
+            // using the given integer condition code.
+            let (name, int_cc) = match cc {
+                FloatCC::Equal => ("__eqtf2", IntCC::Equal),
+                FloatCC::NotEqual => ("__netf2", IntCC::NotEqual),
+                FloatCC::LessThan => ("__lttf2", IntCC::SignedLessThan),
+                FloatCC::LessThanOrEqual => ("__letf2", IntCC::SignedLessThanOrEqual),
+                FloatCC::GreaterThan => ("__gttf2", IntCC::SignedGreaterThan),
+                FloatCC::GreaterThanOrEqual => ("__getf2", IntCC::SignedGreaterThanOrEqual),
+                FloatCC::Unordered => ("__unordtf2", IntCC::NotEqual),
+                FloatCC::Ordered => ("__unordtf2", IntCC::Equal),
+                _ => unreachable!("{cc:?}"),
+            };
+            let res = fx.lib_call(
+                name,
+                vec![AbiParam::new(types::F128), AbiParam::new(types::F128)],
+                vec![AbiParam::new(types::I32)],
+                &[lhs, rhs],
+            )[0];
+            fx.bcx.ins().icmp_imm(int_cc, res, 0)
+        }
+        _ => unreachable!("{ty:?}"),
+    }
+}
+
+pub(crate) fn codegen_f16_f128_binop(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    bin_op: BinOp,
+    lhs: Value,
+    rhs: Value,
+) -> Value {
+    let ty = fx.bcx.func.dfg.value_type(lhs);
+    match ty {
+        types::F16 => {
+            let lhs = f16_to_f32(fx, lhs);
+            let rhs = f16_to_f32(fx, rhs);
+            let res = match bin_op {
+                BinOp::Add => fx.bcx.ins().fadd(lhs, rhs),
+                BinOp::Sub => fx.bcx.ins().fsub(lhs, rhs),
+                BinOp::Mul => fx.bcx.ins().fmul(lhs, rhs),
+                BinOp::Div => fx.bcx.ins().fdiv(lhs, rhs),
+                BinOp::Rem => fx.lib_call(
+                    "fmodf",
+                    vec![AbiParam::new(types::F32), AbiParam::new(types::F32)],
+                    vec![AbiParam::new(types::F32)],
+                    &[lhs, rhs],
+                )[0],
+                _ => unreachable!("{bin_op:?}"),
+            };
+            f32_to_f16(fx, res)
+        }
+        types::F128 => {
+            let name = match bin_op {
+                BinOp::Add => "__addtf3",
+                BinOp::Sub => "__subtf3",
+                BinOp::Mul => "__multf3",
+                BinOp::Div => "__divtf3",
+                BinOp::Rem => match f128_fmod_name(fx) {
+                    Some(name) => name,
+                    None => {
+                        crate::trap::trap_unimplemented(
+                            fx,
+                            "f128 remainder is not supported on this target",
+                        );
+                        // Continue in an unreachable block to produce the result value.
+                        let unreachable_block = fx.bcx.create_block();
+                        fx.bcx.switch_to_block(unreachable_block);
+                        fx.bcx.seal_block(unreachable_block);
+                        let zero = fx.bcx.ins().iconst(types::I64, 0);
+                        let zero = fx.bcx.ins().uextend(types::I128, zero);
+                        return codegen_bitcast(fx, types::F128, zero);
+                    }
+                },
+                _ => unreachable!("{bin_op:?}"),
+            };
+            fx.lib_call(
+                name,
+                vec![AbiParam::new(types::F128), AbiParam::new(types::F128)],
+                vec![AbiParam::new(types::F128)],
+                &[lhs, rhs],
+            )[0]
+        }
+        _ => unreachable!("{ty:?}"),
+    }
+}
+
+/// Returns the name of the libm function implementing `fmod` for `f128` if the target has one.
+///
+/// compiler-builtins doesn't provide it. glibc has `fmodf128` on all architectures. On the
+/// remaining targets where `long double` is IEEE binary128, `fmodl` is used instead.
+fn f128_fmod_name(fx: &FunctionCx<'_, '_, '_>) -> Option<&'static str> {
+    let target = &fx.tcx.sess.target;
+    if target.os == "linux" && target.env == "gnu" {
+        Some("fmodf128")
+    } else if matches!(&*target.arch, "aarch64" | "riscv64" | "s390x")
+        && !target.is_like_osx
+        && !target.is_like_windows
+    {
+        Some("fmodl")
+    } else {
+        None
+    }
+}
+
+/// Returns the integer type with the same size as the given `f16` or `f128` value and the mask of
+/// the sign bit within the most significant 64 bits of it.
+fn sign_bit(fx: &FunctionCx<'_, '_, '_>, value: Value) -> (Type, i64) {
+    match fx.bcx.func.dfg.value_type(value) {
+        types::F16 => (types::I16, 0x8000),
+        types::F128 => (types::I128, i64::MIN),
+        ty => unreachable!("{ty:?}"),
+    }
+}
+
+/// Apply `f` to the most significant 64 bits of the bit representation of `value` together with
+/// the mask of the sign bit.
+fn map_sign_word(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    value: Value,
+    f: &dyn Fn(&mut FunctionCx<'_, '_, '_>, Value, i64) -> Value,
+) -> Value {
+    let float_ty = fx.bcx.func.dfg.value_type(value);
+    let (int_ty, mask) = sign_bit(fx, value);
+    let bits = codegen_bitcast(fx, int_ty, value);
+    let bits = if int_ty == types::I128 {
+        let (lsb, msb) = fx.bcx.ins().isplit(bits);
+        let msb = f(fx, msb, mask);
+        fx.bcx.ins().iconcat(lsb, msb)
+    } else {
+        f(fx, bits, mask)
+    };
+    codegen_bitcast(fx, float_ty, bits)
+}
+
+pub(crate) fn fneg(fx: &mut FunctionCx<'_, '_, '_>, value: Value) -> Value {
+    match fx.bcx.func.dfg.value_type(value) {
+        types::F32 | types::F64 => fx.bcx.ins().fneg(value),
+        _ => map_sign_word(fx, value, &|fx, bits, mask| fx.bcx.ins().bxor_imm(bits, mask)),
+    }
+}
+
+pub(crate) fn fabs(fx: &mut FunctionCx<'_, '_, '_>, value: Value) -> Value {
+    match fx.bcx.func.dfg.value_type(value) {
+        types::F32 | types::F64 => fx.bcx.ins().fabs(value),
+        _ => map_sign_word(fx, value, &|fx, bits, mask| fx.bcx.ins().band_imm(bits, !mask)),
+    }
+}
+
+pub(crate) fn fcopysign(fx: &mut FunctionCx<'_, '_, '_>, lhs: Value, rhs: Value) -> Value {
+    match fx.bcx.func.dfg.value_type(lhs) {
+        types::F32 | types::F64 => fx.bcx.ins().fcopysign(lhs, rhs),
+        _ => {
+            let (int_ty, _) = sign_bit(fx, rhs);
+            let rhs_bits = codegen_bitcast(fx, int_ty, rhs);
+            let rhs_sign_word =
+                if int_ty == types::I128 { fx.bcx.ins().isplit(rhs_bits).1 } else { rhs_bits };
+            let abs = fabs(fx, lhs);
+            map_sign_word(fx, abs, &|fx, bits, mask| {
+                let sign = fx.bcx.ins().band_imm(rhs_sign_word, mask);
+                fx.bcx.ins().bor(bits, sign)
+            })
+        }
+    }
+}
+
+pub(crate) fn codegen_cast(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    from: Value,
+    from_signed: bool,
+    to_ty: Type,
+    to_signed: bool,
+) -> Value {
+    let from_ty = fx.bcx.func.dfg.value_type(from);
+    if from_ty.is_float() && to_ty.is_float() {
+        let name = match (from_ty, to_ty) {
+            (types::F16, types::F32) => return f16_to_f32(fx, from),
+            (types::F16, types::F64) => {
+                let from = f16_to_f32(fx, from);
+                return fx.bcx.ins().fpromote(types::F64, from);
+            }
+            (types::F32, types::F16) => return f32_to_f16(fx, from),
+            (types::F64, types::F16) => return f64_to_f16(fx, from),
+            (types::F16, types::F128) => "__extendhftf2",
+            (types::F32, types::F128) => "__extendsftf2",
+            (types::F64, types::F128) => "__extenddftf2",
+            (types::F128, types::F16) => "__trunctfhf2",
+            (types::F128, types::F32) => "__trunctfsf2",
+            (types::F128, types::F64) => "__trunctfdf2",
+            _ => unreachable!("{from_ty:?} -> {to_ty:?}"),
+        };
+        fx.lib_call(name, vec![AbiParam::new(from_ty)], vec![AbiParam::new(to_ty)], &[from])[0]
+    } else if from_ty.is_int() && to_ty == types::F16 {
+        // Every integer up to 64 bits rounds to the same `f16` when going through `f32`, except
+        // for values that would overflow `f16` anyway.
+        let res = clif_int_or_float_cast(fx, from, from_signed, types::F32, false);
+        f32_to_f16(fx, res)
+    } else if from_ty == types::F16 && to_ty.is_int() {
+        let from = f16_to_f32(fx, from);
+        clif_int_or_float_cast(fx, from, false, to_ty, to_signed)
+    } else if from_ty.is_int() && to_ty == types::F128 {
+        // ________ss___
+        // __float  sitf: i32 -> f128
+        // __float  ditf: i64 -> f128
+        // __float  titf: i128 -> f128
+        // __floatunsitf: u32 -> f128
+        // __floatunditf: u64 -> f128
+        // __floatuntitf: u128 -> f128
+        let (from, from_ty) = if from_ty.bits() < 32 {
+            (clif_intcast(fx, from, types::I32, from_signed), types::I32)
+        } else {
+            (from, from_ty)
+        };
+        let name = format!(
+            "__float{sign}{size}itf",
+            sign = if from_signed { "" } else { "un" },
+            size = match from_ty {
+                types::I32 => 's',
+                types::I64 => 'd',
+                types::I128 => 't',
+                _ => unreachable!("{from_ty:?}"),
+            },
+        );
+        fx.lib_call(&name, vec![AbiParam::new(from_ty)], vec![AbiParam::new(to_ty)], &[from])[0]
+    } else if from_ty == types::F128 && to_ty.is_int() {
+        // _____sss___
+        // __fix   tfsi: f128 -> i32
+        // __fix   tfdi: f128 -> i64
+        // __fix   tfti: f128 -> i128
+        // __fixunstfsi: f128 -> u32
+        // __fixunstfdi: f128 -> u64
+        // __fixunstfti: f128 -> u128
+        //
+        // The compiler-builtins implementations already saturate and return 0 for NaN.
+        let ret_ty = if to_ty.bits() < 32 { types::I32 } else { to_ty };
+        let name = format!(
+            "__fix{sign}tf{size}i",
+            sign = if to_signed { "" } else { "uns" },
+            size = match ret_ty {
+                types::I32 => 's',
+                types::I64 => 'd',
+                types::I128 => 't',
+                _ => unreachable!("{ret_ty:?}"),
+            },
+        );
+        let ret = fx
+            .lib_call(&name, vec![AbiParam::new(from_ty)], vec![AbiParam::new(ret_ty)], &[from])[0];
+        if ret_ty == to_ty {
+            return ret;
+        }
+
+        let (min, max) = match (to_ty, to_signed) {
+            (types::I8, false) => (0, i64::from(u8::MAX)),
+            (types::I16, false) => (0, i64::from(u16::MAX)),
+            (types::I8, true) => (i64::from(i8::MIN as u32), i64::from(i8::MAX as u32)),
+            (types::I16, true) => (i64::from(i16::MIN as u32), i64::from(i16::MAX as u32)),
+            _ => unreachable!(),
+        };
+        let min_val = fx.bcx.ins().iconst(types::I32, min);
+        let max_val = fx.bcx.ins().iconst(types::I32, max);
+
+        let val = if to_signed {
+            let has_underflow = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, ret, min);
+            let has_overflow = fx.bcx.ins().icmp_imm(IntCC::SignedGreaterThan, ret, max);
+            let bottom_capped = fx.bcx.ins().select(has_underflow, min_val, ret);
+            fx.bcx.ins().select(has_overflow, max_val, bottom_capped)
+        } else {
+            let has_overflow = fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThan, ret, max);
+            fx.bcx.ins().select(has_overflow, max_val, ret)
+        };
+        fx.bcx.ins().ireduce(to_ty, val)
+    } else {
+        unreachable!("cast value from {:?} to {:?}", from_ty, to_ty);
+    }
+}
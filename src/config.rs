@@ -5,6 +5,14 @@ fn bool_env_var(key: &str) -> bool {
     env::var(key).as_deref() == Ok("1")
 }
 
+fn list_env_var(key: &str) -> Vec<String> {
+    match env::var(key) {
+        Ok(list) => list.split(':').filter(|item| !item.is_empty()).map(str::to_owned).collect(),
+        Err(env::VarError::NotPresent) => vec![],
+        Err(env::VarError::NotUnicode(s)) => panic!("{key} not unicode: {:?}", s),
+    }
+}
+
 /// The mode to use for compilation.
 #[derive(Copy, Clone, Debug)]
 pub enum CodegenMode {
@@ -39,9 +47,43 @@ pub struct BackendConfig {
 
     /// When JIT mode is enable pass these arguments to the program.
     ///
-    /// Defaults to the value of `CG_CLIF_JIT_ARGS`.
+    /// Defaults to the value of `CG_CLIF_JIT_ARGS`. Can be extended with the lines of a file using
+    /// `-Cllvm-args=jit_args_file=...`.
     pub jit_args: Vec<String>,
 
+    /// When JIT mode is enabled set (`Some`) or remove (`None`) these environment variables
+    /// before running the program. As the jitted program runs inside the rustc process this is
+    /// the only way to give it a different environment than rustc itself.
+    ///
+    /// Can be set using `-Cllvm-args=jit_env=NAME=VALUE` and `-Cllvm-args=jit_env_remove=NAME`,
+    /// which can both be passed multiple times.
+    pub jit_env: Vec<(String, Option<String>)>,
+
+    /// When JIT mode is enabled write a perf jitdump file to the temp dir describing every jitted
+    /// function, such that `perf inject --jit` can attribute samples to them.
+    ///
+    /// Defaults to true when the `CG_CLIF_JIT_PERF_DUMP` env var is set to 1 or false otherwise.
+    /// Can be set using `-Cllvm-args=jit_perf_dump=...`.
+    pub jit_perf_dump: bool,
+
+    /// When JIT mode is enabled search these directories for the libraries in [`jit_preload`].
+    ///
+    /// Defaults to the `:` separated list in `CG_CLIF_JIT_LIB_PATH`. Can be extended using
+    /// `-Cllvm-args=jit_lib_path=...`, which can be passed multiple times.
+    ///
+    /// [`jit_preload`]: BackendConfig::jit_preload
+    pub jit_lib_paths: Vec<String>,
+
+    /// When JIT mode is enabled load these dynamic libraries before running the program and
+    /// resolve otherwise undefined symbols from them. Either a path or a library name like `z`,
+    /// which is searched for as `libz.so` (or the platform equivalent) in [`jit_lib_paths`].
+    ///
+    /// Defaults to the `:` separated list in `CG_CLIF_JIT_PRELOAD`. Can be extended using
+    /// `-Cllvm-args=jit_preload=...`, which can be passed multiple times.
+    ///
+    /// [`jit_lib_paths`]: BackendConfig::jit_lib_paths
+    pub jit_preload: Vec<String>,
+
     /// Enable the Cranelift ir verifier for all compilation passes. If not set it will only run
     /// once before passing the clif ir to Cranelift for compilation.
     ///
@@ -72,6 +114,10 @@ fn default() -> Self {
                     }
                 }
             },
+            jit_env: vec![],
+            jit_perf_dump: bool_env_var("CG_CLIF_JIT_PERF_DUMP"),
+            jit_lib_paths: list_env_var("CG_CLIF_JIT_LIB_PATH"),
+            jit_preload: list_env_var("CG_CLIF_JIT_PRELOAD"),
             enable_verifier: cfg!(debug_assertions) || bool_env_var("CG_CLIF_ENABLE_VERIFIER"),
             disable_incr_cache: bool_env_var("CG_CLIF_DISABLE_INCR_CACHE"),
         }
@@ -95,6 +141,22 @@ fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
             if let Some((name, value)) = opt.split_once('=') {
                 match name {
                     "mode" => config.codegen_mode = value.parse()?,
+                    "jit_args_file" => {
+                        let args = std::fs::read_to_string(value).map_err(|err| {
+                            format!("failed to read jit args file `{}`: {}", value, err)
+                        })?;
+                        config.jit_args.extend(args.lines().map(|arg| arg.to_owned()));
+                    }
+                    "jit_env" => match value.split_once('=') {
+                        Some((var, val)) => {
+                            config.jit_env.push((var.to_owned(), Some(val.to_owned())))
+                        }
+                        None => return Err(format!("Invalid jit_env value `{}`", value)),
+                    },
+                    "jit_env_remove" => config.jit_env.push((value.to_owned(), None)),
+                    "jit_perf_dump" => config.jit_perf_dump = parse_bool(name, value)?,
+                    "jit_lib_path" => config.jit_lib_paths.push(value.to_owned()),
+                    "jit_preload" => config.jit_preload.push(value.to_owned()),
                     "enable_verifier" => config.enable_verifier = parse_bool(name, value)?,
                     "disable_incr_cache" => config.disable_incr_cache = parse_bool(name, value)?,
                     _ => return Err(format!("Unknown option `{}`", name)),
@@ -22,7 +22,7 @@ pub(super) fn address_for_data(data_id: DataId) -> Address {
 }
 
 impl DebugContext {
-    pub(crate) fn emit(&mut self, product: &mut ObjectProduct) {
+    fn write_sections(&mut self) -> Sections<WriterRelocate> {
         let unit_range_list_id = self.dwarf.unit.ranges.add(self.unit_range_list.clone());
         let root = self.dwarf.unit.root();
         let root = self.dwarf.unit.get_mut(root);
@@ -30,6 +30,11 @@ pub(crate) fn emit(&mut self, product: &mut ObjectProduct) {
 
         let mut sections = Sections::new(WriterRelocate::new(self.endian));
         self.dwarf.write(&mut sections).unwrap();
+        sections
+    }
+
+    pub(crate) fn emit(&mut self, product: &mut ObjectProduct) {
+        let mut sections = self.write_sections();
 
         let mut section_map = FxHashMap::default();
         let _: Result<()> = sections.for_each_mut(|id, section| {
@@ -49,6 +54,26 @@ pub(crate) fn emit(&mut self, product: &mut ObjectProduct) {
             Ok(())
         });
     }
+
+    /// Write the debuginfo into sections with all addresses resolved for use by the GDB JIT
+    /// interface. All functions referenced by the debuginfo must have been finalized.
+    #[cfg(feature = "jit")]
+    pub(crate) fn emit_for_jit(
+        &mut self,
+        jit_module: &cranelift_jit::JITModule,
+    ) -> Vec<(SectionId, Vec<u8>)> {
+        let mut sections = self.write_sections();
+
+        let mut jit_sections = vec![];
+        let _: Result<()> = sections.for_each_mut(|id, section| {
+            if !section.writer.slice().is_empty() {
+                let section = std::mem::replace(section, WriterRelocate::new(self.endian));
+                jit_sections.push((id, section.relocate_for_jit(jit_module)));
+            }
+            Ok(())
+        });
+        jit_sections
+    }
 }
 
 #[derive(Clone)]
@@ -79,15 +104,24 @@ pub(super) fn new(endian: RunTimeEndian) -> Self {
     }
 
     /// Perform the collected relocations to be usable for JIT usage.
-    #[cfg(all(feature = "jit", not(windows)))]
+    #[cfg(feature = "jit")]
     pub(super) fn relocate_for_jit(mut self, jit_module: &cranelift_jit::JITModule) -> Vec<u8> {
         for reloc in self.relocs.drain(..) {
             match reloc.name {
-                super::DebugRelocName::Section(_) => unreachable!(),
+                super::DebugRelocName::Section(_) => {
+                    // Offsets into other debug sections are relative to the start of that
+                    // section, so they don't need to be adjusted.
+                    self.writer
+                        .write_udata_at(reloc.offset as usize, reloc.addend as u64, reloc.size)
+                        .unwrap();
+                }
                 super::DebugRelocName::Symbol(sym) => {
-                    let addr = jit_module.get_finalized_function(
-                        cranelift_module::FuncId::from_u32(sym.try_into().unwrap()),
-                    );
+                    let sym = u32::try_from(sym).unwrap();
+                    let addr = if sym & 1 << 31 == 0 {
+                        jit_module.get_finalized_function(FuncId::from_u32(sym))
+                    } else {
+                        jit_module.get_finalized_data(DataId::from_u32(sym & !(1 << 31))).0
+                    };
                     let val = (addr as u64 as i64 + reloc.addend) as u64;
                     self.writer.write_udata_at(reloc.offset as usize, val, reloc.size).unwrap();
                 }
@@ -0,0 +1,247 @@
+//! Registration of jitted functions with the GDB JIT interface, which is also understood by LLDB.
+//!
+//! For every batch of finalized functions an in-memory ELF file is created containing a symbol
+//! for each function and, if debuginfo is enabled, the DWARF sections describing them. This allows
+//! debuggers to show function names and source locations in backtraces and to set breakpoints on
+//! jitted functions.
+//!
+//! See <https://sourceware.org/gdb/current/onlinedocs/gdb.html/JIT-Interface.html>
+
+use std::sync::Mutex;
+
+use cranelift_codegen::ir::Endianness;
+use cranelift_jit::JITModule;
+use gimli::SectionId;
+use object::elf;
+use object::write::elf::{FileHeader, SectionHeader, Sym, Writer};
+
+use crate::prelude::*;
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+// Debuggers look these two symbols up by name, so they must not be mangled. LLVM defines them too,
+// so these are only used when the process doesn't already export them. See `jit_debug_interface`.
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: std::ptr::null_mut(),
+    first_entry: std::ptr::null_mut(),
+};
+
+/// Debuggers put a breakpoint on this function to get notified of newly registered code.
+#[no_mangle]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    // Prevent the call from being optimized away.
+    unsafe { std::arch::asm!("", options(nomem, nostack, preserves_flags)) };
+}
+
+/// Serializes access to the JIT descriptor.
+static DESCRIPTOR_LOCK: Mutex<()> = Mutex::new(());
+
+/// Get the JIT descriptor and registration function that debuggers watch.
+///
+/// rustc links in LLVM, which defines its own copies of both symbols. Which definition a debugger
+/// picks up depends on the symbol lookup order, so if the process already exports them, register
+/// through those instead of the ones defined by cg_clif.
+fn jit_debug_interface() -> (*mut JitDescriptor, extern "C" fn()) {
+    #[cfg(unix)]
+    {
+        let this = libloading::os::unix::Library::this();
+        if let (Ok(descriptor), Ok(register_code)) = unsafe {
+            (
+                this.get::<*mut JitDescriptor>(b"__jit_debug_descriptor"),
+                this.get::<extern "C" fn()>(b"__jit_debug_register_code"),
+            )
+        } {
+            return (*descriptor, *register_code);
+        }
+    }
+
+    (std::ptr::addr_of_mut!(__jit_debug_descriptor), __jit_debug_register_code)
+}
+
+/// Register the given functions and their code size with an attached debugger together with the
+/// debug sections describing them. Must only be called after the functions have been finalized.
+pub(crate) unsafe fn register_gdb_jit_functions(
+    jit_module: &JITModule,
+    functions: &[(FuncId, usize)],
+    debug_sections: &[(SectionId, Vec<u8>)],
+) {
+    if functions.is_empty() {
+        return;
+    }
+
+    // Leaked as debuggers may read the symbol file at any time and jitted code is never freed.
+    let symfile =
+        Box::leak(create_symfile(jit_module, functions, debug_sections).into_boxed_slice());
+    let entry = Box::leak(Box::new(JitCodeEntry {
+        next_entry: std::ptr::null_mut(),
+        prev_entry: std::ptr::null_mut(),
+        symfile_addr: symfile.as_ptr(),
+        symfile_size: symfile.len() as u64,
+    }));
+
+    let (descriptor, register_code) = jit_debug_interface();
+
+    let _guard = DESCRIPTOR_LOCK.lock().unwrap();
+    unsafe {
+        let descriptor = &mut *descriptor;
+        entry.next_entry = descriptor.first_entry;
+        if !descriptor.first_entry.is_null() {
+            (*descriptor.first_entry).prev_entry = entry;
+        }
+        descriptor.first_entry = entry;
+        descriptor.relevant_entry = entry;
+        descriptor.action_flag = JIT_REGISTER_FN;
+        register_code();
+        descriptor.action_flag = JIT_NOACTION;
+    }
+}
+
+/// Create an ELF file with an allocated but empty `.text` section covering all given functions,
+/// a symbol for each function and the given debug sections.
+fn create_symfile(
+    jit_module: &JITModule,
+    functions: &[(FuncId, usize)],
+    debug_sections: &[(SectionId, Vec<u8>)],
+) -> Vec<u8> {
+    let isa = jit_module.isa();
+    let endian = match isa.endianness() {
+        Endianness::Little => object::Endianness::Little,
+        Endianness::Big => object::Endianness::Big,
+    };
+    let is_64 = isa.pointer_bits() == 64;
+    let e_machine = match isa.triple().architecture {
+        target_lexicon::Architecture::X86_64 => elf::EM_X86_64,
+        target_lexicon::Architecture::Aarch64(_) => elf::EM_AARCH64,
+        target_lexicon::Architecture::Riscv64(_) => elf::EM_RISCV,
+        target_lexicon::Architecture::S390x => elf::EM_S390,
+        _ => elf::EM_NONE,
+    };
+
+    let functions = functions
+        .iter()
+        .map(|&(func_id, size)| {
+            let name = jit_module.declarations().get_function_decl(func_id).linkage_name(func_id);
+            (name, jit_module.get_finalized_function(func_id) as u64, size as u64)
+        })
+        .collect::<Vec<_>>();
+    let text_start = functions.iter().map(|&(_, addr, _)| addr).min().unwrap();
+    let text_end = functions.iter().map(|&(_, addr, size)| addr + size).max().unwrap();
+
+    let mut buffer = Vec::new();
+    let mut writer = Writer::new(endian, is_64, &mut buffer);
+
+    writer.reserve_file_header();
+    let text_name = writer.add_section_name(b".text");
+    let text_index = writer.reserve_section_index();
+    let debug_sections = debug_sections
+        .iter()
+        .map(|(id, data)| {
+            let name = writer.add_section_name(id.name().as_bytes());
+            writer.reserve_section_index();
+            let offset = writer.reserve(data.len(), 1);
+            (name, offset, data)
+        })
+        .collect::<Vec<_>>();
+    writer.reserve_null_symbol_index();
+    let symbols = functions
+        .iter()
+        .map(|(name, _, _)| {
+            let name = writer.add_string(name.as_bytes());
+            writer.reserve_symbol_index(Some(text_index));
+            name
+        })
+        .collect::<Vec<_>>();
+    writer.reserve_symtab_section_index();
+    writer.reserve_symtab();
+    writer.reserve_strtab_section_index();
+    writer.reserve_strtab();
+    writer.reserve_shstrtab_section_index();
+    writer.reserve_shstrtab();
+    writer.reserve_section_headers();
+
+    writer
+        .write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_EXEC,
+            e_machine,
+            e_entry: 0,
+            e_flags: 0,
+        })
+        .unwrap();
+
+    for (_, _, data) in &debug_sections {
+        writer.write(data);
+    }
+
+    writer.write_null_symbol();
+    for (name, (_, addr, size)) in symbols.into_iter().zip(&functions) {
+        writer.write_symbol(&Sym {
+            name: Some(name),
+            section: Some(text_index),
+            st_info: (elf::STB_GLOBAL << 4) | elf::STT_FUNC,
+            st_other: elf::STV_DEFAULT,
+            st_shndx: 0,
+            st_value: *addr,
+            st_size: *size,
+        });
+    }
+    writer.write_strtab();
+    writer.write_shstrtab();
+
+    writer.write_null_section_header();
+    writer.write_section_header(&SectionHeader {
+        name: Some(text_name),
+        sh_type: elf::SHT_NOBITS,
+        sh_flags: (elf::SHF_ALLOC | elf::SHF_EXECINSTR).into(),
+        sh_addr: text_start,
+        sh_offset: 0,
+        sh_size: text_end - text_start,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: 1,
+        sh_entsize: 0,
+    });
+    for (name, offset, data) in &debug_sections {
+        writer.write_section_header(&SectionHeader {
+            name: Some(*name),
+            sh_type: elf::SHT_PROGBITS,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: *offset as u64,
+            sh_size: data.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: 0,
+        });
+    }
+    // All symbols are global, so only the null symbol is local.
+    writer.write_symtab_section_header(1);
+    writer.write_strtab_section_header();
+    writer.write_shstrtab_section_header();
+
+    buffer
+}
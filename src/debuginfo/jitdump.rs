@@ -0,0 +1,185 @@
+//! Support for perf's jitdump format, which allows `perf inject --jit` to attribute samples in
+//! jitted code to the functions they belong to.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/tools/perf/Documentation/jitdump-specification.txt>
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_jit::JITModule;
+
+use crate::prelude::*;
+
+const JITDUMP_MAGIC: u32 = 0x4A695444;
+const JITDUMP_VERSION: u32 = 1;
+const JIT_CODE_LOAD: u32 = 0;
+
+pub(crate) struct JitDump {
+    file: BufWriter<File>,
+    code_index: u64,
+}
+
+impl JitDump {
+    pub(crate) fn new(isa: &dyn TargetIsa) -> std::io::Result<Self> {
+        let pid = std::process::id();
+        let path = std::env::temp_dir().join(format!("jit-{pid}.dump"));
+        let file = File::create(&path)?;
+
+        // perf discovers the jitdump file through the executable mmap event recorded when the
+        // file gets mapped. The mapping itself is never accessed.
+        #[cfg(unix)]
+        unsafe {
+            use std::os::fd::AsRawFd;
+
+            const PROT_READ: i32 = 1;
+            const PROT_EXEC: i32 = 4;
+            const MAP_PRIVATE: i32 = 2;
+
+            extern "C" {
+                fn mmap(
+                    addr: *mut u8,
+                    len: usize,
+                    prot: i32,
+                    flags: i32,
+                    fd: i32,
+                    offset: i64,
+                ) -> *mut u8;
+            }
+
+            let addr = mmap(
+                std::ptr::null_mut(),
+                4096,
+                PROT_READ | PROT_EXEC,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            );
+            if addr as isize == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        let elf_mach: u32 = match isa.triple().architecture {
+            target_lexicon::Architecture::X86_64 => 62,
+            target_lexicon::Architecture::Aarch64(_) => 183,
+            target_lexicon::Architecture::Riscv64(_) => 243,
+            target_lexicon::Architecture::S390x => 22,
+            _ => 0,
+        };
+
+        let mut file = BufWriter::new(file);
+        file.write_all(&JITDUMP_MAGIC.to_ne_bytes())?;
+        file.write_all(&JITDUMP_VERSION.to_ne_bytes())?;
+        file.write_all(&40u32.to_ne_bytes())?; // total_size
+        file.write_all(&elf_mach.to_ne_bytes())?;
+        file.write_all(&0u32.to_ne_bytes())?; // pad1
+        file.write_all(&pid.to_ne_bytes())?;
+        file.write_all(&timestamp().to_ne_bytes())?;
+        file.write_all(&0u64.to_ne_bytes())?; // flags
+        file.flush()?;
+
+        Ok(JitDump { file, code_index: 0 })
+    }
+
+    /// Write a code load record for each of the given functions and their code size. Must only be
+    /// called after the functions have been finalized.
+    pub(crate) fn write_functions(
+        &mut self,
+        jit_module: &JITModule,
+        functions: &[(FuncId, usize)],
+    ) -> std::io::Result<()> {
+        let pid = std::process::id();
+        let tid = thread_id();
+        for &(func_id, code_size) in functions {
+            let name = jit_module.declarations().get_function_decl(func_id).linkage_name(func_id);
+            let code_addr = jit_module.get_finalized_function(func_id);
+            let code = unsafe { std::slice::from_raw_parts(code_addr, code_size) };
+
+            // record header + pid, tid, vma, code_addr, code_size, code_index
+            let total_size = 16 + 4 + 4 + 8 * 4 + name.len() + 1 + code_size;
+
+            self.file.write_all(&JIT_CODE_LOAD.to_ne_bytes())?;
+            self.file.write_all(&u32::try_from(total_size).unwrap().to_ne_bytes())?;
+            self.file.write_all(&timestamp().to_ne_bytes())?;
+            self.file.write_all(&pid.to_ne_bytes())?;
+            self.file.write_all(&tid.to_ne_bytes())?;
+            self.file.write_all(&(code_addr as u64).to_ne_bytes())?; // vma
+            self.file.write_all(&(code_addr as u64).to_ne_bytes())?;
+            self.file.write_all(&(code_size as u64).to_ne_bytes())?;
+            self.file.write_all(&self.code_index.to_ne_bytes())?;
+            self.file.write_all(name.as_bytes())?;
+            self.file.write_all(&[0])?;
+            self.file.write_all(code)?;
+
+            self.code_index += 1;
+        }
+
+        self.file.flush()
+    }
+}
+
+/// The id of the current thread as used by perf. This is the thread which jitted the functions.
+#[cfg(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64",
+        target_arch = "s390x"
+    )
+))]
+fn thread_id() -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    const SYS_GETTID: std::ffi::c_long = 186;
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    const SYS_GETTID: std::ffi::c_long = 178;
+    #[cfg(target_arch = "s390x")]
+    const SYS_GETTID: std::ffi::c_long = 236;
+
+    // glibc only provides a `gettid` wrapper since 2.30, so use the raw syscall instead.
+    extern "C" {
+        fn syscall(num: std::ffi::c_long, ...) -> std::ffi::c_long;
+    }
+
+    unsafe { syscall(SYS_GETTID) as u32 }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64",
+        target_arch = "s390x"
+    )
+)))]
+fn thread_id() -> u32 {
+    std::process::id()
+}
+
+/// perf uses `CLOCK_MONOTONIC` by default to timestamp samples. Jitdump records have to use the
+/// same clock for `perf inject` to match them up.
+#[cfg(target_os = "linux")]
+fn timestamp() -> u64 {
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: std::ffi::c_long,
+        tv_nsec: std::ffi::c_long,
+    }
+
+    const CLOCK_MONOTONIC: i32 = 1;
+
+    extern "C" {
+        fn clock_gettime(clock_id: i32, tp: *mut Timespec) -> i32;
+    }
+
+    let mut ts = Timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(target_os = "linux"))]
+fn timestamp() -> u64 {
+    0
+}
@@ -1,6 +1,10 @@
 //! Handling of everything related to debuginfo.
 
 mod emit;
+#[cfg(feature = "jit")]
+mod gdb_jit;
+#[cfg(feature = "jit")]
+mod jitdump;
 mod line_info;
 mod object;
 mod types;
@@ -23,6 +27,10 @@
 use rustc_target::callconv::FnAbi;
 
 pub(crate) use self::emit::{DebugReloc, DebugRelocName};
+#[cfg(feature = "jit")]
+pub(crate) use self::gdb_jit::register_gdb_jit_functions;
+#[cfg(feature = "jit")]
+pub(crate) use self::jitdump::JitDump;
 pub(crate) use self::types::TypeDebugContext;
 pub(crate) use self::unwind::UnwindContext;
 use crate::debuginfo::emit::{address_for_data, address_for_func};
@@ -3,10 +3,11 @@
 
 use std::fs::{self, File};
 use std::io::BufWriter;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::sync::Arc;
 use std::thread::JoinHandle;
 
+use cranelift_codegen::isa::OwnedTargetIsa;
 use cranelift_object::{ObjectBuilder, ObjectModule};
 use rustc_codegen_ssa::assert_module_sources::CguReuse;
 use rustc_codegen_ssa::back::link::ensure_removed;
@@ -28,7 +29,7 @@
 use crate::BackendConfig;
 use crate::concurrency_limiter::{ConcurrencyLimiter, ConcurrencyLimiterToken};
 use crate::debuginfo::TypeDebugContext;
-use crate::global_asm::GlobalAsmConfig;
+use crate::global_asm::{GlobalAsmConfig, GlobalAsmError};
 use crate::prelude::*;
 use crate::unwind_module::UnwindModule;
 
@@ -38,9 +39,26 @@ struct ModuleCodegenResult {
     existing_work_product: Option<(WorkProductId, WorkProduct)>,
 }
 
+enum ModuleCodegenError {
+    Message(String),
+    GlobalAsm(GlobalAsmError),
+}
+
+impl From<String> for ModuleCodegenError {
+    fn from(err: String) -> Self {
+        ModuleCodegenError::Message(err)
+    }
+}
+
+impl From<GlobalAsmError> for ModuleCodegenError {
+    fn from(err: GlobalAsmError) -> Self {
+        ModuleCodegenError::GlobalAsm(err)
+    }
+}
+
 enum OngoingModuleCodegen {
     Sync(Result<ModuleCodegenResult, String>),
-    Async(JoinHandle<Result<ModuleCodegenResult, String>>),
+    Async(JoinHandle<Result<ModuleCodegenResult, ModuleCodegenError>>),
 }
 
 impl<HCX> HashStable<HCX> for OngoingModuleCodegen {
@@ -70,7 +88,9 @@ pub(crate) fn join(
 
         for module_codegen in self.modules {
             let module_codegen_result = match module_codegen {
-                OngoingModuleCodegen::Sync(module_codegen_result) => module_codegen_result,
+                OngoingModuleCodegen::Sync(module_codegen_result) => {
+                    module_codegen_result.map_err(ModuleCodegenError::Message)
+                }
                 OngoingModuleCodegen::Async(join_handle) => match join_handle.join() {
                     Ok(module_codegen_result) => module_codegen_result,
                     Err(panic) => std::panic::resume_unwind(panic),
@@ -79,7 +99,8 @@ pub(crate) fn join(
 
             let module_codegen_result = match module_codegen_result {
                 Ok(module_codegen_result) => module_codegen_result,
-                Err(err) => sess.dcx().fatal(err),
+                Err(ModuleCodegenError::Message(err)) => sess.dcx().fatal(err),
+                Err(ModuleCodegenError::GlobalAsm(err)) => err.emit(sess.dcx()),
             };
             let ModuleCodegenResult { module_regular, module_global_asm, existing_work_product } =
                 module_codegen_result;
@@ -322,13 +343,7 @@ fn produce_final_output_artifacts(
     // These are used in linking steps and will be cleaned up afterward.
 }
 
-fn make_module(
-    sess: &Session,
-    backend_config: &BackendConfig,
-    name: String,
-) -> UnwindModule<ObjectModule> {
-    let isa = crate::build_isa(sess, backend_config);
-
+fn make_module(sess: &Session, isa: OwnedTargetIsa, name: String) -> UnwindModule<ObjectModule> {
     let mut builder =
         ObjectBuilder::new(isa, name + ".o", cranelift_module::default_libcall_names()).unwrap();
     // Unlike cg_llvm, cg_clif defaults to disabling -Zfunction-sections. For cg_llvm binary size
@@ -344,37 +359,19 @@ fn emit_cgu(
     name: String,
     module: UnwindModule<ObjectModule>,
     debug: Option<DebugContext>,
-    global_asm_object_file: Option<PathBuf>,
     producer: &str,
 ) -> Result<ModuleCodegenResult, String> {
-    let mut product = module.finish();
+    let mut product =
+        prof.generic_activity_with_arg("finalize module", &*name).run(|| module.finish());
 
     if let Some(mut debug) = debug {
-        debug.emit(&mut product);
+        prof.generic_activity_with_arg("emit debug info", &*name).run(|| debug.emit(&mut product));
     }
 
-    let module_regular = emit_module(
-        output_filenames,
-        prof,
-        product.object,
-        ModuleKind::Regular,
-        name.clone(),
-        producer,
-    )?;
+    let module_regular =
+        emit_module(output_filenames, prof, product.object, ModuleKind::Regular, name, producer)?;
 
-    Ok(ModuleCodegenResult {
-        module_regular,
-        module_global_asm: global_asm_object_file.map(|global_asm_object_file| CompiledModule {
-            name: format!("{name}.asm"),
-            kind: ModuleKind::Regular,
-            object: Some(global_asm_object_file),
-            dwarf_object: None,
-            bytecode: None,
-            assembly: None,
-            llvm_ir: None,
-        }),
-        existing_work_product: None,
-    })
+    Ok(ModuleCodegenResult { module_regular, module_global_asm: None, existing_work_product: None })
 }
 
 fn emit_module(
@@ -404,7 +401,10 @@ fn emit_module(
     };
 
     let mut file = BufWriter::new(file);
-    if let Err(err) = object.write_stream(&mut file) {
+    if let Err(err) = prof
+        .generic_activity_with_arg("serialize object file", &*name)
+        .run(|| object.write_stream(&mut file))
+    {
         return Err(format!("error writing object file: {}", err));
     }
     let file = match file.into_inner() {
@@ -495,12 +495,25 @@ fn module_codegen(
         ConcurrencyLimiterToken,
     ),
 ) -> OngoingModuleCodegen {
-    let (cgu_name, mut cx, mut module, codegened_functions) =
-        tcx.prof.generic_activity_with_arg("codegen cgu", cgu_name.as_str()).run(|| {
-            let cgu = tcx.codegen_unit(cgu_name);
+    let cgu = tcx.codegen_unit(cgu_name);
+    // Include the size estimate in the -Ztime-passes output to make it easier to see how well it
+    // predicts the actual codegen time.
+    let (cgu_name, mut cx, mut module, isa, codegened_functions) = tcx
+        .prof
+        .verbose_generic_activity_with_arg(
+            "codegen cgu",
+            format!("{cgu_name}, size estimate {}", cgu.size_estimate()),
+        )
+        .run(|| {
             let mono_items = cgu.items_in_deterministic_order(tcx);
 
-            let mut module = make_module(tcx.sess, &backend_config, cgu_name.as_str().to_string());
+            // `va_start` needs the frame pointer to find the stack arguments.
+            let isa = crate::build_isa(
+                tcx.sess,
+                &backend_config,
+                crate::abi::defines_c_variadic_fn(tcx, mono_items.iter().map(|(item, _)| item)),
+            );
+            let mut module = make_module(tcx.sess, isa.clone(), cgu_name.as_str().to_string());
 
             let mut cx = crate::CodegenCx::new(
                 tcx,
@@ -535,7 +548,8 @@ fn module_codegen(
                     MonoItem::GlobalAsm(item_id) => {
                         crate::global_asm::codegen_global_asm_item(
                             tcx,
-                            &mut cx.global_asm,
+                            &mut cx,
+                            &mut module,
                             item_id,
                         );
                     }
@@ -545,42 +559,95 @@ fn module_codegen(
 
             let cgu_name = cgu.name().as_str().to_owned();
 
-            (cgu_name, cx, module, codegened_functions)
+            (cgu_name, cx, module, isa, codegened_functions)
         });
 
     let producer = crate::debuginfo::producer(tcx.sess);
 
     OngoingModuleCodegen::Async(std::thread::spawn(move || {
+        // All global and inline asm is known at this point. Start assembling it right away such
+        // that the assembler runs in parallel with compiling the functions and writing the
+        // object file.
+        let pending_global_asm = crate::global_asm::spawn_global_asm_assembler(
+            &global_asm_config,
+            &cgu_name,
+            &cx.global_asm,
+            &cx.global_asm_spans,
+        );
+
         cx.profiler.clone().generic_activity_with_arg("compile functions", &*cgu_name).run(|| {
-            cranelift_codegen::timing::set_thread_profiler(Box::new(super::MeasuremeProfiler(
-                cx.profiler.clone(),
-            )));
+            // Lower functions to machine code on a separate thread while this thread copies the
+            // machine code of already lowered functions into the object file and generates the
+            // debuginfo for them. The channel is bounded to limit how many compiled functions are
+            // kept in memory at the same time.
+            //
+            // Both threads share the single concurrency limiter token of this codegen unit rather
+            // than acquiring a second one. Defining a function and its debuginfo is cheap compared
+            // to lowering it, so this thread spends most of its time blocked on the channel and
+            // the pair keeps roughly one core busy.
+            let profiler = cx.profiler.clone();
+            let should_write_ir = cx.should_write_ir;
+            std::thread::scope(|scope| {
+                let (sender, receiver) = std::sync::mpsc::sync_channel(16);
+                let lower_thread = scope.spawn(move || {
+                    cranelift_codegen::timing::set_thread_profiler(Box::new(
+                        super::MeasuremeProfiler(profiler.clone()),
+                    ));
+
+                    for codegened_func in codegened_functions {
+                        let compiled_func = crate::base::lower_fn(
+                            &profiler,
+                            &*isa,
+                            should_write_ir,
+                            codegened_func,
+                        );
+                        if sender.send(compiled_func).is_err() {
+                            // The receiving side panicked.
+                            break;
+                        }
+                    }
+                });
 
-            let mut cached_context = Context::new();
-            for codegened_func in codegened_functions {
-                crate::base::compile_fn(&mut cx, &mut cached_context, &mut module, codegened_func);
-            }
-        });
+                for compiled_func in receiver {
+                    crate::base::define_compiled_fn(&mut cx, &mut module, compiled_func);
+                }
 
-        let global_asm_object_file =
-            cx.profiler.generic_activity_with_arg("compile assembly", &*cgu_name).run(|| {
-                crate::global_asm::compile_global_asm(&global_asm_config, &cgu_name, &cx.global_asm)
-            })?;
+                if let Err(panic) = lower_thread.join() {
+                    std::panic::resume_unwind(panic);
+                }
+            });
+        });
 
         let codegen_result =
             cx.profiler.generic_activity_with_arg("write object file", &*cgu_name).run(|| {
                 emit_cgu(
                     &global_asm_config.output_filenames,
                     &cx.profiler,
-                    cgu_name,
+                    cgu_name.clone(),
                     module,
                     cx.debug_context,
-                    global_asm_object_file,
                     &producer,
                 )
             });
+
+        let global_asm_object_file =
+            cx.profiler.generic_activity_with_arg("compile assembly", &*cgu_name).run(|| {
+                pending_global_asm.map(|pending_global_asm| pending_global_asm.wait()).transpose()
+            });
+
         std::mem::drop(token);
-        codegen_result
+        let mut codegen_result = codegen_result?;
+        codegen_result.module_global_asm =
+            global_asm_object_file?.map(|global_asm_object_file| CompiledModule {
+                name: format!("{cgu_name}.asm"),
+                kind: ModuleKind::Regular,
+                object: Some(global_asm_object_file),
+                dwarf_object: None,
+                bytecode: None,
+                assembly: None,
+                llvm_ir: None,
+            });
+        Ok(codegen_result)
     }))
 }
 
@@ -632,13 +699,18 @@ pub(crate) fn run_aot(
 
     let global_asm_config = Arc::new(crate::global_asm::GlobalAsmConfig::new(tcx));
 
-    let (todo_cgus, done_cgus) =
+    let (mut todo_cgus, done_cgus) =
         cgus.into_iter().enumerate().partition::<Vec<_>, _>(|&(i, _)| match cgu_reuse[i] {
             _ if backend_config.disable_incr_cache => true,
             CguReuse::No => true,
             CguReuse::PreLto | CguReuse::PostLto => false,
         });
 
+    // Start codegen of the biggest codegen units first. Otherwise a big codegen unit which
+    // happens to be near the end of the list may be left running alone at the end of the build.
+    // This matches what cg_ssa does for cg_llvm.
+    todo_cgus.sort_by_cached_key(|&(_, cgu)| std::cmp::Reverse(cgu.size_estimate()));
+
     let concurrency_limiter = IntoDynSyncSend(ConcurrencyLimiter::new(tcx.sess, todo_cgus.len()));
 
     let modules = tcx.sess.time("codegen mono items", || {
@@ -667,8 +739,14 @@ pub(crate) fn run_aot(
         modules
     });
 
-    let mut allocator_module = make_module(tcx.sess, &backend_config, "allocator_shim".to_string());
-    let created_alloc_shim = crate::allocator::codegen(tcx, &mut allocator_module);
+    let mut allocator_module = make_module(
+        tcx.sess,
+        crate::build_isa(tcx.sess, &backend_config, false),
+        "allocator_shim".to_string(),
+    );
+    let created_alloc_shim = tcx
+        .sess
+        .time("codegen allocator shim", || crate::allocator::codegen(tcx, &mut allocator_module));
 
     let allocator_module = if created_alloc_shim {
         let product = allocator_module.finish();
@@ -4,16 +4,29 @@
 use std::cell::RefCell;
 use std::ffi::CString;
 use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Mutex, OnceLock, mpsc};
 
+use cranelift_codegen::binemit::Reloc;
+use cranelift_codegen::ir::GlobalValueData;
+use cranelift_codegen::{FinalizedMachReloc, FinalizedRelocTarget};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
 use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::FuncOrDataId;
+use object::{
+    Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget, SectionIndex,
+    SectionKind,
+};
 use rustc_codegen_ssa::CrateInfo;
 use rustc_middle::mir::mono::MonoItem;
 use rustc_session::Session;
+use rustc_session::config::DebugInfo;
 use rustc_span::Symbol;
+use target_lexicon::BinaryFormat;
 
 use crate::debuginfo::TypeDebugContext;
+use crate::global_asm::{GlobalAsmConfig, spawn_global_asm_assembler};
 use crate::prelude::*;
 use crate::unwind_module::UnwindModule;
 use crate::{BackendConfig, CodegenCx, CodegenMode};
@@ -27,6 +40,9 @@ struct JitState {
     static LAZY_JIT_STATE: RefCell<Option<JitState>> = const { RefCell::new(None) };
 }
 
+/// The names of the weak imports of the jitted program. These are allowed to stay unresolved.
+static WEAK_IMPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
 /// The Sender owned by the rustc thread
 static GLOBAL_MESSAGE_SENDER: OnceLock<Mutex<mpsc::Sender<UnsafeMessage>>> = OnceLock::new();
 
@@ -64,18 +80,29 @@ fn create_jit_module(
     tcx: TyCtxt<'_>,
     backend_config: &BackendConfig,
     hotswap: bool,
+    force_frame_pointers: bool,
 ) -> (UnwindModule<JITModule>, CodegenCx) {
     let crate_info = CrateInfo::new(tcx, "dummy_target_cpu".to_string());
 
-    let isa = crate::build_isa(tcx.sess, backend_config);
+    let isa = crate::build_isa(tcx.sess, backend_config, force_frame_pointers);
     let mut jit_builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
     jit_builder.hotswap(hotswap);
     crate::compiler_builtins::register_functions_for_jit(&mut jit_builder);
-    jit_builder.symbol_lookup_fn(dep_symbol_lookup_fn(tcx.sess, crate_info));
+    jit_builder.symbol_lookup_fn(dep_symbol_lookup_fn(tcx.sess, backend_config, crate_info));
     jit_builder.symbol("__clif_jit_fn", clif_jit_fn as *const u8);
-    let mut jit_module = UnwindModule::new(JITModule::new(jit_builder), false);
+    let mut jit_module = UnwindModule::new_jit(JITModule::new(jit_builder));
+    if backend_config.jit_perf_dump {
+        if let Err(err) = jit_module.enable_jit_dump() {
+            tcx.dcx().fatal(format!("failed to create perf jitdump file: {err}"));
+        }
+    }
 
-    let cx = crate::CodegenCx::new(tcx, jit_module.isa(), false, Symbol::intern("dummy_cgu_name"));
+    let cx = crate::CodegenCx::new(
+        tcx,
+        jit_module.isa(),
+        tcx.sess.opts.debuginfo != DebugInfo::None,
+        Symbol::intern("dummy_cgu_name"),
+    );
 
     crate::allocator::codegen(tcx, &mut jit_module);
 
@@ -91,13 +118,6 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
         tcx.dcx().fatal("can't jit non-executable crate");
     }
 
-    let (mut jit_module, mut cx) = create_jit_module(
-        tcx,
-        &backend_config,
-        matches!(backend_config.codegen_mode, CodegenMode::JitLazy),
-    );
-    let mut cached_context = Context::new();
-
     let (_, cgus) = tcx.collect_and_partition_mono_items(());
     let mono_items = cgus
         .iter()
@@ -107,6 +127,15 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
         .into_iter()
         .collect::<Vec<(_, _)>>();
 
+    let (mut jit_module, mut cx) = create_jit_module(
+        tcx,
+        &backend_config,
+        matches!(backend_config.codegen_mode, CodegenMode::JitLazy),
+        // `va_start` needs the frame pointer to find the stack arguments.
+        crate::abi::defines_c_variadic_fn(tcx, mono_items.iter().map(|(item, _)| item)),
+    );
+    let mut cached_context = Context::new();
+
     tcx.sess.time("codegen mono items", || {
         super::predefine_mono_items(tcx, &mut jit_module, &mono_items);
         for (mono_item, _) in mono_items {
@@ -131,22 +160,29 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
                     crate::constant::codegen_static(tcx, &mut jit_module, def_id);
                 }
                 MonoItem::GlobalAsm(item_id) => {
-                    let item = tcx.hir().item(item_id);
-                    tcx.dcx().span_fatal(item.span, "Global asm is not supported in JIT mode");
+                    crate::global_asm::codegen_global_asm_item(
+                        tcx,
+                        &mut cx,
+                        &mut jit_module,
+                        item_id,
+                    );
                 }
             }
         }
     });
 
     if !cx.global_asm.is_empty() {
-        tcx.dcx().fatal("Inline asm is not supported in JIT mode");
+        tcx.sess.time("load global asm", || load_global_asm(tcx, &mut jit_module, &cx));
     }
 
     crate::main_shim::maybe_create_entry_wrapper(tcx, &mut jit_module, true, true);
 
     tcx.dcx().abort_if_errors();
 
-    jit_module.finalize_definitions();
+    tcx.sess.time("finalize jitted definitions", || {
+        record_weak_imports(&jit_module);
+        jit_module.finalize_definitions(tcx.dcx(), cx.debug_context.as_mut());
+    });
 
     println!(
         "Rustc codegen cranelift will JIT run the executable, because -Cllvm-args=mode=jit was passed"
@@ -168,6 +204,11 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
     let start_func_id = jit_module.declare_function("main", Linkage::Import, &start_sig).unwrap();
     let finalized_start: *const u8 = jit_module.module.get_finalized_function(start_func_id);
 
+    let jit_env = backend_config.jit_env.clone();
+    if cfg!(not(unix)) && !jit_env.is_empty() {
+        tcx.dcx().fatal("changing the environment of the jitted program is only supported on unix");
+    }
+
     LAZY_JIT_STATE.with(|lazy_jit_state| {
         let mut lazy_jit_state = lazy_jit_state.borrow_mut();
         assert!(lazy_jit_state.is_none());
@@ -177,6 +218,13 @@ pub(crate) fn run_jit(tcx: TyCtxt<'_>, backend_config: BackendConfig) -> ! {
     let f: extern "C" fn(c_int, *const *const c_char) -> c_int =
         unsafe { ::std::mem::transmute(finalized_start) };
 
+    // The jitted program shares the environment with rustc, so apply the requested changes as
+    // late as possible.
+    #[cfg(unix)]
+    if !jit_env.is_empty() {
+        set_jit_environ(&jit_env);
+    }
+
     let (tx, rx) = mpsc::channel();
     GLOBAL_MESSAGE_SENDER.set(Mutex::new(tx)).unwrap();
 
@@ -280,11 +328,16 @@ fn jit_fn(instance_ptr: *const Instance<'static>, trampoline_ptr: *const u8) ->
 
             jit_module.module.prepare_for_function_redefine(func_id).unwrap();
 
+            // Every lazily jitted function gets its own codegen unit. Give each a unique name to
+            // prevent the names of inline asm wrappers from clashing with those of previously
+            // jitted functions.
+            static LAZY_CGU_INDEX: AtomicUsize = AtomicUsize::new(0);
+            let cgu_name = format!("lazy_jit_{}", LAZY_CGU_INDEX.fetch_add(1, Ordering::Relaxed));
             let mut cx = crate::CodegenCx::new(
                 tcx,
                 jit_module.isa(),
-                false,
-                Symbol::intern("dummy_cgu_name"),
+                tcx.sess.opts.debuginfo != DebugInfo::None,
+                Symbol::intern(&cgu_name),
             );
             codegen_and_compile_fn(
                 tcx,
@@ -295,15 +348,88 @@ fn jit_fn(instance_ptr: *const Instance<'static>, trampoline_ptr: *const u8) ->
                 instance,
             );
 
-            assert!(cx.global_asm.is_empty());
-            jit_module.finalize_definitions();
+            if !cx.global_asm.is_empty() {
+                load_global_asm(tcx, jit_module, &cx);
+            }
+            record_weak_imports(jit_module);
+            jit_module.finalize_definitions(tcx.dcx(), cx.debug_context.as_mut());
             jit_module.module.get_finalized_function(func_id)
         })
     })
 }
 
+/// Replace the environment of the process with the environment of the jitted program.
+///
+/// Rather than changing variables one at a time using `std::env::set_var`, which races with rustc
+/// threads reading the environment, the complete environment is built up front and installed with
+/// a single store to `environ`. Neither the old nor the new environment is ever freed, so threads
+/// which are reading the environment concurrently keep seeing a consistent one.
+#[cfg(unix)]
+fn set_jit_environ(jit_env: &[(String, Option<String>)]) {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    struct Environ {
+        _vars: Vec<CString>,
+        ptrs: Vec<*const c_char>,
+    }
+    unsafe impl Send for Environ {}
+    unsafe impl Sync for Environ {}
+
+    static JIT_ENVIRON: OnceLock<Environ> = OnceLock::new();
+
+    let mut vars = std::env::vars_os().collect::<Vec<_>>();
+    for (var, val) in jit_env {
+        vars.retain(|(name, _)| name.as_bytes() != var.as_bytes());
+        if let Some(val) = val {
+            vars.push((var.into(), val.into()));
+        }
+    }
+    let vars = vars
+        .into_iter()
+        .map(|(name, val)| {
+            let mut var = name.into_vec();
+            var.push(b'=');
+            var.extend_from_slice(val.as_bytes());
+            CString::new(var).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let ptrs =
+        vars.iter().map(|var| var.as_ptr()).chain(std::iter::once(std::ptr::null())).collect();
+    let jit_environ = JIT_ENVIRON.get_or_init(|| Environ { _vars: vars, ptrs });
+
+    #[cfg(target_vendor = "apple")]
+    unsafe {
+        extern "C" {
+            fn _NSGetEnviron() -> *mut *const *const c_char;
+        }
+        *_NSGetEnviron() = jit_environ.ptrs.as_ptr();
+    }
+
+    #[cfg(not(target_vendor = "apple"))]
+    unsafe {
+        extern "C" {
+            static mut environ: *const *const c_char;
+        }
+        environ = jit_environ.ptrs.as_ptr();
+    }
+}
+
+/// Record the names of all weak imports declared so far in [`WEAK_IMPORTS`] for use by the symbol
+/// lookup function during `finalize_definitions`.
+fn record_weak_imports(module: &UnwindModule<JITModule>) {
+    let declarations = module.declarations();
+    let functions = declarations.get_functions().map(|(_, decl)| (decl.linkage, &decl.name));
+    let data_objects = declarations.get_data_objects().map(|(_, decl)| (decl.linkage, &decl.name));
+    let mut weak_imports = WEAK_IMPORTS.lock().unwrap();
+    weak_imports.clear();
+    weak_imports.extend(functions.chain(data_objects).filter_map(|(linkage, name)| {
+        if linkage == Linkage::Preemptible { name.clone() } else { None }
+    }));
+}
+
 fn dep_symbol_lookup_fn(
     sess: &Session,
+    backend_config: &BackendConfig,
     crate_info: CrateInfo,
 ) -> Box<dyn Fn(&str) -> Option<*const u8> + Send> {
     use rustc_middle::middle::dependency_format::Linkage;
@@ -336,10 +462,29 @@ fn dep_symbol_lookup_fn(
         }
     }
 
+    for lib in &backend_config.jit_preload {
+        match find_preload_lib(&backend_config.jit_lib_paths, lib) {
+            Some(path) => dylib_paths.push(path),
+            None => {
+                let mut diag = sess.dcx().struct_err(format!("Can't find library `{}`", lib));
+                if !backend_config.jit_lib_paths.is_empty() {
+                    diag.note(format!("searched in {}", backend_config.jit_lib_paths.join(", ")));
+                }
+                diag.emit();
+            }
+        }
+    }
+
     let imported_dylibs = Box::leak(
         dylib_paths
-            .into_iter()
-            .map(|path| unsafe { libloading::Library::new(&path).unwrap() })
+            .iter()
+            .filter_map(|path| match unsafe { libloading::Library::new(path) } {
+                Ok(lib) => Some(lib),
+                Err(err) => {
+                    sess.dcx().err(format!("Can't load {}: {}", path.display(), err));
+                    None
+                }
+            })
             .collect::<Box<[_]>>(),
     );
 
@@ -351,10 +496,60 @@ fn dep_symbol_lookup_fn(
                 return Some(*sym);
             }
         }
+
+        // Cranelift-jit falls back to looking up the symbol in the current process and panics
+        // if that fails too unless the symbol is a weak import. Do the same lookup here to
+        // produce a useful error instead.
+        #[cfg(unix)]
+        {
+            let this = libloading::os::unix::Library::this();
+            if let Ok(sym) = unsafe { this.get::<*const u8>(sym_name.as_bytes()) } {
+                return Some(*sym);
+            }
+
+            // Let cranelift-jit resolve weak imports to null.
+            if WEAK_IMPORTS.lock().unwrap().iter().any(|name| name == sym_name) {
+                return None;
+            }
+
+            let early_dcx = rustc_session::EarlyDiagCtxt::new(
+                rustc_session::config::ErrorOutputType::default(),
+            );
+            let searched = dylib_paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .chain(std::iter::once("the rustc process".to_owned()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            early_dcx.early_fatal(format!(
+                "can't resolve symbol `{sym_name}` in JIT mode, searched {searched}. \
+                 Use -Cllvm-args=jit_preload=... to load additional libraries."
+            ));
+        }
+
+        #[cfg(not(unix))]
         None
     })
 }
 
+/// Find a library passed to `-Cllvm-args=jit_preload`. Paths are used as is, while bare names
+/// are searched for using the platform naming convention in the `jit_lib_path` directories.
+fn find_preload_lib(lib_paths: &[String], lib: &str) -> Option<PathBuf> {
+    let path = Path::new(lib);
+    if path.components().count() > 1 {
+        return path.exists().then(|| path.to_owned());
+    }
+
+    let file_names = [
+        lib.to_owned(),
+        format!("{}{}{}", std::env::consts::DLL_PREFIX, lib, std::env::consts::DLL_SUFFIX),
+    ];
+    lib_paths
+        .iter()
+        .flat_map(|dir| file_names.iter().map(move |file_name| Path::new(dir).join(file_name)))
+        .find(|path| path.exists())
+}
+
 fn codegen_shim<'tcx>(
     tcx: TyCtxt<'tcx>,
     cached_context: &mut Context,
@@ -404,3 +599,264 @@ fn codegen_shim<'tcx>(
 
     module.define_function(func_id, context).unwrap();
 }
+
+/// Assemble the global asm, inline asm wrappers and naked functions collected in `cx` and load the
+/// resulting object file into the JIT module.
+///
+/// Every section of the object file is defined as a single function or data object. Global
+/// symbols at the start of a section are used as name of the function or data object, such that
+/// references from Rust code resolve to it. Global functions at other offsets get a jump stub.
+fn load_global_asm(tcx: TyCtxt<'_>, module: &mut UnwindModule<JITModule>, cx: &CodegenCx) {
+    static ASM_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+    if crate::target_triple(tcx.sess).binary_format != BinaryFormat::Elf {
+        tcx.dcx().fatal("Inline asm in JIT mode is only supported on ELF targets");
+    }
+
+    let config = GlobalAsmConfig::new(tcx);
+    let cgu_name = format!("jit_asm_{}", ASM_INDEX.fetch_add(1, Ordering::Relaxed));
+    let object_file =
+        spawn_global_asm_assembler(&config, &cgu_name, &cx.global_asm, &cx.global_asm_spans)
+            .unwrap()
+            .wait()
+            .unwrap_or_else(|err| err.emit(tcx.dcx()));
+    let data = std::fs::read(&object_file).unwrap();
+    if !tcx.sess.opts.cg.save_temps {
+        let _ = std::fs::remove_file(&object_file);
+    }
+    let object = object::File::parse(&*data).unwrap_or_else(|err| {
+        tcx.dcx().fatal(format!("failed to parse assembled object file: {err}"))
+    });
+
+    let default_call_conv = module.target_config().default_call_conv;
+    let mut section_ids = FxHashMap::<SectionIndex, FuncOrDataId>::default();
+    let mut stubs = vec![];
+    for section in object.sections() {
+        let is_text = match section.kind() {
+            _ if section.size() == 0 => continue,
+            SectionKind::Text => true,
+            SectionKind::Data
+            | SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString
+            | SectionKind::UninitializedData => false,
+            SectionKind::Tls | SectionKind::UninitializedTls => {
+                tcx.dcx().fatal("Thread locals defined in asm are not supported in JIT mode")
+            }
+            _ => continue,
+        };
+
+        let mut name = None;
+        for symbol in object.symbols() {
+            if symbol.section_index() != Some(section.index())
+                || !symbol.is_global()
+                || !symbol.is_definition()
+            {
+                continue;
+            }
+            let symbol_name = symbol.name().unwrap();
+            let offset = symbol.address() - section.address();
+            if offset == 0 && name.is_none() {
+                name = Some(symbol_name);
+            } else if is_text {
+                stubs.push((symbol_name, section.index(), offset));
+            } else if module.get_name(symbol_name).is_some() {
+                tcx.dcx().fatal(format!(
+                    "Data symbol `{symbol_name}` defined in asm is not at the start of a section, \
+                     which is not supported in JIT mode"
+                ));
+            }
+        }
+
+        let id = if is_text {
+            FuncOrDataId::Func(match name {
+                Some(name) => {
+                    // Keep the signature of functions already referenced from Rust code.
+                    let sig = match module.get_name(name) {
+                        Some(FuncOrDataId::Func(func_id)) => {
+                            module.declarations().get_function_decl(func_id).signature.clone()
+                        }
+                        _ => Signature::new(default_call_conv),
+                    };
+                    module.declare_function(name, Linkage::Export, &sig).unwrap()
+                }
+                None => {
+                    module.declare_anonymous_function(&Signature::new(default_call_conv)).unwrap()
+                }
+            })
+        } else {
+            let writable =
+                matches!(section.kind(), SectionKind::Data | SectionKind::UninitializedData);
+            FuncOrDataId::Data(match name {
+                Some(name) => module.declare_data(name, Linkage::Export, writable, false).unwrap(),
+                None => module.declare_anonymous_data(writable, false).unwrap(),
+            })
+        };
+        section_ids.insert(section.index(), id);
+    }
+
+    for section in object.sections() {
+        let Some(&id) = section_ids.get(&section.index()) else {
+            continue;
+        };
+
+        let mut relocs = vec![];
+        for (offset, reloc) in section.relocations() {
+            let RelocationFlags::Elf { r_type } = reloc.flags() else { unreachable!() };
+            let Some(kind) = asm_reloc_kind(&tcx.sess.target.arch, r_type) else {
+                tcx.dcx().fatal(format!("Unsupported relocation {r_type} in asm in JIT mode"));
+            };
+            let (target, addend) = match reloc.target() {
+                RelocationTarget::Symbol(symbol_index) => {
+                    let symbol = object.symbol_by_index(symbol_index).unwrap();
+                    match symbol.section_index() {
+                        Some(target_section) => {
+                            let target_section = object.section_by_index(target_section).unwrap();
+                            let Some(&target) = section_ids.get(&target_section.index()) else {
+                                tcx.dcx().fatal(format!(
+                                    "Reference to unsupported section `{}` in asm in JIT mode",
+                                    target_section.name().unwrap_or("<unknown>"),
+                                ));
+                            };
+                            let offset = symbol.address() - target_section.address();
+                            (target, reloc.addend() + offset as i64)
+                        }
+                        None => {
+                            let name = symbol.name().unwrap();
+                            let target = match module.get_name(name) {
+                                Some(target) => target,
+                                None => FuncOrDataId::Func(
+                                    module
+                                        .declare_function(
+                                            name,
+                                            Linkage::Import,
+                                            &Signature::new(default_call_conv),
+                                        )
+                                        .unwrap(),
+                                ),
+                            };
+                            (target, reloc.addend())
+                        }
+                    }
+                }
+                RelocationTarget::Section(target_section) => {
+                    (section_ids[&target_section], reloc.addend())
+                }
+                _ => tcx.dcx().fatal("Unsupported relocation target in asm in JIT mode"),
+            };
+            relocs.push((u32::try_from(offset).unwrap(), kind, target, addend));
+        }
+
+        match id {
+            FuncOrDataId::Func(func_id) => {
+                define_asm_function(
+                    module,
+                    func_id,
+                    section.align(),
+                    section.data().unwrap(),
+                    &relocs,
+                );
+            }
+            FuncOrDataId::Data(data_id) => {
+                let mut data = DataDescription::new();
+                if section.kind() == SectionKind::UninitializedData {
+                    data.define_zeroinit(section.size() as usize);
+                } else {
+                    data.define(section.data().unwrap().into());
+                }
+                data.set_align(section.align());
+                for &(offset, kind, target, addend) in &relocs {
+                    if kind != Reloc::Abs8 {
+                        tcx.dcx().fatal("Unsupported relocation in asm data in JIT mode");
+                    }
+                    match target {
+                        FuncOrDataId::Func(func_id) if addend == 0 => {
+                            let func_ref = module.declare_func_in_data(func_id, &mut data);
+                            data.write_function_addr(offset, func_ref);
+                        }
+                        FuncOrDataId::Func(_) => tcx.dcx().fatal(
+                            "Function addresses with offset in asm data are not supported in JIT \
+                             mode",
+                        ),
+                        FuncOrDataId::Data(target_data_id) => {
+                            let gv = module.declare_data_in_data(target_data_id, &mut data);
+                            data.write_data_addr(offset, gv, addend);
+                        }
+                    }
+                }
+                module.define_data(data_id, &data).unwrap();
+            }
+        }
+    }
+
+    for (name, section_index, offset) in stubs {
+        let sig = match module.get_name(name) {
+            Some(FuncOrDataId::Func(func_id)) => {
+                module.declarations().get_function_decl(func_id).signature.clone()
+            }
+            _ => Signature::new(default_call_conv),
+        };
+        let func_id = module.declare_function(name, Linkage::Export, &sig).unwrap();
+        let target = section_ids[&section_index];
+        let offset = offset as i64;
+        let (bytes, reloc) = match &*tcx.sess.target.arch {
+            // jmp rel32
+            "x86_64" => (vec![0xe9, 0, 0, 0, 0], (1, Reloc::X86CallPCRel4, target, offset - 4)),
+            // b imm26
+            "aarch64" => {
+                (0x14000000u32.to_le_bytes().to_vec(), (0, Reloc::Arm64Call, target, offset))
+            }
+            arch => tcx.dcx().fatal(format!("Inline asm in JIT mode is not supported on {arch}")),
+        };
+        define_asm_function(module, func_id, 4, &bytes, &[reloc]);
+    }
+}
+
+fn define_asm_function(
+    module: &mut UnwindModule<JITModule>,
+    func_id: FuncId,
+    align: u64,
+    bytes: &[u8],
+    relocs: &[(u32, Reloc, FuncOrDataId, i64)],
+) {
+    // Only used for resolving the names of relocation targets.
+    let mut func = Function::new();
+    let relocs = relocs
+        .iter()
+        .map(|&(offset, kind, target, addend)| {
+            let name = match target {
+                FuncOrDataId::Func(target_func_id) => {
+                    let func_ref = module.declare_func_in_func(target_func_id, &mut func);
+                    func.dfg.ext_funcs[func_ref].name.clone()
+                }
+                FuncOrDataId::Data(target_data_id) => {
+                    let gv = module.declare_data_in_func(target_data_id, &mut func);
+                    let GlobalValueData::Symbol { name, .. } = &func.global_values[gv] else {
+                        unreachable!();
+                    };
+                    name.clone()
+                }
+            };
+            FinalizedMachReloc {
+                offset,
+                kind,
+                addend,
+                target: FinalizedRelocTarget::ExternalName(name),
+            }
+        })
+        .collect::<Vec<_>>();
+    module.define_function_bytes(func_id, &func, align, bytes, &relocs).unwrap();
+}
+
+fn asm_reloc_kind(arch: &str, r_type: u32) -> Option<Reloc> {
+    use object::elf;
+
+    Some(match (arch, r_type) {
+        ("x86_64", elf::R_X86_64_64) => Reloc::Abs8,
+        ("x86_64", elf::R_X86_64_PC32) => Reloc::X86PCRel4,
+        ("x86_64", elf::R_X86_64_PLT32) => Reloc::X86CallPLTRel4,
+        ("aarch64", elf::R_AARCH64_ABS64) => Reloc::Abs8,
+        ("aarch64", elf::R_AARCH64_CALL26 | elf::R_AARCH64_JUMP26) => Reloc::Arm64Call,
+        _ => return None,
+    })
+}
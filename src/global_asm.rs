@@ -2,23 +2,36 @@
 //! standalone executable.
 
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
 
 use rustc_ast::{InlineAsmOptions, InlineAsmTemplatePiece};
+use rustc_errors::DiagCtxtHandle;
 use rustc_hir::{InlineAsmOperand, ItemId};
 use rustc_middle::mir::interpret::ErrorHandled;
+use rustc_session::Session;
 use rustc_session::config::{OutputFilenames, OutputType};
+use rustc_span::fatal_error::FatalError;
 use rustc_target::asm::InlineAsmArch;
+use target_lexicon::BinaryFormat;
 
 use crate::prelude::*;
 
-pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String, item_id: ItemId) {
+pub(crate) fn codegen_global_asm_item(
+    tcx: TyCtxt<'_>,
+    cx: &mut crate::CodegenCx,
+    module: &mut dyn Module,
+    item_id: ItemId,
+) {
     let item = tcx.hir().item(item_id);
     if let rustc_hir::ItemKind::GlobalAsm(asm) = item.kind {
         let is_x86 =
             matches!(tcx.sess.asm_arch.unwrap(), InlineAsmArch::X86 | InlineAsmArch::X86_64);
+        let symbol_prefix = asm_symbol_prefix(tcx.sess);
+
+        // Creating wrappers for sym operands needs `cx`, so collect the asm separately.
+        let mut global_asm = String::new();
 
         if is_x86 {
             if !asm.options.contains(InlineAsmOptions::ATT_SYNTAX) {
@@ -56,33 +69,29 @@ pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String,
                             }
                         }
                         InlineAsmOperand::SymFn { anon_const } => {
-                            if cfg!(not(feature = "inline_asm_sym")) {
-                                tcx.dcx().span_err(
-                                    item.span,
-                                    "asm! and global_asm! sym operands are not yet supported",
-                                );
-                            }
-
                             let ty = tcx.typeck_body(anon_const.body).node_type(anon_const.hir_id);
                             let instance = match ty.kind() {
-                                &ty::FnDef(def_id, args) => Instance::new(def_id, args),
+                                &ty::FnDef(def_id, args) => Instance::resolve_for_fn_ptr(
+                                    tcx,
+                                    ty::ParamEnv::reveal_all(),
+                                    def_id,
+                                    args,
+                                )
+                                .unwrap(),
                                 _ => span_bug!(op_sp, "asm sym is not a function"),
                             };
-                            let symbol = tcx.symbol_name(instance);
-                            // FIXME handle the case where the function was made private to the
-                            // current codegen unit
-                            global_asm.push_str(symbol.name);
+                            // The function may have been made private to the current codegen
+                            // unit, so reference it through an exported wrapper.
+                            let symbol = crate::inline_asm::create_asm_sym_fn_wrapper(
+                                tcx, cx, module, instance,
+                            );
+                            global_asm.push_str(symbol_prefix);
+                            global_asm.push_str(&symbol);
                         }
                         InlineAsmOperand::SymStatic { path: _, def_id } => {
-                            if cfg!(not(feature = "inline_asm_sym")) {
-                                tcx.dcx().span_err(
-                                    item.span,
-                                    "asm! and global_asm! sym operands are not yet supported",
-                                );
-                            }
-
                             let instance = Instance::mono(tcx, def_id);
                             let symbol = tcx.symbol_name(instance);
+                            global_asm.push_str(symbol_prefix);
                             global_asm.push_str(symbol.name);
                         }
                         InlineAsmOperand::In { .. }
@@ -101,11 +110,24 @@ pub(crate) fn codegen_global_asm_item(tcx: TyCtxt<'_>, global_asm: &mut String,
         if is_x86 {
             global_asm.push_str(".att_syntax\n\n");
         }
+        // Every global_asm! item as well as the asm wrappers expect to start in the text section.
+        global_asm.push_str(".text\n\n");
+
+        cx.push_global_asm(&global_asm, item.span);
     } else {
         bug!("Expected GlobalAsm found {:?}", item);
     }
 }
 
+/// The prefix to use when referencing a symbol from assembly. Mach-O prefixes all symbols with an
+/// underscore, while ELF and x86_64 COFF use the symbol name as is.
+pub(crate) fn asm_symbol_prefix(sess: &Session) -> &'static str {
+    match crate::target_triple(sess).binary_format {
+        BinaryFormat::Macho => "_",
+        _ => "",
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct GlobalAsmConfig {
     assembler: PathBuf,
@@ -128,15 +150,108 @@ pub(crate) fn new(tcx: TyCtxt<'_>) -> Self {
     }
 }
 
-pub(crate) fn compile_global_asm(
-    config: &GlobalAsmConfig,
+/// An assembler process started by [`spawn_global_asm_assembler`] which may still be running.
+pub(crate) struct PendingGlobalAsm<'a> {
+    child: Child,
+    config: &'a GlobalAsmConfig,
+    global_asm: String,
+    global_asm_spans: Vec<(usize, Span)>,
+    object_file: PathBuf,
+}
+
+impl PendingGlobalAsm<'_> {
+    /// Wait for the assembler to finish and return the path of the produced object file.
+    pub(crate) fn wait(self) -> Result<PathBuf, GlobalAsmError> {
+        let output = self.child.wait_with_output().expect("Failed to wait for `as`.");
+        if output.status.success() {
+            return Ok(self.object_file);
+        }
+
+        // The assembler output doesn't tell which asm block is wrong, so add the asm blocks one at
+        // a time to find the ones which fail. Asm blocks may depend on previous asm blocks, for
+        // example through macros or symbol definitions, so every asm block is assembled together
+        // with all preceding asm blocks which didn't fail.
+        let mut failed_asm = vec![];
+        let mut valid_asm = String::new();
+        for (i, &(start, span)) in self.global_asm_spans.iter().enumerate() {
+            let end =
+                self.global_asm_spans.get(i + 1).map_or(self.global_asm.len(), |&(end, _)| end);
+            let prev_len = valid_asm.len();
+            valid_asm.push_str(&self.global_asm[start..end]);
+            let object_file = add_file_stem_postfix(self.object_file.clone(), &format!(".{i}"));
+            let prefix_output = spawn_assembler(self.config, &object_file, &valid_asm)
+                .wait_with_output()
+                .expect("Failed to wait for `as`.");
+            let _ = std::fs::remove_file(&object_file);
+            if !prefix_output.status.success() {
+                valid_asm.truncate(prev_len);
+                failed_asm
+                    .push((span, String::from_utf8_lossy(&prefix_output.stderr).into_owned()));
+            }
+        }
+
+        Err(GlobalAsmError {
+            global_asm: self.global_asm,
+            output: String::from_utf8_lossy(&output.stderr).into_owned(),
+            failed_asm,
+        })
+    }
+}
+
+/// The error returned by [`PendingGlobalAsm::wait`] when assembling failed.
+pub(crate) struct GlobalAsmError {
+    global_asm: String,
+    output: String,
+    /// The asm blocks which fail to assemble together with the preceding asm blocks and the
+    /// corresponding assembler output.
+    failed_asm: Vec<(Span, String)>,
+}
+
+impl GlobalAsmError {
+    pub(crate) fn emit(self, dcx: DiagCtxtHandle<'_>) -> ! {
+        if self.failed_asm.is_empty() {
+            // None of the asm blocks could be blamed for the failure.
+            dcx.fatal(format!("Failed to assemble `{}`\n{}", self.global_asm, self.output));
+        }
+
+        for (span, output) in self.failed_asm {
+            dcx.struct_span_err(span, "failed to assemble asm")
+                .with_note(output.trim_end().to_owned())
+                .emit();
+        }
+        FatalError.raise()
+    }
+}
+
+/// Start assembling `global_asm` in the background. This allows the assembler to run while
+/// Cranelift is still compiling the functions of the codegen unit.
+pub(crate) fn spawn_global_asm_assembler<'a>(
+    config: &'a GlobalAsmConfig,
     cgu_name: &str,
     global_asm: &str,
-) -> Result<Option<PathBuf>, String> {
+    global_asm_spans: &[(usize, Span)],
+) -> Option<PendingGlobalAsm<'a>> {
     if global_asm.is_empty() {
-        return Ok(None);
+        return None;
     }
 
+    let global_asm_object_file = add_file_stem_postfix(
+        config.output_filenames.temp_path(OutputType::Object, Some(cgu_name)),
+        ".asm",
+    );
+
+    let child = spawn_assembler(config, &global_asm_object_file, global_asm);
+
+    Some(PendingGlobalAsm {
+        child,
+        config,
+        global_asm: global_asm.to_owned(),
+        global_asm_spans: global_asm_spans.to_vec(),
+        object_file: global_asm_object_file,
+    })
+}
+
+fn spawn_assembler(config: &GlobalAsmConfig, object_file: &Path, global_asm: &str) -> Child {
     // Remove all LLVM style comments
     let mut global_asm = global_asm
         .lines()
@@ -145,24 +260,23 @@ pub(crate) fn compile_global_asm(
         .join("\n");
     global_asm.push('\n');
 
-    let global_asm_object_file = add_file_stem_postfix(
-        config.output_filenames.temp_path(OutputType::Object, Some(cgu_name)),
-        ".asm",
-    );
-
     // Assemble `global_asm`
     if option_env!("CG_CLIF_FORCE_GNU_AS").is_some() {
         let mut child = Command::new(&config.assembler)
             .arg("-o")
-            .arg(&global_asm_object_file)
+            .arg(object_file)
             .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()
             .expect("Failed to spawn `as`.");
-        child.stdin.take().unwrap().write_all(global_asm.as_bytes()).unwrap();
-        let status = child.wait().expect("Failed to wait for `as`.");
-        if !status.success() {
-            return Err(format!("Failed to assemble `{}`", global_asm));
-        }
+        // `as` may report errors before it has read all input. Write the input on a separate
+        // thread to avoid a deadlock when the stderr pipe is full. Write errors are ignored as
+        // `as` exiting early is reported through its exit status instead.
+        let mut stdin = child.stdin.take().unwrap();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(global_asm.as_bytes());
+        });
+        child
     } else {
         let mut child = Command::new(std::env::current_exe().unwrap())
             // Avoid a warning about the jobserver fd not being passed
@@ -174,11 +288,12 @@ pub(crate) fn compile_global_asm(
             .arg("--emit")
             .arg("obj")
             .arg("-o")
-            .arg(&global_asm_object_file)
+            .arg(object_file)
             .arg("-")
             .arg("-Abad_asm_style")
             .arg("-Zcodegen-backend=llvm")
             .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()
             .expect("Failed to spawn `as`.");
         let mut stdin = child.stdin.take().unwrap();
@@ -196,21 +311,18 @@ pub(crate) fn compile_global_asm(
             )
             .unwrap();
         stdin.write_all(global_asm.as_bytes()).unwrap();
+        // All operands have already been substituted. Use `options(raw)` to prevent braces like
+        // those of AVX-512 masks from being interpreted as placeholders.
         stdin
             .write_all(
                 br####"
-                "###);
+                "###, options(raw));
                 "####,
             )
             .unwrap();
         std::mem::drop(stdin);
-        let status = child.wait().expect("Failed to wait for `as`.");
-        if !status.success() {
-            return Err(format!("Failed to assemble `{}`", global_asm));
-        }
+        child
     }
-
-    Ok(Some(global_asm_object_file))
 }
 
 pub(crate) fn add_file_stem_postfix(mut path: PathBuf, postfix: &str) -> PathBuf {
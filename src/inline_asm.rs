@@ -14,7 +14,7 @@
 pub(crate) enum CInlineAsmOperand<'tcx> {
     In {
         reg: InlineAsmRegOrRegClass,
-        value: Value,
+        value: CValue<'tcx>,
     },
     Out {
         reg: InlineAsmRegOrRegClass,
@@ -24,7 +24,7 @@ pub(crate) enum CInlineAsmOperand<'tcx> {
     InOut {
         reg: InlineAsmRegOrRegClass,
         _late: bool,
-        in_value: Value,
+        in_value: CValue<'tcx>,
         out_place: Option<CPlace<'tcx>>,
     },
     Const {
@@ -54,10 +54,9 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
     let operands = operands
         .iter()
         .map(|operand| match *operand {
-            InlineAsmOperand::In { reg, ref value } => CInlineAsmOperand::In {
-                reg,
-                value: crate::base::codegen_operand(fx, value).load_scalar(fx),
-            },
+            InlineAsmOperand::In { reg, ref value } => {
+                CInlineAsmOperand::In { reg, value: crate::base::codegen_operand(fx, value) }
+            }
             InlineAsmOperand::Out { reg, late, ref place } => CInlineAsmOperand::Out {
                 reg,
                 late,
@@ -67,7 +66,7 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
                 CInlineAsmOperand::InOut {
                     reg,
                     _late: late,
-                    in_value: crate::base::codegen_operand(fx, in_value).load_scalar(fx),
+                    in_value: crate::base::codegen_operand(fx, in_value),
                     out_place: out_place.map(|place| crate::base::codegen_place(fx, place)),
                 }
             }
@@ -82,12 +81,6 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
                 CInlineAsmOperand::Const { value }
             }
             InlineAsmOperand::SymFn { ref value } => {
-                if cfg!(not(feature = "inline_asm_sym")) {
-                    fx.tcx
-                        .dcx()
-                        .span_err(span, "asm! and global_asm! sym operands are not yet supported");
-                }
-
                 let const_ = fx.monomorphize(value.const_);
                 if let ty::FnDef(def_id, args) = *const_.ty().kind() {
                     let instance = ty::Instance::resolve_for_fn_ptr(
@@ -97,23 +90,9 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
                         args,
                     )
                     .unwrap();
-                    let symbol = fx.tcx.symbol_name(instance);
-
-                    // Pass a wrapper rather than the function itself as the function itself may not
-                    // be exported from the main codegen unit and may thus be unreachable from the
-                    // object file created by an external assembler.
-                    let inline_asm_index = fx.cx.inline_asm_index.get();
-                    fx.cx.inline_asm_index.set(inline_asm_index + 1);
-                    let wrapper_name = format!(
-                        "__inline_asm_{}_wrapper_n{}",
-                        fx.cx.cgu_name.as_str().replace('.', "__").replace('-', "_"),
-                        inline_asm_index
-                    );
-                    let sig =
-                        get_function_sig(fx.tcx, fx.target_config.default_call_conv, instance);
-                    create_wrapper_function(fx.module, sig, &wrapper_name, symbol.name);
+                    let symbol = create_asm_sym_fn_wrapper(fx.tcx, fx.cx, fx.module, instance);
 
-                    CInlineAsmOperand::Symbol { symbol: wrapper_name }
+                    CInlineAsmOperand::Symbol { symbol }
                 } else {
                     span_bug!(span, "invalid type for asm sym (fn)");
                 }
@@ -129,7 +108,7 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
         })
         .collect::<Vec<_>>();
 
-    codegen_inline_asm_inner(fx, template, &operands, options);
+    codegen_inline_asm_with_span(fx, span, template, &operands, options);
 
     match destination {
         Some(destination) => {
@@ -142,14 +121,40 @@ pub(crate) fn codegen_inline_asm_terminator<'tcx>(
     }
 }
 
+/// Codegen asm used to implement intrinsics. Assembler errors point at the enclosing function.
 pub(crate) fn codegen_inline_asm_inner<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     template: &[InlineAsmTemplatePiece],
     operands: &[CInlineAsmOperand<'tcx>],
     options: InlineAsmOptions,
 ) {
+    let span = fx.mir.span;
+    codegen_inline_asm_with_span(fx, span, template, operands, options);
+}
+
+fn codegen_inline_asm_with_span<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    span: Span,
+    template: &[InlineAsmTemplatePiece],
+    operands: &[CInlineAsmOperand<'tcx>],
+    options: InlineAsmOptions,
+) {
+    if codegen_simple_inline_asm(fx, template, operands, options) {
+        return;
+    }
+
     // FIXME add .eh_frame unwind info directives
 
+    let operand_sizes = operands
+        .iter()
+        .map(|operand| match *operand {
+            CInlineAsmOperand::In { value, .. }
+            | CInlineAsmOperand::InOut { in_value: value, .. } => Some(value.layout().size),
+            CInlineAsmOperand::Out { place, .. } => place.map(|place| place.layout().size),
+            CInlineAsmOperand::Const { .. } | CInlineAsmOperand::Symbol { .. } => None,
+        })
+        .collect();
+
     let mut asm_gen = InlineAssemblyGenerator {
         tcx: fx.tcx,
         arch: fx.tcx.sess.asm_arch.unwrap(),
@@ -157,6 +162,7 @@ pub(crate) fn codegen_inline_asm_inner<'tcx>(
         template,
         operands,
         options,
+        operand_sizes,
         registers: Vec::new(),
         stack_slots_clobber: Vec::new(),
         stack_slots_input: Vec::new(),
@@ -176,7 +182,7 @@ pub(crate) fn codegen_inline_asm_inner<'tcx>(
     );
 
     let generated_asm = asm_gen.generate_asm_wrapper(&asm_name);
-    fx.cx.global_asm.push_str(&generated_asm);
+    fx.cx.push_global_asm(&generated_asm, span);
 
     let mut inputs = Vec::new();
     let mut outputs = Vec::new();
@@ -203,6 +209,223 @@ pub(crate) fn codegen_inline_asm_inner<'tcx>(
     call_inline_asm(fx, &asm_name, asm_gen.stack_slot_size, inputs, outputs);
 }
 
+/// Lower asm blocks that only consist of instructions which can be expressed in Cranelift IR
+/// without invoking an external assembler. Returns `false` if the asm block contains anything else.
+///
+/// This handles `nomem` asm blocks that only contain comments (like those used as optimization
+/// barriers), `nop` and moves between general purpose registers, as well as full memory fences.
+/// Spin loop hints like `pause` and `yield` and CPU feature reads like `cpuid` have no Cranelift
+/// equivalent and syscalls need the register assignment and clobber handling of the wrapper, so
+/// these still go through the assembler.
+fn codegen_simple_inline_asm<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    template: &[InlineAsmTemplatePiece],
+    operands: &[CInlineAsmOperand<'tcx>],
+    options: InlineAsmOptions,
+) -> bool {
+    // Marks the start and end of an operand placeholder. It can't occur in the asm template.
+    const PLACEHOLDER_MARKER: char = '\0';
+
+    if options.contains(InlineAsmOptions::NORETURN) {
+        return false;
+    }
+    let arch = fx.tcx.sess.asm_arch.unwrap();
+    let (line_comments, nops, fences, mov): (&[&str], &[&str], &[&str], &str) = match arch {
+        InlineAsmArch::X86 | InlineAsmArch::X86_64 => (&["//", "#"], &["nop"], &["mfence"], "mov"),
+        InlineAsmArch::AArch64 => (&["//"], &["nop"], &["dmb ish"], "mov"),
+        InlineAsmArch::RiscV64 => (&["//", "#"], &["nop"], &["fence rw, rw"], "mv"),
+        _ => return false,
+    };
+    // The operand order of AT&T syntax is reversed.
+    let is_att_syntax = options.contains(InlineAsmOptions::ATT_SYNTAX);
+
+    let mut asm = String::new();
+    let mut placeholders = vec![];
+    for piece in template {
+        match piece {
+            InlineAsmTemplatePiece::String(s) => asm.push_str(s),
+            InlineAsmTemplatePiece::Placeholder { operand_idx, modifier, span: _ } => {
+                write!(asm, "{PLACEHOLDER_MARKER}{}{PLACEHOLDER_MARKER}", placeholders.len())
+                    .unwrap();
+                placeholders.push((*operand_idx, *modifier));
+            }
+        }
+    }
+    while let Some(start) = asm.find("/*") {
+        let Some(len) = asm[start..].find("*/") else {
+            return false;
+        };
+        asm.replace_range(start..start + len + 2, " ");
+    }
+
+    // Parse the placeholder for a general purpose register operand without modifier.
+    let parse_reg_operand = |arg: &str| {
+        let arg = arg.trim().strip_prefix(PLACEHOLDER_MARKER)?.strip_suffix(PLACEHOLDER_MARKER)?;
+        let (operand_idx, modifier) = placeholders[arg.parse::<usize>().ok()?];
+        let reg = match operands[operand_idx] {
+            CInlineAsmOperand::In { reg, .. }
+            | CInlineAsmOperand::Out { reg, .. }
+            | CInlineAsmOperand::InOut { reg, .. } => reg,
+            CInlineAsmOperand::Const { .. } | CInlineAsmOperand::Symbol { .. } => return None,
+        };
+        let is_gpr = matches!(
+            reg.reg_class(),
+            InlineAsmRegClass::X86(X86InlineAsmRegClass::reg | X86InlineAsmRegClass::reg_abcd)
+                | InlineAsmRegClass::AArch64(AArch64InlineAsmRegClass::reg)
+                | InlineAsmRegClass::RiscV(RiscVInlineAsmRegClass::reg)
+        );
+        if is_gpr && modifier.is_none() { Some(operand_idx) } else { None }
+    };
+
+    let mut moves = vec![];
+    let mut has_fence = false;
+    for statement in asm.split(['\n', ';']) {
+        let statement = line_comments
+            .iter()
+            .fold(statement, |statement, comment| statement.split(comment).next().unwrap())
+            .trim();
+        let (mnemonic, args) = statement.split_once(char::is_whitespace).unwrap_or((statement, ""));
+        let args = args.trim();
+        if statement.is_empty() {
+            continue;
+        }
+
+        // Normalize the whitespace between arguments for matching against known instructions.
+        let normalized = if args.is_empty() {
+            mnemonic.to_owned()
+        } else {
+            format!("{mnemonic} {}", args.split(',').map(str::trim).collect::<Vec<_>>().join(", "))
+        };
+        if nops.contains(&&*normalized) {
+            continue;
+        }
+        if fences.contains(&&*normalized) {
+            has_fence = true;
+            continue;
+        }
+
+        if mnemonic == mov {
+            let Some((dst, src)) = args.split_once(',') else {
+                return false;
+            };
+            let (dst, src) = if is_att_syntax { (src, dst) } else { (dst, src) };
+            let (Some(dst), Some(src)) = (parse_reg_operand(dst), parse_reg_operand(src)) else {
+                return false;
+            };
+            moves.push((dst, src));
+            continue;
+        }
+
+        return false;
+    }
+
+    // Without `nomem` the asm block may read or write arbitrary memory, so memory accesses must not
+    // be moved across it. A Cranelift fence guarantees this, otherwise only the call to the wrapper
+    // function does.
+    if !options.contains(InlineAsmOptions::NOMEM) && !has_fence {
+        return false;
+    }
+
+    // Track the value of every register operand while executing the moves.
+    let mut values = operands
+        .iter()
+        .map(|operand| match *operand {
+            CInlineAsmOperand::In { value, .. } => Some(value),
+            CInlineAsmOperand::InOut { in_value, .. } => Some(in_value),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for &(dst, src) in &moves {
+        let Some(value) = values[src] else {
+            // Reading an output register before it is written results in an undefined value.
+            return false;
+        };
+        values[dst] = Some(value);
+    }
+
+    let overlaps_input_reg = |out_reg: InlineAsmReg| {
+        operands.iter().any(|operand| match *operand {
+            CInlineAsmOperand::In { reg: InlineAsmRegOrRegClass::Reg(in_reg), .. } => {
+                let mut overlaps = false;
+                in_reg.overlapping_regs(|r| overlaps |= r == out_reg);
+                overlaps
+            }
+            _ => false,
+        })
+    };
+
+    let mut outputs = vec![];
+    for (operand, value) in operands.iter().zip(values) {
+        let (CInlineAsmOperand::Out { place: Some(place), .. }
+        | CInlineAsmOperand::InOut { out_place: Some(place), .. }) = *operand
+        else {
+            continue;
+        };
+        let Some(value) = value else {
+            // An explicit output register that isn't written still contains the value of an
+            // input operand assigned to the same register. Other output registers that aren't
+            // written contain an undefined value.
+            if let CInlineAsmOperand::Out { reg: InlineAsmRegOrRegClass::Reg(reg), .. } = *operand {
+                if overlaps_input_reg(reg) {
+                    return false;
+                }
+            }
+            continue;
+        };
+        if place.layout().ty.is_simd()
+            || value.layout().ty.is_simd()
+            || asm_clif_type(fx, place.layout().ty).is_none()
+            || asm_clif_type(fx, place.layout().ty) != asm_clif_type(fx, value.layout().ty)
+        {
+            return false;
+        }
+        outputs.push((place, value));
+    }
+
+    if has_fence {
+        fx.bcx.ins().fence();
+    }
+
+    // Input operands may still refer to the places of output operands, so load all of them before
+    // writing any output.
+    let outputs = outputs
+        .into_iter()
+        .map(|(place, value)| (place, value.load_scalar(fx)))
+        .collect::<Vec<_>>();
+    for (place, value) in outputs {
+        place.write_cvalue(fx, CValue::by_val(value, place.layout()));
+    }
+
+    true
+}
+
+/// Create a wrapper for a function referenced by a `sym` operand and return the symbol name of
+/// the wrapper.
+///
+/// The wrapper is used rather than the function itself as the function itself may not be exported
+/// from the codegen unit and may thus be unreachable from the object file created by an external
+/// assembler.
+pub(crate) fn create_asm_sym_fn_wrapper<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    cx: &crate::CodegenCx,
+    module: &mut dyn Module,
+    instance: Instance<'tcx>,
+) -> String {
+    let symbol = tcx.symbol_name(instance);
+
+    let inline_asm_index = cx.inline_asm_index.get();
+    cx.inline_asm_index.set(inline_asm_index + 1);
+    let wrapper_name = format!(
+        "__inline_asm_{}_wrapper_n{}",
+        cx.cgu_name.as_str().replace('.', "__").replace('-', "_"),
+        inline_asm_index
+    );
+    let sig = get_function_sig(tcx, module.target_config().default_call_conv, instance);
+    create_wrapper_function(module, sig, &wrapper_name, symbol.name);
+
+    wrapper_name
+}
+
 pub(crate) fn codegen_naked_asm<'tcx>(
     tcx: TyCtxt<'tcx>,
     cx: &mut crate::CodegenCx,
@@ -243,11 +466,6 @@ pub(crate) fn codegen_naked_asm<'tcx>(
                 CInlineAsmOperand::Const { value }
             }
             InlineAsmOperand::SymFn { ref value } => {
-                if cfg!(not(feature = "inline_asm_sym")) {
-                    tcx.dcx()
-                        .span_err(span, "asm! and global_asm! sym operands are not yet supported");
-                }
-
                 let const_ = instance.instantiate_mir_and_normalize_erasing_regions(
                     tcx,
                     ty::ParamEnv::reveal_all(),
@@ -261,23 +479,9 @@ pub(crate) fn codegen_naked_asm<'tcx>(
                         args,
                     )
                     .unwrap();
-                    let symbol = tcx.symbol_name(instance);
-
-                    // Pass a wrapper rather than the function itself as the function itself may not
-                    // be exported from the main codegen unit and may thus be unreachable from the
-                    // object file created by an external assembler.
-                    let inline_asm_index = cx.inline_asm_index.get();
-                    cx.inline_asm_index.set(inline_asm_index + 1);
-                    let wrapper_name = format!(
-                        "__inline_asm_{}_wrapper_n{}",
-                        cx.cgu_name.as_str().replace('.', "__").replace('-', "_"),
-                        inline_asm_index
-                    );
-                    let sig =
-                        get_function_sig(tcx, module.target_config().default_call_conv, instance);
-                    create_wrapper_function(module, sig, &wrapper_name, symbol.name);
+                    let symbol = create_asm_sym_fn_wrapper(tcx, cx, module, instance);
 
-                    CInlineAsmOperand::Symbol { symbol: wrapper_name }
+                    CInlineAsmOperand::Symbol { symbol }
                 } else {
                     span_bug!(span, "invalid type for asm sym (fn)");
                 }
@@ -300,6 +504,7 @@ pub(crate) fn codegen_naked_asm<'tcx>(
         template,
         operands: &operands,
         options,
+        operand_sizes: Vec::new(),
         registers: Vec::new(),
         stack_slots_clobber: Vec::new(),
         stack_slots_input: Vec::new(),
@@ -309,7 +514,7 @@ pub(crate) fn codegen_naked_asm<'tcx>(
     };
 
     let generated_asm = asm_gen.generate_asm_wrapper(symbol_name);
-    cx.global_asm.push_str(&generated_asm);
+    cx.push_global_asm(&generated_asm, span);
 }
 
 struct InlineAssemblyGenerator<'a, 'tcx> {
@@ -319,6 +524,8 @@ struct InlineAssemblyGenerator<'a, 'tcx> {
     template: &'a [InlineAsmTemplatePiece],
     operands: &'a [CInlineAsmOperand<'tcx>],
     options: InlineAsmOptions,
+    /// The size of the value passed in or out of each register operand.
+    operand_sizes: Vec<Option<Size>>,
     registers: Vec<Option<InlineAsmReg>>,
     stack_slots_clobber: Vec<Option<Size>>,
     stack_slots_input: Vec<Option<Size>>,
@@ -492,7 +699,16 @@ fn allocate_stack_slots(&mut self) {
             }
 
             if need_save {
-                slots_clobber[i] = Some(new_slot(reg.reg_class()));
+                let class = clobber_save_class(reg);
+                if class.supported_types(self.arch).is_empty() {
+                    // Clobber-only register classes like the RISC-V vector registers can't be
+                    // saved as there is no instruction available to access them.
+                    self.tcx.dcx().fatal(format!(
+                        "clobbering callee-saved register `{}` in inline asm is not supported",
+                        reg.name(),
+                    ));
+                }
+                slots_clobber[i] = Some(new_slot(class));
             }
         }
 
@@ -549,20 +765,69 @@ fn allocate_stack_slots(&mut self) {
     fn generate_asm_wrapper(&self, asm_name: &str) -> String {
         let binary_format = crate::target_triple(self.tcx.sess).binary_format;
 
+        // Naked functions are emitted as the function symbol itself rather than a wrapper, so they
+        // need to respect the linkage, alignment and section requested by the user.
+        let (is_weak, alignment, link_section) = if self.is_naked {
+            let attrs = self.tcx.codegen_fn_attrs(self.enclosing_def_id);
+            let is_weak = matches!(
+                attrs.linkage,
+                Some(
+                    rustc_middle::mir::mono::Linkage::WeakAny
+                        | rustc_middle::mir::mono::Linkage::WeakODR
+                )
+            );
+            (is_weak, attrs.alignment.map(|align| align.bytes()), attrs.link_section)
+        } else {
+            (false, None, None)
+        };
+        let global_directive = if is_weak { ".weak" } else { ".globl" };
+
         let mut generated_asm = String::new();
         match binary_format {
             BinaryFormat::Elf => {
-                writeln!(generated_asm, ".globl {}", asm_name).unwrap();
+                writeln!(generated_asm, "{global_directive} {}", asm_name).unwrap();
                 writeln!(generated_asm, ".type {},@function", asm_name).unwrap();
-                writeln!(generated_asm, ".section .text.{},\"ax\",@progbits", asm_name).unwrap();
+                if let Some(section) = link_section {
+                    writeln!(generated_asm, ".section {},\"ax\",@progbits", section).unwrap();
+                } else {
+                    writeln!(generated_asm, ".section .text.{},\"ax\",@progbits", asm_name)
+                        .unwrap();
+                }
+                if let Some(alignment) = alignment {
+                    writeln!(generated_asm, ".balign {alignment}").unwrap();
+                }
                 writeln!(generated_asm, "{}:", asm_name).unwrap();
             }
             BinaryFormat::Macho => {
                 writeln!(generated_asm, ".globl _{}", asm_name).unwrap();
+                if is_weak {
+                    writeln!(generated_asm, ".weak_definition _{}", asm_name).unwrap();
+                }
+                if let Some(section) = link_section {
+                    writeln!(generated_asm, ".section {}", section).unwrap();
+                } else {
+                    generated_asm.push_str(".section __TEXT,__text,regular,pure_instructions\n");
+                }
+                if let Some(alignment) = alignment {
+                    writeln!(generated_asm, ".balign {alignment}").unwrap();
+                }
                 writeln!(generated_asm, "_{}:", asm_name).unwrap();
             }
             BinaryFormat::Coff => {
-                writeln!(generated_asm, ".globl {}", asm_name).unwrap();
+                writeln!(generated_asm, "{global_directive} {}", asm_name).unwrap();
+                // Mark the symbol as external function
+                writeln!(generated_asm, ".def {}", asm_name).unwrap();
+                generated_asm.push_str(".scl 2\n");
+                generated_asm.push_str(".type 32\n");
+                generated_asm.push_str(".endef\n");
+                if let Some(section) = link_section {
+                    writeln!(generated_asm, ".section {},\"xr\"", section).unwrap();
+                } else {
+                    generated_asm.push_str(".text\n");
+                }
+                if let Some(alignment) = alignment {
+                    writeln!(generated_asm, ".balign {alignment}").unwrap();
+                }
                 writeln!(generated_asm, "{}:", asm_name).unwrap();
             }
             _ => self
@@ -572,12 +837,28 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
         }
 
         let is_x86 = matches!(self.arch, InlineAsmArch::X86 | InlineAsmArch::X86_64);
+        // Windows requires unwind info for every function that modifies the stack pointer, even
+        // if no exception is thrown through it, to be able to walk the stack.
+        let emit_seh = !self.is_naked
+            && binary_format == BinaryFormat::Coff
+            && self.arch == InlineAsmArch::X86_64;
+        // Unwinding out of the asm block requires the unwinder to be able to step through the
+        // wrapper.
+        let emit_cfi = !self.is_naked
+            && binary_format != BinaryFormat::Coff
+            && self.options.contains(InlineAsmOptions::MAY_UNWIND);
 
         if is_x86 {
             generated_asm.push_str(".intel_syntax noprefix\n");
         }
+        if emit_seh {
+            writeln!(generated_asm, ".seh_proc {}", asm_name).unwrap();
+        }
+        if emit_cfi {
+            generated_asm.push_str(".cfi_startproc\n");
+        }
         if !self.is_naked {
-            Self::prologue(&mut generated_asm, self.arch);
+            Self::prologue(&mut generated_asm, self.arch, emit_seh, emit_cfi);
 
             // Save clobbered registers
             if !self.options.contains(InlineAsmOptions::NORETURN) {
@@ -587,18 +868,29 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
                     .zip(self.stack_slots_clobber.iter().copied())
                     .filter_map(|(r, s)| r.zip(s))
                 {
-                    Self::save_register(&mut generated_asm, self.arch, reg, slot);
+                    Self::save_register(
+                        &mut generated_asm,
+                        self.arch,
+                        reg,
+                        clobber_save_class(reg),
+                        None,
+                        slot,
+                    );
                 }
             }
 
             // Write input registers
-            for (reg, slot) in self
-                .registers
-                .iter()
-                .zip(self.stack_slots_input.iter().copied())
-                .filter_map(|(r, s)| r.zip(s))
+            for (i, slot) in
+                self.stack_slots_input.iter().enumerate().filter_map(|(i, s)| Some((i, (*s)?)))
             {
-                Self::restore_register(&mut generated_asm, self.arch, reg, slot);
+                Self::restore_register(
+                    &mut generated_asm,
+                    self.arch,
+                    self.registers[i].unwrap(),
+                    self.operand_reg_class(i),
+                    self.operand_sizes[i],
+                    slot,
+                );
             }
         }
 
@@ -623,36 +915,39 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
 
                             let reg = self.registers[*operand_idx].unwrap();
                             match self.arch {
-                                InlineAsmArch::X86_64 => match reg {
-                                    InlineAsmReg::X86(reg)
-                                        if reg as u32 >= X86InlineAsmReg::xmm0 as u32
-                                            && reg as u32 <= X86InlineAsmReg::xmm15 as u32 =>
-                                    {
+                                InlineAsmArch::X86_64 => {
+                                    if let Some(index) = x86_vector_reg_index(reg) {
                                         // rustc emits x0 rather than xmm0
                                         let class = match *modifier {
-                                            None | Some('x') => "xmm",
+                                            None => x86_vector_reg_prefix(
+                                                self.operand_reg_class(*operand_idx),
+                                            ),
+                                            Some('x') => "xmm",
                                             Some('y') => "ymm",
                                             Some('z') => "zmm",
                                             _ => unreachable!(),
                                         };
-                                        write!(
-                                            generated_asm,
-                                            "{class}{}",
-                                            reg as u32 - X86InlineAsmReg::xmm0 as u32
+                                        write!(generated_asm, "{class}{index}").unwrap();
+                                    } else {
+                                        reg.emit(
+                                            &mut generated_asm,
+                                            InlineAsmArch::X86_64,
+                                            *modifier,
                                         )
                                         .unwrap();
                                     }
-                                    _ => reg
-                                        .emit(&mut generated_asm, InlineAsmArch::X86_64, *modifier)
-                                        .unwrap(),
-                                },
+                                }
                                 _ => reg.emit(&mut generated_asm, self.arch, *modifier).unwrap(),
                             }
                         }
                         CInlineAsmOperand::Const { ref value } => {
                             generated_asm.push_str(value);
                         }
-                        CInlineAsmOperand::Symbol { ref symbol } => generated_asm.push_str(symbol),
+                        CInlineAsmOperand::Symbol { ref symbol } => {
+                            generated_asm
+                                .push_str(crate::global_asm::asm_symbol_prefix(self.tcx.sess));
+                            generated_asm.push_str(symbol);
+                        }
                     }
                 }
             }
@@ -666,13 +961,17 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
         if !self.is_naked {
             if !self.options.contains(InlineAsmOptions::NORETURN) {
                 // Read output registers
-                for (reg, slot) in self
-                    .registers
-                    .iter()
-                    .zip(self.stack_slots_output.iter().copied())
-                    .filter_map(|(r, s)| r.zip(s))
+                for (i, slot) in
+                    self.stack_slots_output.iter().enumerate().filter_map(|(i, s)| Some((i, (*s)?)))
                 {
-                    Self::save_register(&mut generated_asm, self.arch, reg, slot);
+                    Self::save_register(
+                        &mut generated_asm,
+                        self.arch,
+                        self.registers[i].unwrap(),
+                        self.operand_reg_class(i),
+                        self.operand_sizes[i],
+                        slot,
+                    );
                 }
 
                 // Restore clobbered registers
@@ -682,14 +981,24 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
                     .zip(self.stack_slots_clobber.iter().copied())
                     .filter_map(|(r, s)| r.zip(s))
                 {
-                    Self::restore_register(&mut generated_asm, self.arch, reg, slot);
+                    Self::restore_register(
+                        &mut generated_asm,
+                        self.arch,
+                        reg,
+                        clobber_save_class(reg),
+                        None,
+                        slot,
+                    );
                 }
 
-                Self::epilogue(&mut generated_asm, self.arch);
+                Self::epilogue(&mut generated_asm, self.arch, emit_cfi);
             } else {
                 Self::epilogue_noreturn(&mut generated_asm, self.arch);
             }
         }
+        if emit_cfi {
+            generated_asm.push_str(".cfi_endproc\n");
+        }
 
         if is_x86 {
             generated_asm.push_str(".att_syntax\n");
@@ -700,7 +1009,12 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
                 writeln!(generated_asm, ".size {name}, .-{name}", name = asm_name).unwrap();
                 generated_asm.push_str(".text\n");
             }
-            BinaryFormat::Macho | BinaryFormat::Coff => {}
+            BinaryFormat::Macho | BinaryFormat::Coff => {
+                if emit_seh {
+                    generated_asm.push_str(".seh_endproc\n");
+                }
+                generated_asm.push_str(".text\n");
+            }
             _ => self
                 .tcx
                 .dcx()
@@ -712,19 +1026,47 @@ fn generate_asm_wrapper(&self, asm_name: &str) -> String {
         generated_asm
     }
 
-    fn prologue(generated_asm: &mut String, arch: InlineAsmArch) {
+    fn prologue(generated_asm: &mut String, arch: InlineAsmArch, emit_seh: bool, emit_cfi: bool) {
         match arch {
             InlineAsmArch::X86_64 => {
                 generated_asm.push_str("    push rbp\n");
+                if emit_seh {
+                    generated_asm.push_str("    .seh_pushreg rbp\n");
+                }
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa_offset 16\n");
+                    generated_asm.push_str("    .cfi_offset rbp, -16\n");
+                }
                 generated_asm.push_str("    mov rbp,rsp\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa_register rbp\n");
+                }
                 generated_asm.push_str("    push rbx\n"); // rbx is callee saved
+                if emit_seh {
+                    generated_asm.push_str("    .seh_pushreg rbx\n");
+                }
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_offset rbx, -24\n");
+                }
                 // rbx is reserved by LLVM for the "base pointer", so rustc doesn't allow using it
                 generated_asm.push_str("    mov rbx,rdi\n");
+                // Keep the stack 16 byte aligned for calls from within the asm block
+                generated_asm.push_str("    sub rsp,8\n");
+                if emit_seh {
+                    generated_asm.push_str("    .seh_stackalloc 8\n");
+                    generated_asm.push_str("    .seh_endprologue\n");
+                }
             }
             InlineAsmArch::AArch64 => {
                 generated_asm.push_str("    stp fp, lr, [sp, #-32]!\n");
                 generated_asm.push_str("    mov fp, sp\n");
                 generated_asm.push_str("    str x19, [sp, #24]\n"); // x19 is callee saved
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa fp, 32\n");
+                    generated_asm.push_str("    .cfi_offset x19, -8\n");
+                    generated_asm.push_str("    .cfi_offset lr, -24\n");
+                    generated_asm.push_str("    .cfi_offset fp, -32\n");
+                }
                 // x19 is reserved by LLVM for the "base pointer", so rustc doesn't allow using it
                 generated_asm.push_str("    mov x19, x0\n");
             }
@@ -732,31 +1074,71 @@ fn prologue(generated_asm: &mut String, arch: InlineAsmArch) {
                 generated_asm.push_str("    addi sp, sp, -16\n");
                 generated_asm.push_str("    sd ra, 8(sp)\n");
                 generated_asm.push_str("    sd s1, 0(sp)\n"); // s1 is callee saved
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa_offset 16\n");
+                    generated_asm.push_str("    .cfi_offset ra, -8\n");
+                    generated_asm.push_str("    .cfi_offset s1, -16\n");
+                }
                 // s1/x9 is reserved by LLVM for the "base pointer", so rustc doesn't allow using it
                 generated_asm.push_str("    mv s1, a0\n");
             }
+            InlineAsmArch::S390x => {
+                // Save r11 and the return address r14 in the register save area of the caller
+                generated_asm.push_str("    stmg %r11, %r15, 88(%r15)\n");
+                if emit_cfi {
+                    // The CFA is 160 bytes above the stack pointer on entry
+                    for (i, reg) in (11..=15).enumerate() {
+                        writeln!(generated_asm, "    .cfi_offset %r{reg}, {}", -72 + i as i32 * 8)
+                            .unwrap();
+                    }
+                }
+                // r11 is reserved by LLVM for the frame pointer, so rustc doesn't allow using it
+                generated_asm.push_str("    lgr %r11, %r2\n");
+                // Allocate a register save area for calls from within the asm block
+                generated_asm.push_str("    aghi %r15, -160\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_adjust_cfa_offset 160\n");
+                }
+            }
             _ => unimplemented!("prologue for {:?}", arch),
         }
     }
 
-    fn epilogue(generated_asm: &mut String, arch: InlineAsmArch) {
+    fn epilogue(generated_asm: &mut String, arch: InlineAsmArch, emit_cfi: bool) {
         match arch {
             InlineAsmArch::X86_64 => {
+                generated_asm.push_str("    add rsp,8\n");
                 generated_asm.push_str("    pop rbx\n");
                 generated_asm.push_str("    pop rbp\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa rsp, 8\n");
+                }
                 generated_asm.push_str("    ret\n");
             }
             InlineAsmArch::AArch64 => {
                 generated_asm.push_str("    ldr x19, [sp, #24]\n");
                 generated_asm.push_str("    ldp fp, lr, [sp], #32\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa sp, 0\n");
+                }
                 generated_asm.push_str("    ret\n");
             }
             InlineAsmArch::RiscV64 => {
                 generated_asm.push_str("    ld s1, 0(sp)\n");
                 generated_asm.push_str("    ld ra, 8(sp)\n");
                 generated_asm.push_str("    addi sp, sp, 16\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa_offset 0\n");
+                }
                 generated_asm.push_str("    ret\n");
             }
+            InlineAsmArch::S390x => {
+                generated_asm.push_str("    lmg %r11, %r15, 248(%r15)\n");
+                if emit_cfi {
+                    generated_asm.push_str("    .cfi_def_cfa_offset 160\n");
+                }
+                generated_asm.push_str("    br %r14\n");
+            }
             _ => unimplemented!("epilogue for {:?}", arch),
         }
     }
@@ -772,97 +1154,216 @@ fn epilogue_noreturn(generated_asm: &mut String, arch: InlineAsmArch) {
             InlineAsmArch::RiscV64 => {
                 generated_asm.push_str("    ebreak\n");
             }
+            InlineAsmArch::S390x => {
+                // Jump into the middle of the instruction, which is an illegal instruction
+                generated_asm.push_str("    j .+2\n");
+            }
             _ => unimplemented!("epilogue_noreturn for {:?}", arch),
         }
     }
 
+    fn operand_reg_class(&self, operand_idx: usize) -> InlineAsmRegClass {
+        match self.operands[operand_idx] {
+            CInlineAsmOperand::In { reg, .. }
+            | CInlineAsmOperand::Out { reg, .. }
+            | CInlineAsmOperand::InOut { reg, .. } => reg.reg_class(),
+            CInlineAsmOperand::Const { .. } | CInlineAsmOperand::Symbol { .. } => unreachable!(),
+        }
+    }
+
+    /// Save `reg` to the stack slot at `offset`. `class` determines how much of the register is
+    /// saved and must be the same register class that was used to allocate the stack slot. `size`
+    /// is the size of the operand value if `reg` belongs to an operand rather than a clobber.
     fn save_register(
         generated_asm: &mut String,
         arch: InlineAsmArch,
         reg: InlineAsmReg,
+        class: InlineAsmRegClass,
+        size: Option<Size>,
         offset: Size,
     ) {
         match arch {
             InlineAsmArch::X86_64 => {
-                match reg {
-                    InlineAsmReg::X86(reg)
-                        if reg as u32 >= X86InlineAsmReg::xmm0 as u32
-                            && reg as u32 <= X86InlineAsmReg::xmm15 as u32 =>
-                    {
-                        // rustc emits x0 rather than xmm0
-                        write!(generated_asm, "    movups [rbx+0x{:x}], ", offset.bytes()).unwrap();
-                        write!(generated_asm, "xmm{}", reg as u32 - X86InlineAsmReg::xmm0 as u32)
-                            .unwrap();
-                    }
-                    _ => {
-                        write!(generated_asm, "    mov [rbx+0x{:x}], ", offset.bytes()).unwrap();
-                        reg.emit(generated_asm, InlineAsmArch::X86_64, None).unwrap();
-                    }
+                if let Some(index) = x86_vector_reg_index(reg) {
+                    let prefix = x86_vector_reg_prefix(class);
+                    write!(
+                        generated_asm,
+                        "    {} [rbx+0x{:x}], {prefix}{index}",
+                        x86_vector_mov(prefix, index),
+                        offset.bytes(),
+                    )
+                    .unwrap();
+                } else {
+                    write!(
+                        generated_asm,
+                        "    {} [rbx+0x{:x}], ",
+                        x86_mov(class, size),
+                        offset.bytes()
+                    )
+                    .unwrap();
+                    reg.emit(generated_asm, InlineAsmArch::X86_64, None).unwrap();
                 }
                 generated_asm.push('\n');
             }
             InlineAsmArch::AArch64 => {
                 generated_asm.push_str("    str ");
-                reg.emit(generated_asm, InlineAsmArch::AArch64, None).unwrap();
+                reg.emit(generated_asm, InlineAsmArch::AArch64, aarch64_reg_modifier(reg)).unwrap();
                 writeln!(generated_asm, ", [x19, 0x{:x}]", offset.bytes()).unwrap();
             }
             InlineAsmArch::RiscV64 => {
-                generated_asm.push_str("    sd ");
+                write!(generated_asm, "    {} ", riscv_store(class, size)).unwrap();
                 reg.emit(generated_asm, InlineAsmArch::RiscV64, None).unwrap();
                 writeln!(generated_asm, ", 0x{:x}(s1)", offset.bytes()).unwrap();
             }
+            InlineAsmArch::S390x => {
+                write!(generated_asm, "    {} ", s390x_store(class)).unwrap();
+                reg.emit(generated_asm, InlineAsmArch::S390x, None).unwrap();
+                writeln!(generated_asm, ", {}(%r11)", offset.bytes()).unwrap();
+            }
             _ => unimplemented!("save_register for {:?}", arch),
         }
     }
 
+    /// Restore `reg` from the stack slot at `offset`. See [`Self::save_register`].
     fn restore_register(
         generated_asm: &mut String,
         arch: InlineAsmArch,
         reg: InlineAsmReg,
+        class: InlineAsmRegClass,
+        size: Option<Size>,
         offset: Size,
     ) {
         match arch {
             InlineAsmArch::X86_64 => {
-                match reg {
-                    InlineAsmReg::X86(reg)
-                        if reg as u32 >= X86InlineAsmReg::xmm0 as u32
-                            && reg as u32 <= X86InlineAsmReg::xmm15 as u32 =>
-                    {
-                        // rustc emits x0 rather than xmm0
-                        write!(
-                            generated_asm,
-                            "    movups xmm{}",
-                            reg as u32 - X86InlineAsmReg::xmm0 as u32
-                        )
+                if let Some(index) = x86_vector_reg_index(reg) {
+                    let prefix = x86_vector_reg_prefix(class);
+                    write!(generated_asm, "    {} {prefix}{index}", x86_vector_mov(prefix, index))
                         .unwrap();
-                    }
-                    _ => {
-                        generated_asm.push_str("    mov ");
-                        reg.emit(generated_asm, InlineAsmArch::X86_64, None).unwrap()
-                    }
+                } else {
+                    write!(generated_asm, "    {} ", x86_mov(class, size)).unwrap();
+                    reg.emit(generated_asm, InlineAsmArch::X86_64, None).unwrap();
                 }
                 writeln!(generated_asm, ", [rbx+0x{:x}]", offset.bytes()).unwrap();
             }
             InlineAsmArch::AArch64 => {
                 generated_asm.push_str("    ldr ");
-                reg.emit(generated_asm, InlineAsmArch::AArch64, None).unwrap();
+                reg.emit(generated_asm, InlineAsmArch::AArch64, aarch64_reg_modifier(reg)).unwrap();
                 writeln!(generated_asm, ", [x19, 0x{:x}]", offset.bytes()).unwrap();
             }
             InlineAsmArch::RiscV64 => {
-                generated_asm.push_str("    ld ");
+                write!(generated_asm, "    {} ", riscv_load(class, size)).unwrap();
                 reg.emit(generated_asm, InlineAsmArch::RiscV64, None).unwrap();
                 writeln!(generated_asm, ", 0x{:x}(s1)", offset.bytes()).unwrap();
             }
+            InlineAsmArch::S390x => {
+                write!(generated_asm, "    {} ", s390x_load(class)).unwrap();
+                reg.emit(generated_asm, InlineAsmArch::S390x, None).unwrap();
+                writeln!(generated_asm, ", {}(%r11)", offset.bytes()).unwrap();
+            }
             _ => unimplemented!("restore_register for {:?}", arch),
         }
     }
 }
 
+/// The register class used for saving and restoring a register clobbered by an asm block.
+fn clobber_save_class(reg: InlineAsmReg) -> InlineAsmRegClass {
+    if x86_vector_reg_index(reg).is_some() {
+        // No ABI preserves more than the lower 128 bits of vector registers across calls. Saving
+        // the whole ymm or zmm register would also require AVX or AVX-512 to be available.
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::xmm_reg)
+    } else {
+        reg.reg_class()
+    }
+}
+
+/// Returns the index of an x86 vector register, independent of whether it is named as xmm, ymm or
+/// zmm register.
+fn x86_vector_reg_index(reg: InlineAsmReg) -> Option<u32> {
+    let InlineAsmReg::X86(_) = reg else {
+        return None;
+    };
+    let name = reg.name();
+    let index = ["xmm", "ymm", "zmm"].iter().find_map(|prefix| name.strip_prefix(prefix))?;
+    index.parse().ok()
+}
+
+/// The name prefix of the part of an x86 vector register accessed by the given register class.
+fn x86_vector_reg_prefix(class: InlineAsmRegClass) -> &'static str {
+    match class {
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::ymm_reg) => "ymm",
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::zmm_reg) => "zmm",
+        _ => "xmm",
+    }
+}
+
+fn x86_vector_mov(prefix: &str, index: u32) -> &'static str {
+    // xmm16-xmm31 can only be accessed using EVEX encoded instructions.
+    if prefix == "xmm" && index < 16 { "movups" } else { "vmovups" }
+}
+
+fn x86_mov(class: InlineAsmRegClass, size: Option<Size>) -> &'static str {
+    match class {
+        // kmovw only requires AVX512F. Wider masks need kmovq, which requires AVX512BW. Mask
+        // registers are never callee-saved, so they are only saved for operands.
+        InlineAsmRegClass::X86(X86InlineAsmRegClass::kreg) => match size {
+            Some(size) if size.bytes() <= 2 => "kmovw",
+            _ => "kmovq",
+        },
+        _ => "mov",
+    }
+}
+
+/// Vector registers are saved and restored as a whole. Their stack slot is large enough for this.
+fn aarch64_reg_modifier(reg: InlineAsmReg) -> Option<char> {
+    match reg.reg_class() {
+        InlineAsmRegClass::AArch64(
+            AArch64InlineAsmRegClass::vreg | AArch64InlineAsmRegClass::vreg_low16,
+        ) => Some('q'),
+        _ => None,
+    }
+}
+
+// f32 operands are accessed as single to get them NaN-boxed. Other float registers are saved as
+// double. Every riscv64 target with float registers has the D extension.
+fn riscv_store(class: InlineAsmRegClass, size: Option<Size>) -> &'static str {
+    match class {
+        InlineAsmRegClass::RiscV(RiscVInlineAsmRegClass::freg) => match size {
+            Some(size) if size.bytes() == 4 => "fsw",
+            _ => "fsd",
+        },
+        _ => "sd",
+    }
+}
+
+fn riscv_load(class: InlineAsmRegClass, size: Option<Size>) -> &'static str {
+    match class {
+        InlineAsmRegClass::RiscV(RiscVInlineAsmRegClass::freg) => match size {
+            Some(size) if size.bytes() == 4 => "flw",
+            _ => "fld",
+        },
+        _ => "ld",
+    }
+}
+
+fn s390x_store(class: InlineAsmRegClass) -> &'static str {
+    match class {
+        InlineAsmRegClass::S390x(S390xInlineAsmRegClass::freg) => "std",
+        _ => "stg",
+    }
+}
+
+fn s390x_load(class: InlineAsmRegClass) -> &'static str {
+    match class {
+        InlineAsmRegClass::S390x(S390xInlineAsmRegClass::freg) => "ld",
+        _ => "lg",
+    }
+}
+
 fn call_inline_asm<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     asm_name: &str,
     slot_size: Size,
-    inputs: Vec<(Size, Value)>,
+    inputs: Vec<(Size, CValue<'tcx>)>,
     outputs: Vec<(Size, CPlace<'tcx>)>,
 ) {
     let stack_slot = fx.create_stack_slot(u32::try_from(slot_size.bytes()).unwrap(), 16);
@@ -881,29 +1382,22 @@ fn call_inline_asm<'tcx>(
     }
 
     for (offset, value) in inputs {
-        stack_slot.offset(fx, i32::try_from(offset.bytes()).unwrap().into()).store(
-            fx,
-            value,
-            MemFlags::trusted(),
+        let dest = CPlace::for_ptr(
+            stack_slot.offset(fx, i32::try_from(offset.bytes()).unwrap().into()),
+            value.layout(),
         );
+        dest.write_cvalue(fx, value);
     }
 
     let stack_slot_addr = stack_slot.get_addr(fx);
     fx.bcx.ins().call(inline_asm_func, &[stack_slot_addr]);
 
     for (offset, place) in outputs {
-        let ty = if place.layout().ty.is_simd() {
-            let (lane_count, lane_type) = place.layout().ty.simd_size_and_type(fx.tcx);
-            asm_clif_type(fx, lane_type).unwrap().by(lane_count.try_into().unwrap()).unwrap()
-        } else {
-            asm_clif_type(fx, place.layout().ty).unwrap()
-        };
-        let value = stack_slot.offset(fx, i32::try_from(offset.bytes()).unwrap().into()).load(
-            fx,
-            ty,
-            MemFlags::trusted(),
+        let value = CValue::by_ref(
+            stack_slot.offset(fx, i32::try_from(offset.bytes()).unwrap().into()),
+            place.layout(),
         );
-        place.write_cvalue(fx, CValue::by_val(value, place.layout()));
+        place.write_cvalue(fx, value);
     }
 }
 
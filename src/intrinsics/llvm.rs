@@ -54,6 +54,31 @@ pub(crate) fn codegen_llvm_intrinsic_call<'tcx>(
             );
         }
 
+        _ if intrinsic.starts_with("llvm.fshl.") || intrinsic.starts_with("llvm.fshr.") => {
+            intrinsic_args!(fx, args => (a, b, c); intrinsic);
+            let left = intrinsic.starts_with("llvm.fshl.");
+
+            if a.layout().ty.is_simd() {
+                simd_trio_for_each_lane(
+                    fx,
+                    a,
+                    b,
+                    c,
+                    ret,
+                    &|fx, _lane_ty, _res_lane_ty, lane_a, lane_b, lane_c| {
+                        crate::num::codegen_funnel_shift(fx, left, lane_a, lane_b, lane_c)
+                    },
+                );
+            } else {
+                let layout = a.layout();
+                let a = a.load_scalar(fx);
+                let b = b.load_scalar(fx);
+                let c = c.load_scalar(fx);
+                let res = crate::num::codegen_funnel_shift(fx, left, a, b, c);
+                ret.write_cvalue(fx, CValue::by_val(res, layout));
+            }
+        }
+
         _ => {
             fx.tcx
                 .dcx()
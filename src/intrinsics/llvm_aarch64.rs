@@ -1,5 +1,9 @@
 //! Emulate AArch64 LLVM intrinsics
 
+use rustc_ast::ast::{InlineAsmOptions, InlineAsmTemplatePiece};
+use rustc_target::asm::*;
+
+use crate::inline_asm::{CInlineAsmOperand, codegen_inline_asm_inner};
 use crate::intrinsics::*;
 use crate::prelude::*;
 
@@ -279,34 +283,343 @@ pub(crate) fn codegen_aarch64_llvm_intrinsic_call<'tcx>(
             );
         }
 
-        // FIXME generalize vector types
-        "llvm.aarch64.neon.tbl1.v8i8" => {
-            intrinsic_args!(fx, args => (t, idx); intrinsic);
+        _ if intrinsic.starts_with("llvm.aarch64.neon.tbl")
+            || intrinsic.starts_with("llvm.aarch64.neon.tbx") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vqtbl2q_u8
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vqtbx2q_u8
+            // tbl{1,2,3,4}(table.., idx) and tbx{1,2,3,4}(fallback, table.., idx)
+            let is_tbx = intrinsic.starts_with("llvm.aarch64.neon.tbx");
+            let table_count =
+                usize::from(intrinsic.as_bytes()["llvm.aarch64.neon.tbl".len()] - b'0');
+            let table_start = if is_tbx { 1 } else { 0 };
+            if args.len() != table_start + table_count + 1 {
+                bug_on_incorrect_arg_count(intrinsic);
+            }
+
+            let fallback = if is_tbx { Some(codegen_operand(fx, &args[0].node)) } else { None };
+            let idx = codegen_operand(fx, &args[table_start + table_count].node);
 
+            // Store all tables next to each other to allow indexing them as a single table.
+            let table = fx.create_stack_slot(u32::try_from(table_count * 16).unwrap(), 16);
+            for (i, table_arg) in args[table_start..table_start + table_count].iter().enumerate() {
+                let table_part = codegen_operand(fx, &table_arg.node).load_scalar(fx);
+                table.offset_i64(fx, i as i64 * 16).store(fx, table_part, MemFlags::trusted());
+            }
+            let table_base = table.get_addr(fx);
+
+            let (lane_count, _lane_ty) = idx.layout().ty.simd_size_and_type(fx.tcx);
             let zero = fx.bcx.ins().iconst(types::I8, 0);
-            for i in 0..8 {
+            for i in 0..lane_count {
                 let idx_lane = idx.value_lane(fx, i).load_scalar(fx);
-                let is_zero =
-                    fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, idx_lane, 16);
-                let t_idx = fx.bcx.ins().uextend(fx.pointer_type, idx_lane);
-                let t_lane = t.value_lane_dyn(fx, t_idx).load_scalar(fx);
-                let res = fx.bcx.ins().select(is_zero, zero, t_lane);
+                let is_out_of_range = fx.bcx.ins().icmp_imm(
+                    IntCC::UnsignedGreaterThanOrEqual,
+                    idx_lane,
+                    table_count as i64 * 16,
+                );
+                let idx_lane = fx.bcx.ins().select(is_out_of_range, zero, idx_lane);
+                let offset = fx.bcx.ins().uextend(fx.pointer_type, idx_lane);
+                let addr = fx.bcx.ins().iadd(table_base, offset);
+                let table_lane = fx.bcx.ins().load(types::I8, MemFlags::trusted(), addr, 0);
+                let out_of_range_lane = match fallback {
+                    Some(fallback) => fallback.value_lane(fx, i).load_scalar(fx),
+                    None => zero,
+                };
+                let res = fx.bcx.ins().select(is_out_of_range, out_of_range_lane, table_lane);
                 ret.place_lane(fx, i).to_ptr().store(fx, res, MemFlags::trusted());
             }
         }
-        "llvm.aarch64.neon.tbl1.v16i8" => {
-            intrinsic_args!(fx, args => (t, idx); intrinsic);
 
-            let zero = fx.bcx.ins().iconst(types::I8, 0);
-            for i in 0..16 {
-                let idx_lane = idx.value_lane(fx, i).load_scalar(fx);
-                let is_zero =
-                    fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, idx_lane, 16);
-                let t_idx = fx.bcx.ins().uextend(fx.pointer_type, idx_lane);
-                let t_lane = t.value_lane_dyn(fx, t_idx).load_scalar(fx);
-                let res = fx.bcx.ins().select(is_zero, zero, t_lane);
-                ret.place_lane(fx, i).to_ptr().store(fx, res, MemFlags::trusted());
+        _ if intrinsic.starts_with("llvm.aarch64.neon.smull.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.umull.v") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vmull_s8
+            intrinsic_args!(fx, args => (x, y); intrinsic);
+
+            let signed = intrinsic.starts_with("llvm.aarch64.neon.smull.v");
+            simd_pair_for_each_lane(fx, x, y, ret, &|fx, _lane_ty, res_lane_ty, x_lane, y_lane| {
+                let res_ty = fx.clif_type(res_lane_ty).unwrap();
+                let x_lane = clif_intcast(fx, x_lane, res_ty, signed);
+                let y_lane = clif_intcast(fx, y_lane, res_ty, signed);
+                fx.bcx.ins().imul(x_lane, y_lane)
+            });
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.sqdmulh.v") => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vqdmulhq_s16
+            intrinsic_args!(fx, args => (x, y); intrinsic);
+
+            simd_pair_for_each_lane(
+                fx,
+                x,
+                y,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, x_lane, y_lane| {
+                    let lane_ty = fx.bcx.func.dfg.value_type(x_lane);
+                    let wide_ty = lane_ty.double_width().unwrap();
+                    let x_lane = fx.bcx.ins().sextend(wide_ty, x_lane);
+                    let y_lane = fx.bcx.ins().sextend(wide_ty, y_lane);
+                    let mul = fx.bcx.ins().imul(x_lane, y_lane);
+                    // (2 * x * y) >> bits
+                    let res = fx.bcx.ins().sshr_imm(mul, i64::from(lane_ty.bits() - 1));
+                    // Only MIN * MIN overflows.
+                    let max = fx.bcx.ins().iconst(wide_ty, (1i64 << (lane_ty.bits() - 1)) - 1);
+                    let res = fx.bcx.ins().smin(res, max);
+                    fx.bcx.ins().ireduce(lane_ty, res)
+                },
+            );
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.uhadd.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.shadd.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.urhadd.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.srhadd.v") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vhaddq_u8
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vrhaddq_u8
+            intrinsic_args!(fx, args => (x, y); intrinsic);
+
+            let signed = intrinsic.as_bytes()["llvm.aarch64.neon.".len()] == b's';
+            let rounding = intrinsic.contains("rhadd");
+            simd_pair_for_each_lane(
+                fx,
+                x,
+                y,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, x_lane, y_lane| {
+                    let lane_ty = fx.bcx.func.dfg.value_type(x_lane);
+                    let wide_ty = lane_ty.double_width().unwrap();
+                    let x_lane = clif_intcast(fx, x_lane, wide_ty, signed);
+                    let y_lane = clif_intcast(fx, y_lane, wide_ty, signed);
+                    let sum = fx.bcx.ins().iadd(x_lane, y_lane);
+                    let sum = if rounding { fx.bcx.ins().iadd_imm(sum, 1) } else { sum };
+                    let res = fx.bcx.ins().sshr_imm(sum, 1);
+                    fx.bcx.ins().ireduce(lane_ty, res)
+                },
+            );
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.uabd.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.sabd.v") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vabdq_u8
+            intrinsic_args!(fx, args => (x, y); intrinsic);
+
+            let gt_cc = if intrinsic.starts_with("llvm.aarch64.neon.sabd.v") {
+                IntCC::SignedGreaterThan
+            } else {
+                IntCC::UnsignedGreaterThan
+            };
+            simd_pair_for_each_lane(
+                fx,
+                x,
+                y,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, x_lane, y_lane| {
+                    let gt = fx.bcx.ins().icmp(gt_cc, x_lane, y_lane);
+                    let x_minus_y = fx.bcx.ins().isub(x_lane, y_lane);
+                    let y_minus_x = fx.bcx.ins().isub(y_lane, x_lane);
+                    fx.bcx.ins().select(gt, x_minus_y, y_minus_x)
+                },
+            );
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.uaddlv.")
+            || intrinsic.starts_with("llvm.aarch64.neon.saddlv.") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vaddlvq_u8
+            intrinsic_args!(fx, args => (v); intrinsic);
+
+            let signed = intrinsic.starts_with("llvm.aarch64.neon.saddlv.");
+            let ret_ty = fx.clif_type(ret.layout().ty).unwrap();
+            let (lane_count, _lane_ty) = v.layout().ty.simd_size_and_type(fx.tcx);
+            let mut res = fx.bcx.ins().iconst(ret_ty, 0);
+            for i in 0..lane_count {
+                let lane = v.value_lane(fx, i).load_scalar(fx);
+                let lane = clif_intcast(fx, lane, ret_ty, signed);
+                res = fx.bcx.ins().iadd(res, lane);
             }
+            ret.write_cvalue(fx, CValue::by_val(res, ret.layout()));
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.sqxtn.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.uqxtn.v")
+            || intrinsic.starts_with("llvm.aarch64.neon.sqxtun.v") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vqmovn_s16
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vqmovun_s16
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            let src_signed = !intrinsic.starts_with("llvm.aarch64.neon.uqxtn.v");
+            let dst_signed = intrinsic.starts_with("llvm.aarch64.neon.sqxtn.v");
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, res_lane_ty, lane| {
+                let lane_ty = fx.bcx.func.dfg.value_type(lane);
+                let res_ty = fx.clif_type(res_lane_ty).unwrap();
+                let (min, max) = type_min_max_value(&mut fx.bcx, res_ty, dst_signed);
+                let min = clif_intcast(fx, min, lane_ty, dst_signed);
+                let max = clif_intcast(fx, max, lane_ty, dst_signed);
+                let lane = if src_signed {
+                    let lane = fx.bcx.ins().smax(lane, min);
+                    if dst_signed {
+                        fx.bcx.ins().smin(lane, max)
+                    } else {
+                        fx.bcx.ins().umin(lane, max)
+                    }
+                } else {
+                    fx.bcx.ins().umin(lane, max)
+                };
+                fx.bcx.ins().ireduce(res_ty, lane)
+            });
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.ld1x") => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vld1q_u8_x2
+            intrinsic_args!(fx, args => (ptr); intrinsic);
+
+            // The vectors are stored consecutively in memory just like in the returned struct.
+            let ptr = ptr.load_scalar(fx);
+            ret.write_cvalue(fx, CValue::by_ref(Pointer::new(ptr), ret.layout()));
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.st1x") => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vst1q_u8_x2
+            let (ptr, vectors) = args.split_last().unwrap();
+            let ptr = codegen_operand(fx, &ptr.node).load_scalar(fx);
+
+            let mut offset = 0;
+            for vector in vectors {
+                let vector = codegen_operand(fx, &vector.node);
+                let size = vector.layout().size.bytes();
+                let vector = vector.load_scalar(fx);
+                Pointer::new(ptr).offset_i64(fx, offset).store(fx, vector, MemFlags::trusted());
+                offset += size as i64;
+            }
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.ld2.")
+            || intrinsic.starts_with("llvm.aarch64.neon.ld3.")
+            || intrinsic.starts_with("llvm.aarch64.neon.ld4.") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vld2q_u8
+            intrinsic_args!(fx, args => (ptr); intrinsic);
+            let ptr = ptr.load_scalar(fx);
+
+            // Load the interleaved elements into separate vectors.
+            let vector_count =
+                usize::from(intrinsic.as_bytes()["llvm.aarch64.neon.ld".len()] - b'0');
+            let vector_layout = ret.place_field(fx, FieldIdx::new(0)).layout();
+            let (lane_count, lane_ty) = vector_layout.ty.simd_size_and_type(fx.tcx);
+            let lane_layout = fx.layout_of(lane_ty);
+            let lane_clif_ty = fx.clif_type(lane_ty).unwrap();
+            for vector_idx in 0..vector_count {
+                let vector = ret.place_field(fx, FieldIdx::new(vector_idx));
+                for lane_idx in 0..lane_count {
+                    let elem_idx = lane_idx * vector_count as u64 + vector_idx as u64;
+                    let offset = (elem_idx * lane_layout.size.bytes()) as i64;
+                    let lane = Pointer::new(ptr).offset_i64(fx, offset).load(
+                        fx,
+                        lane_clif_ty,
+                        MemFlags::trusted(),
+                    );
+                    vector
+                        .place_lane(fx, lane_idx)
+                        .write_cvalue(fx, CValue::by_val(lane, lane_layout));
+                }
+            }
+        }
+
+        _ if intrinsic.starts_with("llvm.aarch64.neon.st2.")
+            || intrinsic.starts_with("llvm.aarch64.neon.st3.")
+            || intrinsic.starts_with("llvm.aarch64.neon.st4.") =>
+        {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vst2q_u8
+            let (ptr, vectors) = args.split_last().unwrap();
+            let ptr = codegen_operand(fx, &ptr.node).load_scalar(fx);
+
+            // Interleave the elements of all vectors when storing them.
+            let vector_count = vectors.len();
+            for (vector_idx, vector) in vectors.iter().enumerate() {
+                let vector = codegen_operand(fx, &vector.node);
+                let (lane_count, lane_ty) = vector.layout().ty.simd_size_and_type(fx.tcx);
+                let lane_size = fx.layout_of(lane_ty).size.bytes();
+                for lane_idx in 0..lane_count {
+                    let lane = vector.value_lane(fx, lane_idx).load_scalar(fx);
+                    let elem_idx = lane_idx * vector_count as u64 + vector_idx as u64;
+                    let offset = (elem_idx * lane_size) as i64;
+                    Pointer::new(ptr).offset_i64(fx, offset).store(fx, lane, MemFlags::trusted());
+                }
+            }
+        }
+
+        "llvm.aarch64.crypto.aese"
+        | "llvm.aarch64.crypto.aesd"
+        | "llvm.aarch64.crypto.aesmc"
+        | "llvm.aarch64.crypto.aesimc"
+        | "llvm.aarch64.crypto.sha1c"
+        | "llvm.aarch64.crypto.sha1p"
+        | "llvm.aarch64.crypto.sha1m"
+        | "llvm.aarch64.crypto.sha1su0"
+        | "llvm.aarch64.crypto.sha1su1"
+        | "llvm.aarch64.crypto.sha256h"
+        | "llvm.aarch64.crypto.sha256h2"
+        | "llvm.aarch64.crypto.sha256su0"
+        | "llvm.aarch64.crypto.sha256su1" => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vaeseq_u8
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vsha256hq_u32
+            let (feature, asm) = match intrinsic {
+                "llvm.aarch64.crypto.aese" => ("aes", "aese v0.16b, v1.16b"),
+                "llvm.aarch64.crypto.aesd" => ("aes", "aesd v0.16b, v1.16b"),
+                "llvm.aarch64.crypto.aesmc" => ("aes", "aesmc v0.16b, v0.16b"),
+                "llvm.aarch64.crypto.aesimc" => ("aes", "aesimc v0.16b, v0.16b"),
+                "llvm.aarch64.crypto.sha1c" => ("sha2", "sha1c q0, s1, v2.4s"),
+                "llvm.aarch64.crypto.sha1p" => ("sha2", "sha1p q0, s1, v2.4s"),
+                "llvm.aarch64.crypto.sha1m" => ("sha2", "sha1m q0, s1, v2.4s"),
+                "llvm.aarch64.crypto.sha1su0" => ("sha2", "sha1su0 v0.4s, v1.4s, v2.4s"),
+                "llvm.aarch64.crypto.sha1su1" => ("sha2", "sha1su1 v0.4s, v1.4s"),
+                "llvm.aarch64.crypto.sha256h" => ("sha2", "sha256h q0, q1, v2.4s"),
+                "llvm.aarch64.crypto.sha256h2" => ("sha2", "sha256h2 q0, q1, v2.4s"),
+                "llvm.aarch64.crypto.sha256su0" => ("sha2", "sha256su0 v0.4s, v1.4s"),
+                "llvm.aarch64.crypto.sha256su1" => ("sha2", "sha256su1 v0.4s, v1.4s, v2.4s"),
+                _ => unreachable!(),
+            };
+            let args = args.iter().map(|arg| codegen_operand(fx, &arg.node)).collect::<Vec<_>>();
+            codegen_crypto_asm(fx, feature, asm, &args, ret);
+        }
+
+        "llvm.aarch64.crypto.sha1h" => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vsha1h_u32
+            intrinsic_args!(fx, args => (hash_e); intrinsic);
+
+            let hash_e = hash_e.load_scalar(fx);
+            let res = fx.bcx.ins().rotl_imm(hash_e, 30);
+            ret.write_cvalue(fx, CValue::by_val(res, ret.layout()));
+        }
+
+        "llvm.aarch64.neon.pmull64" => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vmull_p64
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+
+            codegen_crypto_asm(fx, "aes", "pmull v0.1q, v0.1d, v1.1d", &[a, b], ret);
+        }
+
+        "llvm.aarch64.neon.vcvthf2fp" => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vcvt_f32_f16
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, lane| {
+                let lane = codegen_bitcast(fx, types::F16, lane);
+                crate::codegen_f16_f128::f16_to_f32(fx, lane)
+            });
+        }
+
+        "llvm.aarch64.neon.vcvtfp2hf" => {
+            // https://developer.arm.com/architectures/instruction-sets/intrinsics/#q=vcvt_f16_f32
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, lane| {
+                let res = crate::codegen_f16_f128::f32_to_f16(fx, lane);
+                codegen_bitcast(fx, types::I16, res)
+            });
         }
 
         /*
@@ -373,3 +686,34 @@ pub(crate) fn codegen_aarch64_llvm_intrinsic_call<'tcx>(
     let ret_block = fx.get_block(dest);
     fx.bcx.ins().jump(ret_block, &[]);
 }
+
+/// Cranelift doesn't have instructions for the AES and SHA extensions, so use inline asm. The
+/// first input is passed in `v0` which also holds the result, the others in `v1` and `v2`.
+fn codegen_crypto_asm<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    feature: &str,
+    asm: &str,
+    args: &[CValue<'tcx>],
+    ret: CPlace<'tcx>,
+) {
+    let regs = [AArch64InlineAsmReg::v0, AArch64InlineAsmReg::v1, AArch64InlineAsmReg::v2];
+    let mut operands = vec![CInlineAsmOperand::InOut {
+        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::AArch64(regs[0])),
+        _late: true,
+        in_value: args[0],
+        out_place: Some(ret),
+    }];
+    for (&reg, &value) in regs[1..].iter().zip(&args[1..]) {
+        operands.push(CInlineAsmOperand::In {
+            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::AArch64(reg)),
+            value,
+        });
+    }
+
+    codegen_inline_asm_inner(
+        fx,
+        &[InlineAsmTemplatePiece::String(format!(".arch_extension {feature}\n{asm}").into())],
+        &operands,
+        InlineAsmOptions::NOSTACK | InlineAsmOptions::PURE | InlineAsmOptions::NOMEM,
+    );
+}
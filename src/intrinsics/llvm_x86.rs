@@ -29,8 +29,6 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
         "llvm.x86.xgetbv" => {
             intrinsic_args!(fx, args => (xcr_no); intrinsic);
 
-            let xcr_no = xcr_no.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String(
@@ -195,113 +193,29 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
             );
         }
 
-        "llvm.x86.sse.cmp.ps" | "llvm.x86.sse2.cmp.pd" => {
+        "llvm.x86.sse.cmp.ps"
+        | "llvm.x86.sse2.cmp.pd"
+        | "llvm.x86.avx.cmp.ps.256"
+        | "llvm.x86.avx.cmp.pd.256" => {
             let (x, y, kind) = match args {
                 [x, y, kind] => (x, y, kind),
                 _ => bug!("wrong number of args for intrinsic {intrinsic}"),
             };
             let x = codegen_operand(fx, &x.node);
             let y = codegen_operand(fx, &y.node);
-            let kind = match &kind.node {
-                Operand::Constant(const_) => crate::constant::eval_mir_constant(fx, const_).0,
-                Operand::Copy(_) | Operand::Move(_) => unreachable!("{kind:?}"),
-            };
-
-            let flt_cc = match kind
-                .try_to_bits(Size::from_bytes(1))
-                .unwrap_or_else(|| panic!("kind not scalar: {:?}", kind))
-                .try_into()
-                .unwrap()
-            {
-                _CMP_EQ_OQ | _CMP_EQ_OS => FloatCC::Equal,
-                _CMP_LT_OS | _CMP_LT_OQ => FloatCC::LessThan,
-                _CMP_LE_OS | _CMP_LE_OQ => FloatCC::LessThanOrEqual,
-                _CMP_UNORD_Q | _CMP_UNORD_S => FloatCC::Unordered,
-                _CMP_NEQ_UQ | _CMP_NEQ_US => FloatCC::NotEqual,
-                _CMP_NLT_US | _CMP_NLT_UQ => FloatCC::UnorderedOrGreaterThanOrEqual,
-                _CMP_NLE_US | _CMP_NLE_UQ => FloatCC::UnorderedOrGreaterThan,
-                _CMP_ORD_Q | _CMP_ORD_S => FloatCC::Ordered,
-                _CMP_EQ_UQ | _CMP_EQ_US => FloatCC::UnorderedOrEqual,
-                _CMP_NGE_US | _CMP_NGE_UQ => FloatCC::UnorderedOrLessThan,
-                _CMP_NGT_US | _CMP_NGT_UQ => FloatCC::UnorderedOrLessThanOrEqual,
-                _CMP_FALSE_OQ | _CMP_FALSE_OS => todo!(),
-                _CMP_NEQ_OQ | _CMP_NEQ_OS => FloatCC::OrderedNotEqual,
-                _CMP_GE_OS | _CMP_GE_OQ => FloatCC::GreaterThanOrEqual,
-                _CMP_GT_OS | _CMP_GT_OQ => FloatCC::GreaterThan,
-                _CMP_TRUE_UQ | _CMP_TRUE_US => todo!(),
-
-                kind => unreachable!("kind {:?}", kind),
-            };
+            let kind = crate::constant::mir_operand_get_const_val(fx, &kind.node)
+                .expect("llvm.x86.*.cmp.* kind not const");
 
-            // Copied from stdarch
-            /// Equal (ordered, non-signaling)
-            const _CMP_EQ_OQ: i32 = 0x00;
-            /// Less-than (ordered, signaling)
-            const _CMP_LT_OS: i32 = 0x01;
-            /// Less-than-or-equal (ordered, signaling)
-            const _CMP_LE_OS: i32 = 0x02;
-            /// Unordered (non-signaling)
-            const _CMP_UNORD_Q: i32 = 0x03;
-            /// Not-equal (unordered, non-signaling)
-            const _CMP_NEQ_UQ: i32 = 0x04;
-            /// Not-less-than (unordered, signaling)
-            const _CMP_NLT_US: i32 = 0x05;
-            /// Not-less-than-or-equal (unordered, signaling)
-            const _CMP_NLE_US: i32 = 0x06;
-            /// Ordered (non-signaling)
-            const _CMP_ORD_Q: i32 = 0x07;
-            /// Equal (unordered, non-signaling)
-            const _CMP_EQ_UQ: i32 = 0x08;
-            /// Not-greater-than-or-equal (unordered, signaling)
-            const _CMP_NGE_US: i32 = 0x09;
-            /// Not-greater-than (unordered, signaling)
-            const _CMP_NGT_US: i32 = 0x0a;
-            /// False (ordered, non-signaling)
-            const _CMP_FALSE_OQ: i32 = 0x0b;
-            /// Not-equal (ordered, non-signaling)
-            const _CMP_NEQ_OQ: i32 = 0x0c;
-            /// Greater-than-or-equal (ordered, signaling)
-            const _CMP_GE_OS: i32 = 0x0d;
-            /// Greater-than (ordered, signaling)
-            const _CMP_GT_OS: i32 = 0x0e;
-            /// True (unordered, non-signaling)
-            const _CMP_TRUE_UQ: i32 = 0x0f;
-            /// Equal (ordered, signaling)
-            const _CMP_EQ_OS: i32 = 0x10;
-            /// Less-than (ordered, non-signaling)
-            const _CMP_LT_OQ: i32 = 0x11;
-            /// Less-than-or-equal (ordered, non-signaling)
-            const _CMP_LE_OQ: i32 = 0x12;
-            /// Unordered (signaling)
-            const _CMP_UNORD_S: i32 = 0x13;
-            /// Not-equal (unordered, signaling)
-            const _CMP_NEQ_US: i32 = 0x14;
-            /// Not-less-than (unordered, non-signaling)
-            const _CMP_NLT_UQ: i32 = 0x15;
-            /// Not-less-than-or-equal (unordered, non-signaling)
-            const _CMP_NLE_UQ: i32 = 0x16;
-            /// Ordered (signaling)
-            const _CMP_ORD_S: i32 = 0x17;
-            /// Equal (unordered, signaling)
-            const _CMP_EQ_US: i32 = 0x18;
-            /// Not-greater-than-or-equal (unordered, non-signaling)
-            const _CMP_NGE_UQ: i32 = 0x19;
-            /// Not-greater-than (unordered, non-signaling)
-            const _CMP_NGT_UQ: i32 = 0x1a;
-            /// False (ordered, signaling)
-            const _CMP_FALSE_OS: i32 = 0x1b;
-            /// Not-equal (ordered, signaling)
-            const _CMP_NEQ_OS: i32 = 0x1c;
-            /// Greater-than-or-equal (ordered, non-signaling)
-            const _CMP_GE_OQ: i32 = 0x1d;
-            /// Greater-than (ordered, non-signaling)
-            const _CMP_GT_OQ: i32 = 0x1e;
-            /// True (unordered, signaling)
-            const _CMP_TRUE_US: i32 = 0x1f;
+            let kind = i32::from(kind.to_u8());
+            let flt_cc = float_cmp_predicate_to_floatcc(kind);
 
             simd_pair_for_each_lane(fx, x, y, ret, &|fx, lane_ty, res_lane_ty, x_lane, y_lane| {
-                let res_lane = match lane_ty.kind() {
-                    ty::Float(_) => fx.bcx.ins().fcmp(flt_cc, x_lane, y_lane),
+                let res_lane = match (lane_ty.kind(), flt_cc) {
+                    (ty::Float(_), Some(flt_cc)) => fx.bcx.ins().fcmp(flt_cc, x_lane, y_lane),
+                    // _CMP_FALSE_* and _CMP_TRUE_* respectively
+                    (ty::Float(_), None) => {
+                        fx.bcx.ins().iconst(types::I8, i64::from(kind & 0xf == 0xf))
+                    }
                     _ => unreachable!("{:?}", lane_ty),
                 };
                 bool_to_zero_or_max_uint(fx, res_lane_ty, res_lane)
@@ -340,8 +254,9 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
                 }
             }
         }
-        "llvm.x86.avx2.permd" => {
+        "llvm.x86.avx2.permd" | "llvm.x86.avx2.permps" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_permutevar8x32_epi32
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_permutevar8x32_ps
             intrinsic_args!(fx, args => (a, idx); intrinsic);
 
             for j in 0..=7 {
@@ -355,6 +270,77 @@ pub(crate) fn codegen_x86_llvm_intrinsic_call<'tcx>(
                 );
             }
         }
+        _ if intrinsic.starts_with("llvm.x86.avx512.permvar.") => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm512_permutexvar_epi32
+            intrinsic_args!(fx, args => (a, idx); intrinsic);
+
+            let (lane_count, lane_ty) = a.layout().ty.simd_size_and_type(fx.tcx);
+            let lane_layout = fx.layout_of(lane_ty);
+            for j in 0..lane_count {
+                let index = idx.value_lane(fx, j).load_scalar(fx);
+                let index = fx.bcx.ins().band_imm(index, lane_count as i64 - 1);
+                let index = clif_intcast(fx, index, fx.pointer_type, false);
+                let value = a.value_lane_dyn(fx, index).load_scalar(fx);
+                ret.place_lane(fx, j).write_cvalue(fx, CValue::by_val(value, lane_layout));
+            }
+        }
+        _ if intrinsic.starts_with("llvm.x86.avx512.vpermi2var.") => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm512_permutex2var_epi32
+            intrinsic_args!(fx, args => (a, idx, b); intrinsic);
+
+            let (lane_count, lane_ty) = a.layout().ty.simd_size_and_type(fx.tcx);
+            let lane_layout = fx.layout_of(lane_ty);
+            for j in 0..lane_count {
+                let index = idx.value_lane(fx, j).load_scalar(fx);
+                let from_b = fx.bcx.ins().band_imm(index, lane_count as i64);
+                let index = fx.bcx.ins().band_imm(index, lane_count as i64 - 1);
+                let index = clif_intcast(fx, index, fx.pointer_type, false);
+                let a_value = a.value_lane_dyn(fx, index).load_scalar(fx);
+                let b_value = b.value_lane_dyn(fx, index).load_scalar(fx);
+                let value = fx.bcx.ins().select(from_b, b_value, a_value);
+                ret.place_lane(fx, j).write_cvalue(fx, CValue::by_val(value, lane_layout));
+            }
+        }
+        "llvm.x86.avx512.mask.cmp.ps.128"
+        | "llvm.x86.avx512.mask.cmp.ps.256"
+        | "llvm.x86.avx512.mask.cmp.ps.512"
+        | "llvm.x86.avx512.mask.cmp.pd.128"
+        | "llvm.x86.avx512.mask.cmp.pd.256"
+        | "llvm.x86.avx512.mask.cmp.pd.512" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm512_mask_cmp_ps_mask
+            // The 512bit variants have an additional argument to suppress exceptions, which can be
+            // ignored as Cranelift never raises floating point exceptions.
+            let (a, b, kind, mask) = match args {
+                [a, b, kind, mask] | [a, b, kind, mask, _] => (a, b, kind, mask),
+                _ => bug!("wrong number of args for intrinsic {intrinsic}"),
+            };
+            let a = codegen_operand(fx, &a.node);
+            let b = codegen_operand(fx, &b.node);
+            let kind = crate::constant::mir_operand_get_const_val(fx, &kind.node)
+                .expect("llvm.x86.avx512.mask.cmp.* kind not const");
+            let mask = codegen_operand(fx, &mask.node).load_scalar(fx);
+
+            let kind = kind.to_i32();
+            let flt_cc = float_cmp_predicate_to_floatcc(kind);
+
+            let (lane_count, _lane_ty) = a.layout().ty.simd_size_and_type(fx.tcx);
+            let res_ty = fx.clif_type(ret.layout().ty).unwrap();
+            let mut res = fx.bcx.ins().iconst(res_ty, 0);
+            for i in 0..lane_count {
+                let a_lane = a.value_lane(fx, i).load_scalar(fx);
+                let b_lane = b.value_lane(fx, i).load_scalar(fx);
+                let res_lane = match flt_cc {
+                    Some(flt_cc) => fx.bcx.ins().fcmp(flt_cc, a_lane, b_lane),
+                    // _CMP_FALSE_* and _CMP_TRUE_* respectively
+                    None => fx.bcx.ins().iconst(types::I8, i64::from(kind & 0xf == 0xf)),
+                };
+                let res_lane = fx.bcx.ins().uextend(res_ty, res_lane);
+                let res_lane = fx.bcx.ins().ishl_imm(res_lane, i as i64);
+                res = fx.bcx.ins().bor(res, res_lane);
+            }
+            let res = fx.bcx.ins().band(res, mask);
+            ret.write_cvalue(fx, CValue::by_val(res, ret.layout()));
+        }
         "llvm.x86.avx2.vperm2i128"
         | "llvm.x86.avx.vperm2f128.ps.256"
         | "llvm.x86.avx.vperm2f128.pd.256" => {
@@ -413,6 +399,101 @@ fn select4(
                 MemFlags::trusted(),
             );
         }
+        "llvm.x86.ssse3.phadd.w.128"
+        | "llvm.x86.ssse3.phadd.d.128"
+        | "llvm.x86.ssse3.phadd.sw.128"
+        | "llvm.x86.ssse3.phsub.w.128"
+        | "llvm.x86.ssse3.phsub.d.128"
+        | "llvm.x86.ssse3.phsub.sw.128"
+        | "llvm.x86.avx2.phadd.w"
+        | "llvm.x86.avx2.phadd.d"
+        | "llvm.x86.avx2.phadd.sw"
+        | "llvm.x86.avx2.phsub.w"
+        | "llvm.x86.avx2.phsub.d"
+        | "llvm.x86.avx2.phsub.sw" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_hadd_epi16
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_hadds_epi16
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_hsub_epi32
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+
+            let is_sub = intrinsic.contains(".phsub.");
+            let is_saturating = intrinsic.contains(".sw");
+            horizontal_op_per_128bit_lane(fx, a, b, ret, &|fx, lhs, rhs| {
+                if is_saturating {
+                    let lhs = fx.bcx.ins().sextend(types::I32, lhs);
+                    let rhs = fx.bcx.ins().sextend(types::I32, rhs);
+                    let res = if is_sub {
+                        fx.bcx.ins().isub(lhs, rhs)
+                    } else {
+                        fx.bcx.ins().iadd(lhs, rhs)
+                    };
+                    let min = fx.bcx.ins().iconst(types::I32, i64::from(i16::MIN));
+                    let max = fx.bcx.ins().iconst(types::I32, i64::from(i16::MAX));
+                    let res = fx.bcx.ins().smax(res, min);
+                    let res = fx.bcx.ins().smin(res, max);
+                    fx.bcx.ins().ireduce(types::I16, res)
+                } else if is_sub {
+                    fx.bcx.ins().isub(lhs, rhs)
+                } else {
+                    fx.bcx.ins().iadd(lhs, rhs)
+                }
+            });
+        }
+        "llvm.x86.ssse3.psign.b.128"
+        | "llvm.x86.ssse3.psign.w.128"
+        | "llvm.x86.ssse3.psign.d.128"
+        | "llvm.x86.avx2.psign.b"
+        | "llvm.x86.avx2.psign.w"
+        | "llvm.x86.avx2.psign.d" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sign_epi8
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_sign_epi8
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+
+            simd_pair_for_each_lane(
+                fx,
+                a,
+                b,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, a_lane, b_lane| {
+                    let lane_ty = fx.bcx.func.dfg.value_type(a_lane);
+                    let neg_a_lane = fx.bcx.ins().ineg(a_lane);
+                    let zero = fx.bcx.ins().iconst(lane_ty, 0);
+                    let is_neg = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, b_lane, 0);
+                    let is_zero = fx.bcx.ins().icmp_imm(IntCC::Equal, b_lane, 0);
+                    let res = fx.bcx.ins().select(is_neg, neg_a_lane, a_lane);
+                    fx.bcx.ins().select(is_zero, zero, res)
+                },
+            );
+        }
+        "llvm.x86.sse41.pblendvb"
+        | "llvm.x86.avx2.pblendvb"
+        | "llvm.x86.sse41.blendvps"
+        | "llvm.x86.sse41.blendvpd"
+        | "llvm.x86.avx.blendv.ps.256"
+        | "llvm.x86.avx.blendv.pd.256" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_blendv_epi8
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_blendv_ps
+            intrinsic_args!(fx, args => (a, b, mask); intrinsic);
+
+            simd_trio_for_each_lane(
+                fx,
+                a,
+                b,
+                mask,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, a_lane, b_lane, mask_lane| {
+                    // Only the sign bit of each mask lane is used.
+                    let mask_ty = fx.bcx.func.dfg.value_type(mask_lane);
+                    let mask_lane = if mask_ty.is_float() {
+                        codegen_bitcast(fx, mask_ty.as_int(), mask_lane)
+                    } else {
+                        mask_lane
+                    };
+                    let is_set = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, mask_lane, 0);
+                    fx.bcx.ins().select(is_set, b_lane, a_lane)
+                },
+            );
+        }
         "llvm.x86.ssse3.pabs.b.128" | "llvm.x86.ssse3.pabs.w.128" | "llvm.x86.ssse3.pabs.d.128" => {
             intrinsic_args!(fx, args => (a); intrinsic);
 
@@ -432,7 +513,6 @@ fn select4(
         "llvm.x86.sse2.cvtps2dq" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cvtps_epi32
             intrinsic_args!(fx, args => (a); intrinsic);
-            let a = a.load_scalar(fx);
 
             // Using inline asm instead of fcvt_to_sint_sat as unrepresentable values are turned
             // into 0x80000000 for which Cranelift doesn't have a native instruction.
@@ -498,7 +578,11 @@ fn select4(
                 },
             );
         }
-        "llvm.x86.sse2.psra.w" => {
+        "llvm.x86.sse2.psra.w"
+        | "llvm.x86.sse2.psra.d"
+        | "llvm.x86.avx2.psra.w"
+        | "llvm.x86.avx2.psra.d" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sra_epi16
             intrinsic_args!(fx, args => (a, count); intrinsic);
 
             let count_lane = count.force_stack(fx).0.load(fx, types::I64, MemFlags::trusted());
@@ -511,6 +595,85 @@ fn select4(
                 fx.bcx.ins().sshr(a_lane, saturated_count)
             });
         }
+        "llvm.x86.sse2.psrl.w"
+        | "llvm.x86.sse2.psrl.d"
+        | "llvm.x86.sse2.psrl.q"
+        | "llvm.x86.avx2.psrl.w"
+        | "llvm.x86.avx2.psrl.d"
+        | "llvm.x86.avx2.psrl.q"
+        | "llvm.x86.sse2.psll.w"
+        | "llvm.x86.sse2.psll.d"
+        | "llvm.x86.sse2.psll.q"
+        | "llvm.x86.avx2.psll.w"
+        | "llvm.x86.avx2.psll.d"
+        | "llvm.x86.avx2.psll.q" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_srl_epi16
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sll_epi16
+            intrinsic_args!(fx, args => (a, count); intrinsic);
+
+            let is_left = intrinsic.contains(".psll.");
+            let count_lane = count.force_stack(fx).0.load(fx, types::I64, MemFlags::trusted());
+            let lane_ty = fx.clif_type(a.layout().ty.simd_size_and_type(fx.tcx).1).unwrap();
+            let is_too_big = fx.bcx.ins().icmp_imm(
+                IntCC::UnsignedGreaterThan,
+                count_lane,
+                i64::from(lane_ty.bits() - 1),
+            );
+
+            // FIXME use vector instructions when possible
+            simd_for_each_lane(fx, a, ret, &|fx, _lane_ty, _res_lane_ty, a_lane| {
+                let shifted = if is_left {
+                    fx.bcx.ins().ishl(a_lane, count_lane)
+                } else {
+                    fx.bcx.ins().ushr(a_lane, count_lane)
+                };
+                let zero = fx.bcx.ins().iconst(lane_ty, 0);
+                fx.bcx.ins().select(is_too_big, zero, shifted)
+            });
+        }
+        "llvm.x86.avx2.psllv.d"
+        | "llvm.x86.avx2.psllv.d.256"
+        | "llvm.x86.avx2.psllv.q"
+        | "llvm.x86.avx2.psllv.q.256"
+        | "llvm.x86.avx2.psrlv.d"
+        | "llvm.x86.avx2.psrlv.d.256"
+        | "llvm.x86.avx2.psrlv.q"
+        | "llvm.x86.avx2.psrlv.q.256"
+        | "llvm.x86.avx2.psrav.d"
+        | "llvm.x86.avx2.psrav.d.256" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_sllv_epi32
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_srlv_epi32
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_srav_epi32
+            intrinsic_args!(fx, args => (a, count); intrinsic);
+
+            // FIXME use vector instructions when possible
+            simd_pair_for_each_lane(
+                fx,
+                a,
+                count,
+                ret,
+                &|fx, _lane_ty, _res_lane_ty, a_lane, count_lane| {
+                    let lane_ty = fx.bcx.func.dfg.value_type(a_lane);
+                    let max_count = i64::from(lane_ty.bits() - 1);
+                    let is_too_big =
+                        fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThan, count_lane, max_count);
+                    if intrinsic.contains(".psrav.") {
+                        // Arithmetic shifts by too big counts fill all bits with the sign bit.
+                        let max_count = fx.bcx.ins().iconst(lane_ty, max_count);
+                        let count_lane = fx.bcx.ins().select(is_too_big, max_count, count_lane);
+                        fx.bcx.ins().sshr(a_lane, count_lane)
+                    } else {
+                        let shifted = if intrinsic.contains(".psllv.") {
+                            fx.bcx.ins().ishl(a_lane, count_lane)
+                        } else {
+                            fx.bcx.ins().ushr(a_lane, count_lane)
+                        };
+                        let zero = fx.bcx.ins().iconst(lane_ty, 0);
+                        fx.bcx.ins().select(is_too_big, zero, shifted)
+                    }
+                },
+            );
+        }
         "llvm.x86.sse2.psad.bw" | "llvm.x86.avx2.psad.bw" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sad_epu8&ig_expand=5770
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_sad_epu8&ig_expand=5771
@@ -623,8 +786,9 @@ fn select4(
             }
         }
 
-        "llvm.x86.ssse3.pmul.hr.sw.128" => {
+        "llvm.x86.ssse3.pmul.hr.sw.128" | "llvm.x86.avx2.pmul.hr.sw" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_mulhrs_epi16&ig_expand=4782
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm256_mulhrs_epi16
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
             assert_eq!(a.layout(), b.layout());
@@ -718,9 +882,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#ig_expand=1419&text=_mm_crc32_u32
             intrinsic_args!(fx, args => (crc, v); intrinsic);
 
-            let crc = crc.load_scalar(fx);
-            let v = v.load_scalar(fx);
-
             let asm = match intrinsic {
                 "llvm.x86.sse42.crc32.32.8" => "crc32 eax, dl",
                 "llvm.x86.sse42.crc32.32.16" => "crc32 eax, dx",
@@ -752,11 +913,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpestri&ig_expand=939
             intrinsic_args!(fx, args => (a, la, b, lb, _imm8); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let la = la.load_scalar(fx);
-            let b = b.load_scalar(fx);
-            let lb = lb.load_scalar(fx);
-
             let imm8 =
                 if let Some(imm8) = crate::constant::mir_operand_get_const_val(fx, &args[4].node) {
                     imm8
@@ -805,11 +961,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpestrm&ig_expand=940
             intrinsic_args!(fx, args => (a, la, b, lb, _imm8); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let la = la.load_scalar(fx);
-            let b = b.load_scalar(fx);
-            let lb = lb.load_scalar(fx);
-
             let imm8 =
                 if let Some(imm8) = crate::constant::mir_operand_get_const_val(fx, &args[4].node) {
                     imm8
@@ -850,13 +1001,182 @@ fn select4(
             );
         }
 
+        "llvm.x86.sse42.pcmpistri128" | "llvm.x86.sse42.pcmpistrm128" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpistri
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpistrm
+            intrinsic_args!(fx, args => (a, b, _imm8); intrinsic);
+
+            let imm8 =
+                if let Some(imm8) = crate::constant::mir_operand_get_const_val(fx, &args[2].node) {
+                    imm8
+                } else {
+                    fx.tcx.dcx().span_fatal(
+                        span,
+                        format!("Index argument for `{intrinsic}` is not a constant"),
+                    );
+                };
+
+            let imm8 = imm8.to_u8();
+
+            if intrinsic == "llvm.x86.sse42.pcmpistri128" {
+                codegen_inline_asm_inner(
+                    fx,
+                    &[InlineAsmTemplatePiece::String(
+                        format!("pcmpistri xmm0, xmm1, {imm8}").into(),
+                    )],
+                    &[
+                        CInlineAsmOperand::In {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::xmm0,
+                            )),
+                            value: a,
+                        },
+                        CInlineAsmOperand::In {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::xmm1,
+                            )),
+                            value: b,
+                        },
+                        // Implicit result of the pcmpistri intrinsic
+                        CInlineAsmOperand::Out {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::cx,
+                            )),
+                            late: true,
+                            place: Some(ret),
+                        },
+                    ],
+                    InlineAsmOptions::NOSTACK | InlineAsmOptions::PURE | InlineAsmOptions::NOMEM,
+                );
+            } else {
+                codegen_inline_asm_inner(
+                    fx,
+                    &[InlineAsmTemplatePiece::String(
+                        format!("pcmpistrm xmm0, xmm1, {imm8}").into(),
+                    )],
+                    &[
+                        CInlineAsmOperand::InOut {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::xmm0,
+                            )),
+                            _late: true,
+                            in_value: a,
+                            out_place: Some(ret),
+                        },
+                        CInlineAsmOperand::In {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::xmm1,
+                            )),
+                            value: b,
+                        },
+                    ],
+                    InlineAsmOptions::NOSTACK | InlineAsmOptions::PURE | InlineAsmOptions::NOMEM,
+                );
+            }
+        }
+
+        "llvm.x86.sse42.pcmpestria128"
+        | "llvm.x86.sse42.pcmpestric128"
+        | "llvm.x86.sse42.pcmpestrio128"
+        | "llvm.x86.sse42.pcmpestris128"
+        | "llvm.x86.sse42.pcmpestriz128"
+        | "llvm.x86.sse42.pcmpistria128"
+        | "llvm.x86.sse42.pcmpistric128"
+        | "llvm.x86.sse42.pcmpistrio128"
+        | "llvm.x86.sse42.pcmpistris128"
+        | "llvm.x86.sse42.pcmpistriz128" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpestra
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cmpistrz
+            let is_explicit_len = intrinsic.starts_with("llvm.x86.sse42.pcmpestri");
+            let imm8_idx = if is_explicit_len { 4 } else { 2 };
+            if args.len() != imm8_idx + 1 {
+                bug_on_incorrect_arg_count(intrinsic);
+            }
+
+            let imm8 = if let Some(imm8) =
+                crate::constant::mir_operand_get_const_val(fx, &args[imm8_idx].node)
+            {
+                imm8
+            } else {
+                fx.tcx.dcx().span_fatal(
+                    span,
+                    format!("Index argument for `{intrinsic}` is not a constant"),
+                );
+            };
+            let imm8 = imm8.to_u8();
+
+            // The flag to return is indicated by the letter before the `128` suffix.
+            let setcc = match intrinsic.as_bytes()[intrinsic.len() - 4] {
+                b'a' => "seta",
+                b'c' => "setc",
+                b'o' => "seto",
+                b's' => "sets",
+                b'z' => "setz",
+                _ => unreachable!(),
+            };
+            let instr = if is_explicit_len { "pcmpestri" } else { "pcmpistri" };
+
+            let a = codegen_operand(fx, &args[0].node);
+            let b = codegen_operand(fx, &args[if is_explicit_len { 2 } else { 1 }].node);
+
+            let mut operands = vec![
+                CInlineAsmOperand::In {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::xmm0)),
+                    value: a,
+                },
+                CInlineAsmOperand::In {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::xmm1)),
+                    value: b,
+                },
+                // Implicit result of the pcmp*stri instructions which isn't used here
+                CInlineAsmOperand::Out {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::cx)),
+                    late: true,
+                    place: None,
+                },
+            ];
+            if is_explicit_len {
+                let la = codegen_operand(fx, &args[1].node);
+                let lb = codegen_operand(fx, &args[3].node);
+                operands.push(CInlineAsmOperand::InOut {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::ax)),
+                    _late: true,
+                    in_value: la,
+                    out_place: Some(ret),
+                });
+                operands.push(CInlineAsmOperand::In {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::dx)),
+                    value: lb,
+                });
+            } else {
+                operands.push(CInlineAsmOperand::Out {
+                    reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::ax)),
+                    late: true,
+                    place: Some(ret),
+                });
+            }
+
+            codegen_inline_asm_inner(
+                fx,
+                &[InlineAsmTemplatePiece::String(
+                    format!(
+                        "
+                        {instr} xmm0, xmm1, {imm8}
+                        {setcc} al
+                        movzx eax, al
+                        "
+                    )
+                    .into(),
+                )],
+                &operands,
+                InlineAsmOptions::NOSTACK | InlineAsmOptions::PURE | InlineAsmOptions::NOMEM,
+            );
+        }
+
         "llvm.x86.pclmulqdq" => {
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_clmulepi64_si128&ig_expand=772
             intrinsic_args!(fx, args => (a, b, _imm8); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             let imm8 =
                 if let Some(imm8) = crate::constant::mir_operand_get_const_val(fx, &args[2].node) {
                     imm8
@@ -892,8 +1212,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aeskeygenassist_si128&ig_expand=261
             intrinsic_args!(fx, args => (a, _imm8); intrinsic);
 
-            let a = a.load_scalar(fx);
-
             let imm8 =
                 if let Some(imm8) = crate::constant::mir_operand_get_const_val(fx, &args[1].node) {
                     imm8
@@ -925,8 +1243,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aesimc_si128&ig_expand=260
             intrinsic_args!(fx, args => (a); intrinsic);
 
-            let a = a.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("aesimc xmm0, xmm0".into())],
@@ -944,9 +1260,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aesenc_si128&ig_expand=252
             intrinsic_args!(fx, args => (a, round_key); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let round_key = round_key.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("aesenc xmm0, xmm1".into())],
@@ -970,9 +1283,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aesenclast_si128&ig_expand=257
             intrinsic_args!(fx, args => (a, round_key); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let round_key = round_key.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("aesenclast xmm0, xmm1".into())],
@@ -996,9 +1306,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aesdec_si128&ig_expand=242
             intrinsic_args!(fx, args => (a, round_key); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let round_key = round_key.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("aesdec xmm0, xmm1".into())],
@@ -1022,9 +1329,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_aesdeclast_si128&ig_expand=247
             intrinsic_args!(fx, args => (a, round_key); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let round_key = round_key.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("aesdeclast xmm0, xmm1".into())],
@@ -1048,9 +1352,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha1rnds4_epu32&ig_expand=5877
             intrinsic_args!(fx, args => (a, b, _func); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             let func = if let Some(func) =
                 crate::constant::mir_operand_get_const_val(fx, &args[2].node)
             {
@@ -1086,9 +1387,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha1msg1_epu32&ig_expand=5874
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha1msg1 xmm1, xmm2".into())],
@@ -1112,9 +1410,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha1msg2_epu32&ig_expand=5875
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha1msg2 xmm1, xmm2".into())],
@@ -1138,9 +1433,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha1nexte_epu32&ig_expand=5876
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha1nexte xmm1, xmm2".into())],
@@ -1164,10 +1456,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha256rnds2_epu32&ig_expand=5977
             intrinsic_args!(fx, args => (a, b, k); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-            let k = k.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha256rnds2 xmm1, xmm2".into())],
@@ -1196,9 +1484,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha256msg1_epu32&ig_expand=5975
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha256msg1 xmm1, xmm2".into())],
@@ -1222,9 +1507,6 @@ fn select4(
             // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_sha256msg2_epu32&ig_expand=5976
             intrinsic_args!(fx, args => (a, b); intrinsic);
 
-            let a = a.load_scalar(fx);
-            let b = b.load_scalar(fx);
-
             codegen_inline_asm_inner(
                 fx,
                 &[InlineAsmTemplatePiece::String("sha256msg2 xmm1, xmm2".into())],
@@ -1312,6 +1594,223 @@ fn select4(
             ret.write_cvalue_transmute(fx, res);
         }
 
+        "llvm.x86.bmi.bzhi.32" | "llvm.x86.bmi.bzhi.64" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_bzhi_u32&ig_expand=695
+            intrinsic_args!(fx, args => (a, index); intrinsic);
+
+            let layout = a.layout();
+            let a = a.load_scalar(fx);
+            let index = index.load_scalar(fx);
+            let index = fx.bcx.ins().band_imm(index, 0xff);
+
+            let res = bmi_bzhi(fx, a, index);
+            ret.write_cvalue(fx, CValue::by_val(res, layout));
+        }
+
+        "llvm.x86.bmi.bextr.32" | "llvm.x86.bmi.bextr.64" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_bextr2_u32&ig_expand=554
+            intrinsic_args!(fx, args => (a, control); intrinsic);
+
+            let layout = a.layout();
+            let a = a.load_scalar(fx);
+            let control = control.load_scalar(fx);
+            let ty = fx.bcx.func.dfg.value_type(a);
+
+            let start = fx.bcx.ins().band_imm(control, 0xff);
+            let len = fx.bcx.ins().ushr_imm(control, 8);
+            let len = fx.bcx.ins().band_imm(len, 0xff);
+
+            // Shifting by at least the bit width results in zero rather than wrapping the amount.
+            let start_out_of_range =
+                fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, start, ty.bits() as i64);
+            let shifted = fx.bcx.ins().ushr(a, start);
+            let zero = fx.bcx.ins().iconst(ty, 0);
+            let shifted = fx.bcx.ins().select(start_out_of_range, zero, shifted);
+
+            let res = bmi_bzhi(fx, shifted, len);
+            ret.write_cvalue(fx, CValue::by_val(res, layout));
+        }
+
+        "llvm.x86.bmi.pdep.32"
+        | "llvm.x86.bmi.pdep.64"
+        | "llvm.x86.bmi.pext.32"
+        | "llvm.x86.bmi.pext.64" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_pdep_u32&ig_expand=4940
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_pext_u32&ig_expand=4941
+            intrinsic_args!(fx, args => (a, mask); intrinsic);
+
+            let layout = a.layout();
+
+            // There is no efficient way to emulate these. Use the native instruction when the bmi2
+            // target feature is enabled and fall back to a loop over the mask bits otherwise.
+            let bmi2_enabled = fx
+                .tcx
+                .asm_target_features(fx.instance.def_id())
+                .contains(&rustc_span::Symbol::intern("bmi2"));
+            if bmi2_enabled {
+                let asm = match intrinsic {
+                    "llvm.x86.bmi.pdep.32" => "pdep eax, eax, edx",
+                    "llvm.x86.bmi.pdep.64" => "pdep rax, rax, rdx",
+                    "llvm.x86.bmi.pext.32" => "pext eax, eax, edx",
+                    "llvm.x86.bmi.pext.64" => "pext rax, rax, rdx",
+                    _ => unreachable!(),
+                };
+
+                codegen_inline_asm_inner(
+                    fx,
+                    &[InlineAsmTemplatePiece::String(asm.into())],
+                    &[
+                        CInlineAsmOperand::InOut {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::ax,
+                            )),
+                            _late: true,
+                            in_value: a,
+                            out_place: Some(ret),
+                        },
+                        CInlineAsmOperand::In {
+                            reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(
+                                X86InlineAsmReg::dx,
+                            )),
+                            value: mask,
+                        },
+                    ],
+                    InlineAsmOptions::NOSTACK | InlineAsmOptions::PURE | InlineAsmOptions::NOMEM,
+                );
+            } else {
+                let is_pdep = intrinsic.starts_with("llvm.x86.bmi.pdep");
+                let a = a.load_scalar(fx);
+                let mask = mask.load_scalar(fx);
+                let res = bmi_pdep_pext_fallback(fx, a, mask, is_pdep);
+                ret.write_cvalue(fx, CValue::by_val(res, layout));
+            }
+        }
+
+        "llvm.x86.vcvtph2ps.128" | "llvm.x86.vcvtph2ps.256" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cvtph_ps&ig_expand=2183
+            intrinsic_args!(fx, args => (a); intrinsic);
+
+            let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
+            let ret_lane_layout = fx.layout_of(ret_lane_ty);
+            for i in 0..ret_lane_count {
+                let lane = a.value_lane(fx, i).load_scalar(fx);
+                let lane = codegen_bitcast(fx, types::F16, lane);
+                let res = crate::codegen_f16_f128::f16_to_f32(fx, lane);
+                ret.place_lane(fx, i).write_cvalue(fx, CValue::by_val(res, ret_lane_layout));
+            }
+        }
+
+        "llvm.x86.vcvtps2ph.128" | "llvm.x86.vcvtps2ph.256" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_mm_cvtps_ph&ig_expand=2191
+            let (a, rounding) = match args {
+                [a, rounding] => (a, rounding),
+                _ => bug!("wrong number of args for intrinsic {intrinsic}"),
+            };
+            let a = codegen_operand(fx, &a.node);
+            let rounding = crate::constant::mir_operand_get_const_val(fx, &rounding.node)
+                .expect("llvm.x86.vcvtps2ph.* rounding not const")
+                .to_i32();
+
+            let (lane_count, _lane_ty) = a.layout().ty.simd_size_and_type(fx.tcx);
+            let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
+            let ret_lane_layout = fx.layout_of(ret_lane_ty);
+            for i in 0..lane_count {
+                let lane = a.value_lane(fx, i).load_scalar(fx);
+                let res = f32_to_f16_with_rounding(fx, lane, rounding);
+                ret.place_lane(fx, i).write_cvalue(fx, CValue::by_val(res, ret_lane_layout));
+            }
+            // The upper lanes of the 128bit variant are zeroed.
+            for i in lane_count..ret_lane_count {
+                let zero = fx.bcx.ins().iconst(types::I16, 0);
+                ret.place_lane(fx, i).write_cvalue(fx, CValue::by_val(zero, ret_lane_layout));
+            }
+        }
+
+        "llvm.x86.rdtscp" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=__rdtscp&ig_expand=5274
+
+            intrinsic_args!(fx, args => (aux); intrinsic);
+
+            let aux = aux.load_scalar(fx);
+            let aux_place = CPlace::for_ptr(Pointer::new(aux), fx.layout_of(fx.tcx.types.u32));
+            codegen_inline_asm_inner(
+                fx,
+                &[InlineAsmTemplatePiece::String(
+                    "
+                    rdtscp
+                    // tsc = rdx << 32 | rax
+                    shl rdx, 32
+                    or rax, rdx
+                    "
+                    .into(),
+                )],
+                &[
+                    CInlineAsmOperand::Out {
+                        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::ax)),
+                        late: true,
+                        place: Some(ret),
+                    },
+                    CInlineAsmOperand::Out {
+                        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::cx)),
+                        late: true,
+                        place: Some(aux_place),
+                    },
+                    CInlineAsmOperand::Out {
+                        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::dx)),
+                        late: true,
+                        place: None,
+                    },
+                ],
+                InlineAsmOptions::NOSTACK | InlineAsmOptions::NOMEM,
+            );
+        }
+
+        "llvm.x86.rdrand.16" | "llvm.x86.rdrand.32" | "llvm.x86.rdrand.64"
+        | "llvm.x86.rdseed.16" | "llvm.x86.rdseed.32" | "llvm.x86.rdseed.64" => {
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_rdrand16_step&ig_expand=5264
+            // https://www.intel.com/content/www/us/en/docs/intrinsics-guide/index.html#text=_rdseed16_step&ig_expand=5267
+
+            let asm = match intrinsic {
+                "llvm.x86.rdrand.16" => "rdrand ax",
+                "llvm.x86.rdrand.32" => "rdrand eax",
+                "llvm.x86.rdrand.64" => "rdrand rax",
+                "llvm.x86.rdseed.16" => "rdseed ax",
+                "llvm.x86.rdseed.32" => "rdseed eax",
+                "llvm.x86.rdseed.64" => "rdseed rax",
+                _ => unreachable!(),
+            };
+
+            let val_place = ret.place_field(fx, FieldIdx::new(0));
+            let success_place = ret.place_field(fx, FieldIdx::new(1));
+            codegen_inline_asm_inner(
+                fx,
+                &[InlineAsmTemplatePiece::String(
+                    format!(
+                        "
+                        {asm}
+                        // success = CF
+                        setc dl
+                        movzx edx, dl
+                        "
+                    )
+                    .into(),
+                )],
+                &[
+                    CInlineAsmOperand::Out {
+                        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::ax)),
+                        late: true,
+                        place: Some(val_place),
+                    },
+                    CInlineAsmOperand::Out {
+                        reg: InlineAsmRegOrRegClass::Reg(InlineAsmReg::X86(X86InlineAsmReg::dx)),
+                        late: true,
+                        place: Some(success_place),
+                    },
+                ],
+                InlineAsmOptions::NOSTACK | InlineAsmOptions::NOMEM,
+            );
+        }
+
         _ => {
             fx.tcx
                 .dcx()
@@ -1330,6 +1829,207 @@ fn select4(
 // llvm.x86.ssse3.pshuf.b.128
 // llvm.x86.avx2.pshuf.b
 
+/// Convert the predicate of the x86 float comparison intrinsics to a `FloatCC`. Returns `None`
+/// for the predicates that are always false or always true.
+fn float_cmp_predicate_to_floatcc(kind: i32) -> Option<FloatCC> {
+    // Copied from stdarch
+    /// Equal (ordered, non-signaling)
+    const _CMP_EQ_OQ: i32 = 0x00;
+    /// Less-than (ordered, signaling)
+    const _CMP_LT_OS: i32 = 0x01;
+    /// Less-than-or-equal (ordered, signaling)
+    const _CMP_LE_OS: i32 = 0x02;
+    /// Unordered (non-signaling)
+    const _CMP_UNORD_Q: i32 = 0x03;
+    /// Not-equal (unordered, non-signaling)
+    const _CMP_NEQ_UQ: i32 = 0x04;
+    /// Not-less-than (unordered, signaling)
+    const _CMP_NLT_US: i32 = 0x05;
+    /// Not-less-than-or-equal (unordered, signaling)
+    const _CMP_NLE_US: i32 = 0x06;
+    /// Ordered (non-signaling)
+    const _CMP_ORD_Q: i32 = 0x07;
+    /// Equal (unordered, non-signaling)
+    const _CMP_EQ_UQ: i32 = 0x08;
+    /// Not-greater-than-or-equal (unordered, signaling)
+    const _CMP_NGE_US: i32 = 0x09;
+    /// Not-greater-than (unordered, signaling)
+    const _CMP_NGT_US: i32 = 0x0a;
+    /// False (ordered, non-signaling)
+    const _CMP_FALSE_OQ: i32 = 0x0b;
+    /// Not-equal (ordered, non-signaling)
+    const _CMP_NEQ_OQ: i32 = 0x0c;
+    /// Greater-than-or-equal (ordered, signaling)
+    const _CMP_GE_OS: i32 = 0x0d;
+    /// Greater-than (ordered, signaling)
+    const _CMP_GT_OS: i32 = 0x0e;
+    /// True (unordered, non-signaling)
+    const _CMP_TRUE_UQ: i32 = 0x0f;
+    /// Equal (ordered, signaling)
+    const _CMP_EQ_OS: i32 = 0x10;
+    /// Less-than (ordered, non-signaling)
+    const _CMP_LT_OQ: i32 = 0x11;
+    /// Less-than-or-equal (ordered, non-signaling)
+    const _CMP_LE_OQ: i32 = 0x12;
+    /// Unordered (signaling)
+    const _CMP_UNORD_S: i32 = 0x13;
+    /// Not-equal (unordered, signaling)
+    const _CMP_NEQ_US: i32 = 0x14;
+    /// Not-less-than (unordered, non-signaling)
+    const _CMP_NLT_UQ: i32 = 0x15;
+    /// Not-less-than-or-equal (unordered, non-signaling)
+    const _CMP_NLE_UQ: i32 = 0x16;
+    /// Ordered (signaling)
+    const _CMP_ORD_S: i32 = 0x17;
+    /// Equal (unordered, signaling)
+    const _CMP_EQ_US: i32 = 0x18;
+    /// Not-greater-than-or-equal (unordered, non-signaling)
+    const _CMP_NGE_UQ: i32 = 0x19;
+    /// Not-greater-than (unordered, non-signaling)
+    const _CMP_NGT_UQ: i32 = 0x1a;
+    /// False (ordered, signaling)
+    const _CMP_FALSE_OS: i32 = 0x1b;
+    /// Not-equal (ordered, signaling)
+    const _CMP_NEQ_OS: i32 = 0x1c;
+    /// Greater-than-or-equal (ordered, non-signaling)
+    const _CMP_GE_OQ: i32 = 0x1d;
+    /// Greater-than (ordered, non-signaling)
+    const _CMP_GT_OQ: i32 = 0x1e;
+    /// True (unordered, signaling)
+    const _CMP_TRUE_US: i32 = 0x1f;
+
+    Some(match kind {
+        _CMP_EQ_OQ | _CMP_EQ_OS => FloatCC::Equal,
+        _CMP_LT_OS | _CMP_LT_OQ => FloatCC::LessThan,
+        _CMP_LE_OS | _CMP_LE_OQ => FloatCC::LessThanOrEqual,
+        _CMP_UNORD_Q | _CMP_UNORD_S => FloatCC::Unordered,
+        _CMP_NEQ_UQ | _CMP_NEQ_US => FloatCC::NotEqual,
+        _CMP_NLT_US | _CMP_NLT_UQ => FloatCC::UnorderedOrGreaterThanOrEqual,
+        _CMP_NLE_US | _CMP_NLE_UQ => FloatCC::UnorderedOrGreaterThan,
+        _CMP_ORD_Q | _CMP_ORD_S => FloatCC::Ordered,
+        _CMP_EQ_UQ | _CMP_EQ_US => FloatCC::UnorderedOrEqual,
+        _CMP_NGE_US | _CMP_NGE_UQ => FloatCC::UnorderedOrLessThan,
+        _CMP_NGT_US | _CMP_NGT_UQ => FloatCC::UnorderedOrLessThanOrEqual,
+        _CMP_FALSE_OQ | _CMP_FALSE_OS => return None,
+        _CMP_NEQ_OQ | _CMP_NEQ_OS => FloatCC::OrderedNotEqual,
+        _CMP_GE_OS | _CMP_GE_OQ => FloatCC::GreaterThanOrEqual,
+        _CMP_GT_OS | _CMP_GT_OQ => FloatCC::GreaterThan,
+        _CMP_TRUE_UQ | _CMP_TRUE_US => return None,
+
+        kind => unreachable!("kind {:?}", kind),
+    })
+}
+
+/// Convert an `f32` to the bits of an `f16` using the rounding mode of the `vcvtps2ph` immediate.
+///
+/// The libcall used for the conversion rounds to nearest. For the other rounding modes the result
+/// is moved one ulp towards the requested direction if it was rounded the wrong way. As all `f16`
+/// values are exactly representable as `f32` this can be checked by comparing against the input.
+fn f32_to_f16_with_rounding(fx: &mut FunctionCx<'_, '_, '_>, val: Value, rounding: i32) -> Value {
+    const _MM_FROUND_TO_NEAREST_INT: i32 = 0x00;
+    const _MM_FROUND_TO_NEG_INF: i32 = 0x01;
+    const _MM_FROUND_TO_POS_INF: i32 = 0x02;
+    const _MM_FROUND_TO_ZERO: i32 = 0x03;
+    /// Use the rounding mode of MXCSR, which is always round to nearest in Rust.
+    const _MM_FROUND_CUR_DIRECTION: i32 = 0x04;
+
+    let res = crate::codegen_f16_f128::f32_to_f16(fx, val);
+    let res_bits = codegen_bitcast(fx, types::I16, res);
+
+    let rounding = if rounding & _MM_FROUND_CUR_DIRECTION != 0 {
+        _MM_FROUND_TO_NEAREST_INT
+    } else {
+        rounding & 0x3
+    };
+    if rounding == _MM_FROUND_TO_NEAREST_INT {
+        return res_bits;
+    }
+
+    let res_f32 = crate::codegen_f16_f128::f16_to_f32(fx, res);
+    let res_is_neg = fx.bcx.ins().icmp_imm(IntCC::SignedLessThan, res_bits, 0);
+    // Moving towards zero decrements the bits for both signs, moving away from zero increments them.
+    let towards_zero = fx.bcx.ins().iadd_imm(res_bits, -1);
+    let away_from_zero = fx.bcx.ins().iadd_imm(res_bits, 1);
+
+    let (rounded_wrong_way, adjusted) = match rounding {
+        _MM_FROUND_TO_NEG_INF => (
+            fx.bcx.ins().fcmp(FloatCC::GreaterThan, res_f32, val),
+            fx.bcx.ins().select(res_is_neg, away_from_zero, towards_zero),
+        ),
+        _MM_FROUND_TO_POS_INF => (
+            fx.bcx.ins().fcmp(FloatCC::LessThan, res_f32, val),
+            fx.bcx.ins().select(res_is_neg, towards_zero, away_from_zero),
+        ),
+        _MM_FROUND_TO_ZERO => {
+            let res_abs = fx.bcx.ins().fabs(res_f32);
+            let val_abs = fx.bcx.ins().fabs(val);
+            (fx.bcx.ins().fcmp(FloatCC::GreaterThan, res_abs, val_abs), towards_zero)
+        }
+        _ => unreachable!(),
+    };
+
+    fx.bcx.ins().select(rounded_wrong_way, adjusted, res_bits)
+}
+
+/// Zero all bits of `val` starting at bit `index`. An `index` of at least the bit width of `val`
+/// leaves `val` unchanged.
+fn bmi_bzhi(fx: &mut FunctionCx<'_, '_, '_>, val: Value, index: Value) -> Value {
+    let ty = fx.bcx.func.dfg.value_type(val);
+
+    let zero = fx.bcx.ins().iconst(ty, 0);
+    let all_ones = fx.bcx.ins().bnot(zero);
+    let high_bits = fx.bcx.ins().ishl(all_ones, index);
+    let cleared = fx.bcx.ins().band_not(val, high_bits);
+
+    let index_out_of_range =
+        fx.bcx.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, ty.bits() as i64);
+    fx.bcx.ins().select(index_out_of_range, val, cleared)
+}
+
+/// Deposit (pdep) or extract (pext) the bits of `a` selected by `mask` one mask bit at a time.
+fn bmi_pdep_pext_fallback(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    a: Value,
+    mask: Value,
+    is_pdep: bool,
+) -> Value {
+    let ty = fx.bcx.func.dfg.value_type(a);
+
+    let loop_header = fx.bcx.create_block();
+    let loop_body = fx.bcx.create_block();
+    let done = fx.bcx.create_block();
+    let res = fx.bcx.append_block_param(loop_header, ty);
+    let bit = fx.bcx.append_block_param(loop_header, ty);
+    let remaining_mask = fx.bcx.append_block_param(loop_header, ty);
+    let final_res = fx.bcx.append_block_param(done, ty);
+
+    let zero = fx.bcx.ins().iconst(ty, 0);
+    let one = fx.bcx.ins().iconst(ty, 1);
+    fx.bcx.ins().jump(loop_header, &[zero, one, mask]);
+
+    fx.bcx.switch_to_block(loop_header);
+    fx.bcx.ins().brif(remaining_mask, loop_body, &[], done, &[res]);
+    fx.bcx.seal_block(loop_body);
+    fx.bcx.seal_block(done);
+
+    // `bit` walks over the packed bits, while `lowest_mask_bit` walks over the bits selected by
+    // the mask. pdep copies packed bits to selected bits and pext does the reverse.
+    fx.bcx.switch_to_block(loop_body);
+    let neg_mask = fx.bcx.ins().ineg(remaining_mask);
+    let lowest_mask_bit = fx.bcx.ins().band(remaining_mask, neg_mask);
+    let (src_bit, dst_bit) = if is_pdep { (bit, lowest_mask_bit) } else { (lowest_mask_bit, bit) };
+    let src_bit_set = fx.bcx.ins().band(a, src_bit);
+    let dst_bit = fx.bcx.ins().select(src_bit_set, dst_bit, zero);
+    let next_res = fx.bcx.ins().bor(res, dst_bit);
+    let next_bit = fx.bcx.ins().ishl_imm(bit, 1);
+    let next_remaining_mask = fx.bcx.ins().band_not(remaining_mask, lowest_mask_bit);
+    fx.bcx.ins().jump(loop_header, &[next_res, next_bit, next_remaining_mask]);
+    fx.bcx.seal_block(loop_header);
+
+    fx.bcx.switch_to_block(done);
+    final_res
+}
+
 fn llvm_add_sub<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
@@ -1357,6 +2057,39 @@ fn llvm_add_sub<'tcx>(
     (cb_out, c)
 }
 
+/// Combine each pair of adjacent lanes of `a` and `b` using `f` like the x86 horizontal add and
+/// subtract instructions do. The results for `a` and `b` are interleaved per 128bit lane.
+fn horizontal_op_per_128bit_lane<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    a: CValue<'tcx>,
+    b: CValue<'tcx>,
+    ret: CPlace<'tcx>,
+    f: &dyn Fn(&mut FunctionCx<'_, '_, 'tcx>, Value, Value) -> Value,
+) {
+    assert_eq!(a.layout(), b.layout());
+    let layout = a.layout();
+
+    let (lane_count, lane_ty) = layout.ty.simd_size_and_type(fx.tcx);
+    let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
+    assert_eq!(lane_ty, ret_lane_ty);
+    assert_eq!(lane_count, ret_lane_count);
+
+    let lane_layout = fx.layout_of(lane_ty);
+    let lanes_per_128bit = 128 / lane_layout.size.bits();
+    let half = lanes_per_128bit / 2;
+    for block in 0..lane_count / lanes_per_128bit {
+        let base = block * lanes_per_128bit;
+        for (src, dest_offset) in [(a, 0), (b, half)] {
+            for idx in 0..half {
+                let lhs = src.value_lane(fx, base + idx * 2).load_scalar(fx);
+                let rhs = src.value_lane(fx, base + idx * 2 + 1).load_scalar(fx);
+                let res_lane = CValue::by_val(f(fx, lhs, rhs), lane_layout);
+                ret.place_lane(fx, base + dest_offset + idx).write_cvalue(fx, res_lane);
+            }
+        }
+    }
+}
+
 enum PackSize {
     U8,
     U16,
@@ -262,6 +262,92 @@ fn bool_to_zero_or_max_uint<'tcx>(
     res
 }
 
+/// Maximum amount of bytes for which `raw_eq` and `compare_bytes` are expanded inline.
+const SMALL_MEMCMP_MAX_BYTES: u64 = 32;
+
+/// Split `size` bytes into integer loads of the largest possible size not exceeding 8 bytes. The
+/// last chunk may overlap with the previous one when `size` is not a multiple of the chunk size.
+/// This doesn't affect the result of comparisons as the overlapping bytes are already known to be
+/// equal when the last chunk gets compared.
+fn small_memcmp_chunks(size: u64) -> Option<(Type, Vec<i32>)> {
+    if size == 0 || size > SMALL_MEMCMP_MAX_BYTES {
+        return None;
+    }
+
+    let chunk_size = std::cmp::min(8, 1 << size.ilog2());
+    let mut offsets = (0..size / chunk_size).map(|i| (i * chunk_size) as i32).collect::<Vec<_>>();
+    if size % chunk_size != 0 {
+        offsets.push((size - chunk_size) as i32);
+    }
+
+    Some((Type::int(chunk_size as u16 * 8).unwrap(), offsets))
+}
+
+/// Compare `size` bytes for equality using inline loads, returning `None` if `size` is too big.
+fn emit_small_memeq(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    lhs_ptr: Value,
+    rhs_ptr: Value,
+    size: u64,
+) -> Option<Value> {
+    let (chunk_ty, offsets) = small_memcmp_chunks(size)?;
+
+    // Can't use `trusted` for these loads; they could be unaligned.
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+
+    let mut diff = None;
+    for offset in offsets {
+        let lhs_val = fx.bcx.ins().load(chunk_ty, flags, lhs_ptr, offset);
+        let rhs_val = fx.bcx.ins().load(chunk_ty, flags, rhs_ptr, offset);
+        let chunk_diff = fx.bcx.ins().bxor(lhs_val, rhs_val);
+        diff = Some(match diff {
+            Some(diff) => fx.bcx.ins().bor(diff, chunk_diff),
+            None => chunk_diff,
+        });
+    }
+
+    Some(fx.bcx.ins().icmp_imm(IntCC::Equal, diff.unwrap(), 0))
+}
+
+/// Compare `size` bytes like `memcmp` using inline loads, returning `None` if `size` is too big.
+fn emit_small_memcmp(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    lhs_ptr: Value,
+    rhs_ptr: Value,
+    size: u64,
+) -> Option<Value> {
+    let (chunk_ty, offsets) = small_memcmp_chunks(size)?;
+
+    // Can't use `trusted` for these loads; they could be unaligned.
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+
+    // The first differing chunk determines the result, so start with the last chunk and let every
+    // preceding chunk that isn't equal override the result.
+    let mut res = None;
+    for offset in offsets.into_iter().rev() {
+        let mut lhs_val = fx.bcx.ins().load(chunk_ty, flags, lhs_ptr, offset);
+        let mut rhs_val = fx.bcx.ins().load(chunk_ty, flags, rhs_ptr, offset);
+        // Bytes at lower addresses need to be the most significant for an integer comparison to
+        // match the byte-wise comparison.
+        if fx.tcx.data_layout.endian == rustc_abi::Endian::Little && chunk_ty != types::I8 {
+            lhs_val = fx.bcx.ins().bswap(lhs_val);
+            rhs_val = fx.bcx.ins().bswap(rhs_val);
+        }
+        let gt = fx.bcx.ins().icmp(IntCC::UnsignedGreaterThan, lhs_val, rhs_val);
+        let lt = fx.bcx.ins().icmp(IntCC::UnsignedLessThan, lhs_val, rhs_val);
+        let chunk_res = fx.bcx.ins().isub(gt, lt);
+        let chunk_res = fx.bcx.ins().sextend(types::I32, chunk_res);
+        res = Some(match res {
+            Some(res) => fx.bcx.ins().select(chunk_res, chunk_res, res),
+            None => chunk_res,
+        });
+    }
+
+    res
+}
+
 pub(crate) fn codegen_intrinsic_call<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     instance: Instance<'tcx>,
@@ -398,6 +484,10 @@ fn codegen_float_intrinsic_call<'tcx>(
         | sym::ceilf64
         | sym::truncf32
         | sym::truncf64
+        | sym::rintf32
+        | sym::rintf64
+        | sym::roundevenf32
+        | sym::roundevenf64
         | sym::nearbyintf32
         | sym::nearbyintf64
         | sym::sqrtf32
@@ -407,7 +497,14 @@ fn codegen_float_intrinsic_call<'tcx>(
                 sym::floorf32 | sym::floorf64 => fx.bcx.ins().floor(args[0]),
                 sym::ceilf32 | sym::ceilf64 => fx.bcx.ins().ceil(args[0]),
                 sym::truncf32 | sym::truncf64 => fx.bcx.ins().trunc(args[0]),
-                sym::nearbyintf32 | sym::nearbyintf64 => fx.bcx.ins().nearest(args[0]),
+                // Rust doesn't support changing the rounding mode, so `rint` and `nearbyint`
+                // always round to nearest, ties to even just like `roundeven`.
+                sym::rintf32
+                | sym::rintf64
+                | sym::roundevenf32
+                | sym::roundevenf64
+                | sym::nearbyintf32
+                | sym::nearbyintf64 => fx.bcx.ins().nearest(args[0]),
                 sym::sqrtf32 | sym::sqrtf64 => fx.bcx.ins().sqrt(args[0]),
                 _ => unreachable!(),
             };
@@ -458,6 +555,29 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             ret.write_cvalue(fx, a);
         }
+        sym::select_unpredictable => {
+            intrinsic_args!(fx, args => (c, a, b); intrinsic);
+            let c = c.load_scalar(fx);
+
+            // Use a branchless select for values that fit in registers. Other values use the
+            // fallback body.
+            let res = match a.layout().backend_repr {
+                BackendRepr::Scalar(_) => {
+                    let a = a.load_scalar(fx);
+                    let b = b.load_scalar(fx);
+                    CValue::by_val(fx.bcx.ins().select(c, a, b), ret.layout())
+                }
+                BackendRepr::ScalarPair(_, _) => {
+                    let (a_lo, a_hi) = a.load_scalar_pair(fx);
+                    let (b_lo, b_hi) = b.load_scalar_pair(fx);
+                    let lo = fx.bcx.ins().select(c, a_lo, b_lo);
+                    let hi = fx.bcx.ins().select(c, a_hi, b_hi);
+                    CValue::by_val_pair(lo, hi, ret.layout())
+                }
+                _ => return Err(Instance::new(instance.def_id(), instance.args)),
+            };
+            ret.write_cvalue(fx, res);
+        }
         sym::breakpoint => {
             intrinsic_args!(fx, args => (); intrinsic);
 
@@ -571,7 +691,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let layout = x.layout();
             let x = x.load_scalar(fx);
-            let res = fx.bcx.ins().rotl(x, y);
+            let res = if fx.bcx.func.dfg.value_type(x) == types::I128 {
+                crate::num::codegen_funnel_shift(fx, true, x, x, y)
+            } else {
+                fx.bcx.ins().rotl(x, y)
+            };
             ret.write_cvalue(fx, CValue::by_val(res, layout));
         }
         sym::rotate_right => {
@@ -580,7 +704,11 @@ fn codegen_regular_intrinsic_call<'tcx>(
 
             let layout = x.layout();
             let x = x.load_scalar(fx);
-            let res = fx.bcx.ins().rotr(x, y);
+            let res = if fx.bcx.func.dfg.value_type(x) == types::I128 {
+                crate::num::codegen_funnel_shift(fx, false, x, x, y)
+            } else {
+                fx.bcx.ins().rotr(x, y)
+            };
             ret.write_cvalue(fx, CValue::by_val(res, layout));
         }
 
@@ -736,6 +864,15 @@ fn codegen_regular_intrinsic_call<'tcx>(
             let dest = CPlace::for_ptr(Pointer::new(ptr), val.layout());
             dest.write_cvalue(fx, val);
         }
+        sym::prefetch_read_data
+        | sym::prefetch_write_data
+        | sym::prefetch_read_instruction
+        | sym::prefetch_write_instruction => {
+            intrinsic_args!(fx, args => (_ptr, _locality); intrinsic);
+
+            // Nothing to do. Cranelift doesn't have a prefetch instruction and these are merely
+            // perf hints, so emitting them through inline asm isn't worth the call overhead.
+        }
 
         sym::pref_align_of
         | sym::needs_drop
@@ -1146,6 +1283,37 @@ fn codegen_regular_intrinsic_call<'tcx>(
             ret.write_cvalue(fx, val);
         }
 
+        sym::minnumf16 | sym::minnumf128 | sym::maxnumf16 | sym::maxnumf128 => {
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+            let layout = a.layout();
+            let a = a.load_scalar(fx);
+            let b = b.load_scalar(fx);
+
+            let val = match intrinsic {
+                sym::minnumf16 | sym::minnumf128 => crate::num::codegen_float_min(fx, a, b),
+                sym::maxnumf16 | sym::maxnumf128 => crate::num::codegen_float_max(fx, a, b),
+                _ => unreachable!(),
+            };
+            ret.write_cvalue(fx, CValue::by_val(val, layout));
+        }
+        sym::fabsf16 | sym::fabsf128 => {
+            intrinsic_args!(fx, args => (a); intrinsic);
+            let layout = a.layout();
+            let a = a.load_scalar(fx);
+
+            let val = crate::codegen_f16_f128::fabs(fx, a);
+            ret.write_cvalue(fx, CValue::by_val(val, layout));
+        }
+        sym::copysignf16 | sym::copysignf128 => {
+            intrinsic_args!(fx, args => (a, b); intrinsic);
+            let layout = a.layout();
+            let a = a.load_scalar(fx);
+            let b = b.load_scalar(fx);
+
+            let val = crate::codegen_f16_f128::fcopysign(fx, a, b);
+            ret.write_cvalue(fx, CValue::by_val(val, layout));
+        }
+
         sym::catch_unwind => {
             intrinsic_args!(fx, args => (f, data, catch_fn); intrinsic);
             let f = f.load_scalar(fx);
@@ -1213,7 +1381,6 @@ fn codegen_regular_intrinsic_call<'tcx>(
             let rhs_ref = rhs_ref.load_scalar(fx);
 
             let size = fx.layout_of(generic_args.type_at(0)).layout.size();
-            // FIXME add and use emit_small_memcmp
             let is_eq_value = if size == Size::ZERO {
                 // No bytes means they're trivially equal
                 fx.bcx.ins().iconst(types::I8, 1)
@@ -1224,9 +1391,10 @@ fn codegen_regular_intrinsic_call<'tcx>(
                 let lhs_val = fx.bcx.ins().load(clty, flags, lhs_ref, 0);
                 let rhs_val = fx.bcx.ins().load(clty, flags, rhs_ref, 0);
                 fx.bcx.ins().icmp(IntCC::Equal, lhs_val, rhs_val)
+            } else if let Some(is_eq_value) = emit_small_memeq(fx, lhs_ref, rhs_ref, size.bytes()) {
+                is_eq_value
             } else {
-                // Just call `memcmp` (like slices do in core) when the
-                // size is too large or it's not a power-of-two.
+                // Just call `memcmp` (like slices do in core) when the size is too large.
                 let signed_bytes = i64::try_from(size.bytes()).unwrap();
                 let bytes_val = fx.bcx.ins().iconst(fx.pointer_type, signed_bytes);
                 let params = vec![AbiParam::new(fx.pointer_type); 3];
@@ -1239,16 +1407,30 @@ fn codegen_regular_intrinsic_call<'tcx>(
         }
 
         sym::compare_bytes => {
+            let const_bytes = match args {
+                [_, _, bytes] => crate::constant::mir_operand_get_const_val(fx, &bytes.node),
+                _ => None,
+            };
             intrinsic_args!(fx, args => (lhs_ptr, rhs_ptr, bytes_val); intrinsic);
             let lhs_ptr = lhs_ptr.load_scalar(fx);
             let rhs_ptr = rhs_ptr.load_scalar(fx);
-            let bytes_val = bytes_val.load_scalar(fx);
 
-            let params = vec![AbiParam::new(fx.pointer_type); 3];
-            let returns = vec![AbiParam::new(types::I32)];
-            let args = &[lhs_ptr, rhs_ptr, bytes_val];
-            // Here we assume that the `memcmp` provided by the target is a NOP for size 0.
-            let cmp = fx.lib_call("memcmp", params, returns, args)[0];
+            let small_cmp = match const_bytes.map(|bytes| bytes.to_target_usize(fx.tcx)) {
+                Some(0) => Some(fx.bcx.ins().iconst(types::I32, 0)),
+                Some(bytes) => emit_small_memcmp(fx, lhs_ptr, rhs_ptr, bytes),
+                None => None,
+            };
+            let cmp = if let Some(cmp) = small_cmp {
+                cmp
+            } else {
+                let bytes_val = bytes_val.load_scalar(fx);
+
+                let params = vec![AbiParam::new(fx.pointer_type); 3];
+                let returns = vec![AbiParam::new(types::I32)];
+                let args = &[lhs_ptr, rhs_ptr, bytes_val];
+                // Here we assume that the `memcmp` provided by the target is a NOP for size 0.
+                fx.lib_call("memcmp", params, returns, args)[0]
+            };
             ret.write_cvalue(fx, CValue::by_val(cmp, ret.layout()));
         }
 
@@ -1259,12 +1441,26 @@ fn codegen_regular_intrinsic_call<'tcx>(
             ret.write_cvalue(fx, a);
         }
 
-        // FIXME implement variadics in cranelift
-        sym::va_copy | sym::va_arg | sym::va_end => {
-            fx.tcx.dcx().span_fatal(
-                source_info.span,
-                "Defining variadic functions is not yet supported by Cranelift",
-            );
+        sym::va_copy => {
+            intrinsic_args!(fx, args => (dest, src); intrinsic);
+            let va_list_layout = fx.layout_of(src.layout().ty.builtin_deref(true).unwrap());
+            let dest = dest.load_scalar(fx);
+            let src = src.load_scalar(fx);
+
+            let src = CValue::by_ref(Pointer::new(src), va_list_layout);
+            CPlace::for_ptr(Pointer::new(dest), va_list_layout).write_cvalue(fx, src);
+        }
+
+        sym::va_arg => {
+            intrinsic_args!(fx, args => (va_list); intrinsic);
+            let va_list = va_list.load_scalar(fx);
+
+            let arg = crate::abi::codegen_va_arg(fx, va_list, ret.layout(), source_info.span);
+            ret.write_cvalue(fx, arg);
+        }
+
+        sym::va_end => {
+            // Nothing to do for the supported calling conventions
         }
 
         // Unimplemented intrinsics must have a fallback body. The fallback body is obtained
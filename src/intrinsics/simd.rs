@@ -267,24 +267,33 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
             let base = codegen_operand(fx, &base.node);
             let val = codegen_operand(fx, &val.node);
 
-            // FIXME validate
-            let idx_const = if let Some(idx_const) = idx.node.constant() {
-                crate::constant::eval_mir_constant(fx, idx_const).0.try_to_scalar_int().unwrap()
+            if !base.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, base.layout().ty);
+                return;
+            }
+
+            let ret_lane = if let Some(idx_const) = idx.node.constant() {
+                let idx_const = crate::constant::eval_mir_constant(fx, idx_const)
+                    .0
+                    .try_to_scalar_int()
+                    .unwrap();
+                let idx: u32 = idx_const.to_u32();
+                let (lane_count, _lane_ty) = base.layout().ty.simd_size_and_type(fx.tcx);
+                if u64::from(idx) >= lane_count {
+                    fx.tcx.dcx().span_fatal(
+                        fx.mir.span,
+                        format!("[simd_insert] idx {} >= lane_count {}", idx, lane_count),
+                    );
+                }
+                ret.place_lane(fx, idx.into())
             } else {
-                fx.tcx.dcx().span_fatal(span, "Index argument for `simd_insert` is not a constant");
+                // Out of bounds indices are UB, so a dynamic index doesn't need to be checked.
+                let idx = codegen_operand(fx, &idx.node).load_scalar(fx);
+                let idx = clif_intcast(fx, idx, fx.pointer_type, false);
+                ret.place_lane_dyn(fx, idx)
             };
 
-            let idx: u32 = idx_const.to_u32();
-            let (lane_count, _lane_ty) = base.layout().ty.simd_size_and_type(fx.tcx);
-            if u64::from(idx) >= lane_count {
-                fx.tcx.dcx().span_fatal(
-                    fx.mir.span,
-                    format!("[simd_insert] idx {} >= lane_count {}", idx, lane_count),
-                );
-            }
-
             ret.write_cvalue(fx, base);
-            let ret_lane = ret.place_lane(fx, idx.into());
             ret_lane.write_cvalue(fx, val);
         }
 
@@ -302,24 +311,26 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            let idx_const = if let Some(idx_const) = idx.node.constant() {
-                crate::constant::eval_mir_constant(fx, idx_const).0.try_to_scalar_int().unwrap()
+            let ret_lane = if let Some(idx_const) = idx.node.constant() {
+                let idx_const = crate::constant::eval_mir_constant(fx, idx_const)
+                    .0
+                    .try_to_scalar_int()
+                    .unwrap();
+                let idx = idx_const.to_u32();
+                let (lane_count, _lane_ty) = v.layout().ty.simd_size_and_type(fx.tcx);
+                if u64::from(idx) >= lane_count {
+                    fx.tcx.dcx().span_fatal(
+                        fx.mir.span,
+                        format!("[simd_extract] idx {} >= lane_count {}", idx, lane_count),
+                    );
+                }
+                v.value_lane(fx, idx.into())
             } else {
-                fx.tcx
-                    .dcx()
-                    .span_fatal(span, "Index argument for `simd_extract` is not a constant");
+                // Out of bounds indices are UB, so a dynamic index doesn't need to be checked.
+                let idx = codegen_operand(fx, &idx.node).load_scalar(fx);
+                let idx = clif_intcast(fx, idx, fx.pointer_type, false);
+                v.value_lane_dyn(fx, idx)
             };
-
-            let idx = idx_const.to_u32();
-            let (lane_count, _lane_ty) = v.layout().ty.simd_size_and_type(fx.tcx);
-            if u64::from(idx) >= lane_count {
-                fx.tcx.dcx().span_fatal(
-                    fx.mir.span,
-                    format!("[simd_extract] idx {} >= lane_count {}", idx, lane_count),
-                );
-            }
-
-            let ret_lane = v.value_lane(fx, idx.into());
             ret.write_cvalue(fx, ret_lane);
         }
 
@@ -336,22 +347,33 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                 return;
             }
 
-            simd_for_each_lane(fx, a, ret, &|fx, lane_ty, _ret_lane_ty, lane| match (
-                lane_ty.kind(),
-                intrinsic,
-            ) {
-                (ty::Int(_), sym::simd_neg) => fx.bcx.ins().ineg(lane),
-                (ty::Float(_), sym::simd_neg) => fx.bcx.ins().fneg(lane),
-
-                (ty::Uint(ty::UintTy::U8) | ty::Int(ty::IntTy::I8), sym::simd_bswap) => lane,
-                (ty::Uint(_) | ty::Int(_), sym::simd_bswap) => fx.bcx.ins().bswap(lane),
-                (ty::Uint(_) | ty::Int(_), sym::simd_bitreverse) => fx.bcx.ins().bitrev(lane),
-                (ty::Uint(_) | ty::Int(_), sym::simd_ctlz) => fx.bcx.ins().clz(lane),
-                (ty::Uint(_) | ty::Int(_), sym::simd_ctpop) => fx.bcx.ins().popcnt(lane),
-                (ty::Uint(_) | ty::Int(_), sym::simd_cttz) => fx.bcx.ins().ctz(lane),
+            // The AArch64 backend of Cranelift natively supports popcnt of byte vectors.
+            if intrinsic == sym::simd_ctpop
+                && fx.tcx.sess.target.arch == "aarch64"
+                && matches!(a.layout().backend_repr, BackendRepr::Vector { .. })
+                && matches!(clif_vector_type(fx.tcx, a.layout()), types::I8X8 | types::I8X16)
+            {
+                let a = a.load_scalar(fx);
+                let res = fx.bcx.ins().popcnt(a);
+                ret.write_cvalue(fx, CValue::by_val(res, ret.layout()));
+            } else {
+                simd_for_each_lane(fx, a, ret, &|fx, lane_ty, _ret_lane_ty, lane| match (
+                    lane_ty.kind(),
+                    intrinsic,
+                ) {
+                    (ty::Int(_), sym::simd_neg) => fx.bcx.ins().ineg(lane),
+                    (ty::Float(_), sym::simd_neg) => fx.bcx.ins().fneg(lane),
+
+                    (ty::Uint(ty::UintTy::U8) | ty::Int(ty::IntTy::I8), sym::simd_bswap) => lane,
+                    (ty::Uint(_) | ty::Int(_), sym::simd_bswap) => fx.bcx.ins().bswap(lane),
+                    (ty::Uint(_) | ty::Int(_), sym::simd_bitreverse) => fx.bcx.ins().bitrev(lane),
+                    (ty::Uint(_) | ty::Int(_), sym::simd_ctlz) => fx.bcx.ins().clz(lane),
+                    (ty::Uint(_) | ty::Int(_), sym::simd_ctpop) => fx.bcx.ins().popcnt(lane),
+                    (ty::Uint(_) | ty::Int(_), sym::simd_cttz) => fx.bcx.ins().ctz(lane),
 
-                _ => unreachable!(),
-            });
+                    _ => unreachable!(),
+                });
+            }
         }
 
         sym::simd_add
@@ -803,8 +825,12 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
             let expected_int_bits = lane_count.max(8);
             let expected_bytes = expected_int_bits / 8 + ((expected_int_bits % 8 > 0) as u64);
 
-            let m = match m.layout().ty.kind() {
-                ty::Uint(i) if i.bit_width() == Some(expected_int_bits) => m.load_scalar(fx),
+            // Array masks may not fit in any Cranelift integer type, so their lanes are read one
+            // byte at a time.
+            let (m_int, m_bytes) = match m.layout().ty.kind() {
+                ty::Uint(i) if i.bit_width() == Some(expected_int_bits) => {
+                    (Some(m.load_scalar(fx)), None)
+                }
                 ty::Array(elem, len)
                     if matches!(elem.kind(), ty::Uint(ty::UintTy::U8))
                         && len
@@ -812,11 +838,7 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                             .expect("expected monomorphic const in codegen")
                             == expected_bytes =>
                 {
-                    m.force_stack(fx).0.load(
-                        fx,
-                        Type::int(expected_int_bits as u16).unwrap(),
-                        MemFlags::trusted(),
-                    )
+                    (None, Some(m.force_stack(fx).0))
                 }
                 _ => {
                     fx.tcx.dcx().span_fatal(
@@ -839,7 +861,21 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
                     Endian::Big => lane_count - 1 - lane,
                     Endian::Little => lane,
                 };
-                let m_lane = fx.bcx.ins().ushr_imm(m, u64::from(mask_lane) as i64);
+                let m_lane = if let Some(m) = m_int {
+                    fx.bcx.ins().ushr_imm(m, u64::from(mask_lane) as i64)
+                } else {
+                    // The mask bytes are stored in the byte order of the equivalent integer.
+                    let byte_idx = match fx.tcx.sess.target.endian {
+                        Endian::Big => expected_bytes - 1 - mask_lane / 8,
+                        Endian::Little => mask_lane / 8,
+                    };
+                    let m_byte = m_bytes.unwrap().offset_i64(fx, byte_idx as i64).load(
+                        fx,
+                        types::I8,
+                        MemFlags::trusted(),
+                    );
+                    fx.bcx.ins().ushr_imm(m_byte, (mask_lane % 8) as i64)
+                };
                 let m_lane = fx.bcx.ins().band_imm(m_lane, 1);
                 let a_lane = a.value_lane(fx, lane).load_scalar(fx);
                 let b_lane = b.value_lane(fx, lane).load_scalar(fx);
@@ -981,6 +1017,15 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
         sym::simd_masked_store => {
             intrinsic_args!(fx, args => (mask, ptr, val); intrinsic);
 
+            if !val.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, val.layout().ty);
+                return;
+            }
+            if !mask.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, mask.layout().ty);
+                return;
+            }
+
             let (val_lane_count, val_lane_ty) = val.layout().ty.simd_size_and_type(fx.tcx);
             let (mask_lane_count, _mask_lane_ty) = mask.layout().ty.simd_size_and_type(fx.tcx);
             assert_eq!(val_lane_count, mask_lane_count);
@@ -1012,6 +1057,19 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
         sym::simd_gather => {
             intrinsic_args!(fx, args => (val, ptr, mask); intrinsic);
 
+            if !val.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, val.layout().ty);
+                return;
+            }
+            if !ptr.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, ptr.layout().ty);
+                return;
+            }
+            if !mask.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, mask.layout().ty);
+                return;
+            }
+
             let (val_lane_count, val_lane_ty) = val.layout().ty.simd_size_and_type(fx.tcx);
             let (ptr_lane_count, _ptr_lane_ty) = ptr.layout().ty.simd_size_and_type(fx.tcx);
             let (mask_lane_count, _mask_lane_ty) = mask.layout().ty.simd_size_and_type(fx.tcx);
@@ -1057,6 +1115,15 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
         sym::simd_masked_load => {
             intrinsic_args!(fx, args => (mask, ptr, val); intrinsic);
 
+            if !val.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, val.layout().ty);
+                return;
+            }
+            if !mask.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, mask.layout().ty);
+                return;
+            }
+
             let (val_lane_count, val_lane_ty) = val.layout().ty.simd_size_and_type(fx.tcx);
             let (mask_lane_count, _mask_lane_ty) = mask.layout().ty.simd_size_and_type(fx.tcx);
             let (ret_lane_count, ret_lane_ty) = ret.layout().ty.simd_size_and_type(fx.tcx);
@@ -1106,6 +1173,19 @@ pub(super) fn codegen_simd_intrinsic_call<'tcx>(
         sym::simd_scatter => {
             intrinsic_args!(fx, args => (val, ptr, mask); intrinsic);
 
+            if !val.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, val.layout().ty);
+                return;
+            }
+            if !ptr.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, ptr.layout().ty);
+                return;
+            }
+            if !mask.layout().ty.is_simd() {
+                report_simd_type_validation_error(fx, intrinsic, span, mask.layout().ty);
+                return;
+            }
+
             let (val_lane_count, _val_lane_ty) = val.layout().ty.simd_size_and_type(fx.tcx);
             let (ptr_lane_count, _ptr_lane_ty) = ptr.layout().ty.simd_size_and_type(fx.tcx);
             let (mask_lane_count, _mask_lane_ty) = mask.layout().ty.simd_size_and_type(fx.tcx);
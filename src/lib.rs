@@ -59,6 +59,7 @@
 mod archive;
 mod base;
 mod cast;
+mod codegen_f16_f128;
 mod codegen_i128;
 mod common;
 mod compiler_builtins;
@@ -129,6 +130,9 @@ struct CodegenCx {
     output_filenames: Arc<OutputFilenames>,
     should_write_ir: bool,
     global_asm: String,
+    /// The start offset in `global_asm` and the span of every asm block. Used to point at the
+    /// asm which failed to assemble.
+    global_asm_spans: Vec<(usize, Span)>,
     inline_asm_index: Cell<usize>,
     debug_context: Option<DebugContext>,
     cgu_name: Symbol,
@@ -148,11 +152,17 @@ fn new(tcx: TyCtxt<'_>, isa: &dyn TargetIsa, debug_info: bool, cgu_name: Symbol)
             output_filenames: tcx.output_filenames(()).clone(),
             should_write_ir: crate::pretty_clif::should_write_ir(tcx),
             global_asm: String::new(),
+            global_asm_spans: Vec::new(),
             inline_asm_index: Cell::new(0),
             debug_context,
             cgu_name,
         }
     }
+
+    fn push_global_asm(&mut self, asm: &str, span: Span) {
+        self.global_asm_spans.push((self.global_asm.len(), span));
+        self.global_asm.push_str(asm);
+    }
 }
 
 pub struct CraneliftCodegenBackend {
@@ -227,7 +237,25 @@ fn codegen_crate(
             CodegenMode::Aot => driver::aot::run_aot(tcx, config, metadata, need_metadata_module),
             CodegenMode::Jit | CodegenMode::JitLazy => {
                 #[cfg(feature = "jit")]
-                driver::jit::run_jit(tcx, config);
+                {
+                    use rustc_session::config::OutputType;
+
+                    if tcx.sess.opts.output_types.contains_key(&OutputType::Object) {
+                        // Write the object files AOT compilation would have produced to allow
+                        // inspecting the code that is about to run in case it misbehaves. The
+                        // jitted program may exit the process at any point, so this has to be
+                        // done before running it.
+                        let _ = driver::aot::run_aot(
+                            tcx,
+                            config.clone(),
+                            metadata,
+                            need_metadata_module,
+                        )
+                        .join(tcx.sess, tcx.output_filenames(()), &config);
+                    }
+
+                    driver::jit::run_jit(tcx, config);
+                }
 
                 #[cfg(not(feature = "jit"))]
                 tcx.dcx().fatal("jit support was disabled when compiling rustc_codegen_cranelift");
@@ -271,7 +299,13 @@ fn target_triple(sess: &Session) -> target_lexicon::Triple {
     }
 }
 
-fn build_isa(sess: &Session, backend_config: &BackendConfig) -> Arc<dyn TargetIsa + 'static> {
+/// Build the [`TargetIsa`] for the current target. `force_frame_pointers` preserves frame pointers
+/// even if neither the target nor `-Cforce-frame-pointers` requires them.
+fn build_isa(
+    sess: &Session,
+    backend_config: &BackendConfig,
+    force_frame_pointers: bool,
+) -> Arc<dyn TargetIsa + 'static> {
     use target_lexicon::BinaryFormat;
 
     let target_triple = crate::target_triple(sess);
@@ -285,7 +319,8 @@ fn build_isa(sess: &Session, backend_config: &BackendConfig) -> Arc<dyn TargetIs
 
     let mut frame_ptr = sess.target.options.frame_pointer.clone();
     frame_ptr.ratchet(sess.opts.cg.force_frame_pointers);
-    let preserve_frame_pointer = frame_ptr != rustc_target::spec::FramePointer::MayOmit;
+    let preserve_frame_pointer =
+        force_frame_pointers || frame_ptr != rustc_target::spec::FramePointer::MayOmit;
     flags_builder
         .set("preserve_frame_pointers", if preserve_frame_pointer { "true" } else { "false" })
         .unwrap();
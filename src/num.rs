@@ -333,6 +333,18 @@ pub(crate) fn codegen_saturating_int_binop<'tcx>(
     CValue::by_val(val, lhs.layout())
 }
 
+fn bin_op_to_floatcc(bin_op: BinOp) -> Option<FloatCC> {
+    Some(match bin_op {
+        BinOp::Eq => FloatCC::Equal,
+        BinOp::Lt => FloatCC::LessThan,
+        BinOp::Le => FloatCC::LessThanOrEqual,
+        BinOp::Ne => FloatCC::NotEqual,
+        BinOp::Ge => FloatCC::GreaterThanOrEqual,
+        BinOp::Gt => FloatCC::GreaterThan,
+        _ => return None,
+    })
+}
+
 pub(crate) fn codegen_float_binop<'tcx>(
     fx: &mut FunctionCx<'_, '_, 'tcx>,
     bin_op: BinOp,
@@ -344,6 +356,15 @@ pub(crate) fn codegen_float_binop<'tcx>(
     let lhs = in_lhs.load_scalar(fx);
     let rhs = in_rhs.load_scalar(fx);
 
+    if let ty::Float(FloatTy::F16 | FloatTy::F128) = in_lhs.layout().ty.kind() {
+        if let Some(fltcc) = bin_op_to_floatcc(bin_op) {
+            let val = crate::codegen_f16_f128::fcmp(fx, fltcc, lhs, rhs);
+            return CValue::by_val(val, fx.layout_of(fx.tcx.types.bool));
+        }
+        let res = crate::codegen_f16_f128::codegen_f16_f128_binop(fx, bin_op, lhs, rhs);
+        return CValue::by_val(res, in_lhs.layout());
+    }
+
     let b = fx.bcx.ins();
     let res = match bin_op {
         BinOp::Add => b.fadd(lhs, rhs),
@@ -367,15 +388,7 @@ pub(crate) fn codegen_float_binop<'tcx>(
             return CValue::by_val(ret_val, in_lhs.layout());
         }
         BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Ne | BinOp::Ge | BinOp::Gt => {
-            let fltcc = match bin_op {
-                BinOp::Eq => FloatCC::Equal,
-                BinOp::Lt => FloatCC::LessThan,
-                BinOp::Le => FloatCC::LessThanOrEqual,
-                BinOp::Ne => FloatCC::NotEqual,
-                BinOp::Ge => FloatCC::GreaterThanOrEqual,
-                BinOp::Gt => FloatCC::GreaterThan,
-                _ => unreachable!(),
-            };
+            let fltcc = bin_op_to_floatcc(bin_op).unwrap();
             let val = fx.bcx.ins().fcmp(fltcc, lhs, rhs);
             return CValue::by_val(val, fx.layout_of(fx.tcx.types.bool));
         }
@@ -453,15 +466,50 @@ pub(crate) fn codegen_ptr_binop<'tcx>(
 // and `a.is_nan() ? b : (a <= b ? b : a)` for `maxnumf*`. NaN checks are done by comparing
 // a float against itself. Only in case of NaN is it not equal to itself.
 pub(crate) fn codegen_float_min(fx: &mut FunctionCx<'_, '_, '_>, a: Value, b: Value) -> Value {
-    let a_is_nan = fx.bcx.ins().fcmp(FloatCC::NotEqual, a, a);
-    let a_ge_b = fx.bcx.ins().fcmp(FloatCC::GreaterThanOrEqual, a, b);
+    let a_is_nan = crate::codegen_f16_f128::fcmp(fx, FloatCC::NotEqual, a, a);
+    let a_ge_b = crate::codegen_f16_f128::fcmp(fx, FloatCC::GreaterThanOrEqual, a, b);
     let temp = fx.bcx.ins().select(a_ge_b, b, a);
     fx.bcx.ins().select(a_is_nan, b, temp)
 }
 
 pub(crate) fn codegen_float_max(fx: &mut FunctionCx<'_, '_, '_>, a: Value, b: Value) -> Value {
-    let a_is_nan = fx.bcx.ins().fcmp(FloatCC::NotEqual, a, a);
-    let a_le_b = fx.bcx.ins().fcmp(FloatCC::LessThanOrEqual, a, b);
+    let a_is_nan = crate::codegen_f16_f128::fcmp(fx, FloatCC::NotEqual, a, a);
+    let a_le_b = crate::codegen_f16_f128::fcmp(fx, FloatCC::LessThanOrEqual, a, b);
     let temp = fx.bcx.ins().select(a_le_b, b, a);
     fx.bcx.ins().select(a_is_nan, b, temp)
 }
+
+/// Concatenate `hi` and `lo` and shift the result left (for `fshl`) or right (for `fshr`) by
+/// `amount` modulo the bit width, returning the upper respectively lower half of the result.
+///
+/// This only uses plain shifts and is thus also usable for 128bit integers for which not all
+/// backends support rotates efficiently.
+pub(crate) fn codegen_funnel_shift(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    left: bool,
+    hi: Value,
+    lo: Value,
+    amount: Value,
+) -> Value {
+    let ty = fx.bcx.func.dfg.value_type(hi);
+    let amount_ty = fx.bcx.func.dfg.value_type(amount);
+    let amount =
+        if amount_ty == types::I128 { fx.bcx.ins().ireduce(types::I64, amount) } else { amount };
+    let bits_minus_one = i64::from(ty.bits() - 1);
+    let amount = fx.bcx.ins().band_imm(amount, bits_minus_one);
+    // `bits - amount` would be out of range for an `amount` of zero. Shift by one and then by
+    // `bits - 1 - amount` instead, which correctly shifts all bits out in that case.
+    let inv_amount = fx.bcx.ins().bxor_imm(amount, bits_minus_one);
+
+    if left {
+        let hi = fx.bcx.ins().ishl(hi, amount);
+        let lo = fx.bcx.ins().ushr_imm(lo, 1);
+        let lo = fx.bcx.ins().ushr(lo, inv_amount);
+        fx.bcx.ins().bor(hi, lo)
+    } else {
+        let hi = fx.bcx.ins().ishl_imm(hi, 1);
+        let hi = fx.bcx.ins().ishl(hi, inv_amount);
+        let lo = fx.bcx.ins().ushr(lo, amount);
+        fx.bcx.ins().bor(hi, lo)
+    }
+}
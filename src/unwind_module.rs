@@ -11,19 +11,53 @@
 use crate::UnwindContext;
 
 /// A wrapper around a [Module] which adds any defined function to the [UnwindContext].
+///
+/// In JIT mode it additionally makes defined functions known to debuggers and profilers.
 pub(crate) struct UnwindModule<T> {
     pub(crate) module: T,
     unwind_context: UnwindContext,
+    /// Functions defined since the last `finalize_definitions` together with their code size.
+    /// Only tracked in JIT mode.
+    #[cfg(feature = "jit")]
+    jit_functions: Option<Vec<(FuncId, usize)>>,
+    #[cfg(feature = "jit")]
+    jit_dump: Option<crate::debuginfo::JitDump>,
 }
 
 impl<T: Module> UnwindModule<T> {
     pub(crate) fn new(module: T, pic_eh_frame: bool) -> Self {
         let unwind_context = UnwindContext::new(module.isa(), pic_eh_frame);
-        UnwindModule { module, unwind_context }
+        UnwindModule {
+            module,
+            unwind_context,
+            #[cfg(feature = "jit")]
+            jit_functions: None,
+            #[cfg(feature = "jit")]
+            jit_dump: None,
+        }
     }
 }
 
 impl UnwindModule<ObjectModule> {
+    /// Define a function for which [`Context::compile`] has already been called, possibly on
+    /// another thread.
+    pub(crate) fn define_compiled_function(
+        &mut self,
+        func: FuncId,
+        ctx: &Context,
+    ) -> ModuleResult<()> {
+        let compiled_code = ctx.compiled_code().unwrap();
+        self.module.define_function_bytes(
+            func,
+            &ctx.func,
+            compiled_code.buffer.alignment as u64,
+            compiled_code.code_buffer(),
+            compiled_code.buffer.relocs(),
+        )?;
+        self.unwind_context.add_function(func, ctx, self.module.isa());
+        Ok(())
+    }
+
     pub(crate) fn finish(self) -> ObjectProduct {
         let mut product = self.module.finish();
         self.unwind_context.emit(&mut product);
@@ -33,8 +67,45 @@ pub(crate) fn finish(self) -> ObjectProduct {
 
 #[cfg(feature = "jit")]
 impl UnwindModule<cranelift_jit::JITModule> {
-    pub(crate) fn finalize_definitions(&mut self) {
+    pub(crate) fn new_jit(module: cranelift_jit::JITModule) -> Self {
+        let mut module = UnwindModule::new(module, false);
+        module.jit_functions = Some(vec![]);
+        module
+    }
+
+    /// Write a perf jitdump record for every function defined from now on.
+    pub(crate) fn enable_jit_dump(&mut self) -> std::io::Result<()> {
+        self.jit_dump = Some(crate::debuginfo::JitDump::new(self.module.isa())?);
+        Ok(())
+    }
+
+    /// Finalize all functions defined since the last call. `debug_context` must only contain
+    /// debuginfo for those functions.
+    pub(crate) fn finalize_definitions(
+        &mut self,
+        dcx: rustc_errors::DiagCtxtHandle<'_>,
+        debug_context: Option<&mut crate::DebugContext>,
+    ) {
         self.module.finalize_definitions().unwrap();
+        let jit_functions = std::mem::take(self.jit_functions.as_mut().unwrap());
+        let debug_sections = debug_context
+            .map(|debug_context| debug_context.emit_for_jit(&self.module))
+            .unwrap_or_default();
+        unsafe {
+            crate::debuginfo::register_gdb_jit_functions(
+                &self.module,
+                &jit_functions,
+                &debug_sections,
+            )
+        };
+        if let Some(jit_dump) = &mut self.jit_dump {
+            if let Err(err) = jit_dump.write_functions(&self.module, &jit_functions) {
+                // The jitdump file is likely truncated now, so stop writing to it rather than
+                // warning about every function jitted afterwards.
+                dcx.warn(format!("failed to write perf jitdump file, disabling it: {err}"));
+                self.jit_dump = None;
+            }
+        }
         let prev_unwind_context = std::mem::replace(
             &mut self.unwind_context,
             UnwindContext::new(self.module.isa(), false),
@@ -95,18 +166,29 @@ fn define_function_with_control_plane(
     ) -> ModuleResult<()> {
         self.module.define_function_with_control_plane(func, ctx, ctrl_plane)?;
         self.unwind_context.add_function(func, ctx, self.module.isa());
+        #[cfg(feature = "jit")]
+        if let Some(jit_functions) = &mut self.jit_functions {
+            jit_functions.push((func, ctx.compiled_code().unwrap().code_buffer().len()));
+        }
         Ok(())
     }
 
     fn define_function_bytes(
         &mut self,
-        _func_id: FuncId,
-        _func: &Function,
-        _alignment: u64,
-        _bytes: &[u8],
-        _relocs: &[FinalizedMachReloc],
+        func_id: FuncId,
+        func: &Function,
+        alignment: u64,
+        bytes: &[u8],
+        relocs: &[FinalizedMachReloc],
     ) -> ModuleResult<()> {
-        unimplemented!()
+        // No unwind info is available for functions defined this way. They are only used for asm
+        // in JIT mode.
+        self.module.define_function_bytes(func_id, func, alignment, bytes, relocs)?;
+        #[cfg(feature = "jit")]
+        if let Some(jit_functions) = &mut self.jit_functions {
+            jit_functions.push((func_id, bytes.len()));
+        }
+        Ok(())
     }
 
     fn define_data(&mut self, data_id: DataId, data: &DataDescription) -> ModuleResult<()> {